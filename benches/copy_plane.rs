@@ -0,0 +1,44 @@
+// Measures copying received video frames into GStreamer buffers, run with `cargo bench`
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use gstndi::receiver::CopyPool;
+
+const ITERATIONS: u32 = 100;
+
+fn bench(name: &str, width: usize, height: usize, src_stride: usize, dest_stride: usize) {
+    let line_bytes = 2 * width;
+    let src = vec![0x80u8; src_stride * height];
+    let mut dest = vec![0u8; dest_stride * height];
+
+    for n_threads in [1, 2, 4] {
+        let pool = CopyPool::new(n_threads);
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            pool.copy_plane(
+                black_box(&mut dest),
+                dest_stride,
+                black_box(&src),
+                src_stride,
+                line_bytes,
+            );
+        }
+        let per_frame = start.elapsed() / ITERATIONS;
+
+        println!(
+            "{:<32} {} thread(s): {:>8.3}ms per frame",
+            name,
+            n_threads,
+            per_frame.as_secs_f64() * 1000.0,
+        );
+    }
+}
+
+fn main() {
+    // UYVY as delivered by NDI with the same or a different stride than the output buffer
+    bench("1080p UYVY, same stride", 1920, 1080, 3840, 3840);
+    bench("1080p UYVY, different stride", 1920, 1080, 3904, 3840);
+    bench("2160p UYVY, same stride", 3840, 2160, 7680, 7680);
+    bench("2160p UYVY, different stride", 3840, 2160, 7744, 7680);
+}
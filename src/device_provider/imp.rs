@@ -4,8 +4,9 @@ use gst::{gst_error, gst_log, gst_trace};
 
 use once_cell::sync::OnceCell;
 
+use std::env;
 use std::sync::atomic;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use once_cell::sync::Lazy;
@@ -20,12 +21,45 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
+#[derive(Debug, Clone)]
+struct Settings {
+    groups: Option<String>,
+    extra_ips: Option<String>,
+}
+
+impl Default for Settings {
+    // Device monitors create their providers themselves, so the defaults can also be set via
+    // the environment
+    fn default() -> Self {
+        let extra_ips = env::var("GST_NDI_DEVICE_PROVIDER_EXTRA_IPS")
+            .ok()
+            .filter(|extra_ips| match ndi::validate_address_list(extra_ips) {
+                Ok(()) => true,
+                Err(err) => {
+                    glib::g_warning!(
+                        "ndideviceprovider",
+                        "Invalid GST_NDI_DEVICE_PROVIDER_EXTRA_IPS: {}",
+                        err
+                    );
+                    false
+                }
+            });
+
+        Settings {
+            groups: env::var("GST_NDI_DEVICE_PROVIDER_GROUPS").ok(),
+            extra_ips,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DeviceProvider {
-    thread: Mutex<Option<thread::JoinHandle<()>>>,
+    // Monitoring thread and the flag telling it to keep running, a new one is started every
+    // time the provider is started so that a previous one that is still shutting down can't
+    // interfere
+    thread: Mutex<Option<(thread::JoinHandle<()>, Arc<atomic::AtomicBool>)>>,
     current_devices: Mutex<Vec<super::Device>>,
-    find: Mutex<Option<ndi::FindInstance>>,
-    is_running: atomic::AtomicBool,
+    settings: Mutex<Settings>,
 }
 
 #[glib::object_subclass]
@@ -38,13 +72,74 @@ impl ObjectSubclass for DeviceProvider {
         Self {
             thread: Mutex::new(None),
             current_devices: Mutex::new(vec![]),
-            find: Mutex::new(None),
-            is_running: atomic::AtomicBool::new(false),
+            settings: Mutex::new(Settings::default()),
         }
     }
 }
 
-impl ObjectImpl for DeviceProvider {}
+impl ObjectImpl for DeviceProvider {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+            vec![
+                glib::ParamSpecString::new(
+                    "groups",
+                    "Groups",
+                    "Comma-separated list of NDI groups to discover sources in, the configured default groups if unset. Changes take effect on the next start",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "extra-ips",
+                    "Extra IPs",
+                    "Comma-separated list of additional IP addresses to look for sources at, e.g. in other subnets. Changes take effect on the next start",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(
+        &self,
+        _obj: &Self::Type,
+        _id: usize,
+        value: &glib::Value,
+        pspec: &glib::ParamSpec,
+    ) {
+        match pspec.name() {
+            "groups" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.groups = value.get().unwrap();
+            }
+            "extra-ips" => {
+                let mut settings = self.settings.lock().unwrap();
+                let extra_ips = value.get::<Option<String>>().unwrap();
+                if let Some(Err(err)) = extra_ips.as_deref().map(ndi::validate_address_list) {
+                    glib::g_warning!("ndideviceprovider", "Invalid extra-ips: {}", err);
+                    return;
+                }
+                settings.extra_ips = extra_ips;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "groups" => {
+                let settings = self.settings.lock().unwrap();
+                settings.groups.to_value()
+            }
+            "extra-ips" => {
+                let settings = self.settings.lock().unwrap();
+                settings.extra_ips.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
 
 impl GstObjectImpl for DeviceProvider {}
 
@@ -70,84 +165,98 @@ impl DeviceProviderImpl for DeviceProvider {
     }
 
     fn start(&self, device_provider: &Self::Type) -> Result<(), gst::LoggableError> {
+        crate::ndi::load().map_err(|err| gst::loggable_error!(CAT, "{}", err))?;
+
         let mut thread_guard = self.thread.lock().unwrap();
         if thread_guard.is_some() {
             gst_log!(CAT, obj: device_provider, "Device provider already started");
             return Ok(());
         }
 
-        self.is_running.store(true, atomic::Ordering::SeqCst);
+        let settings = self.settings.lock().unwrap().clone();
+        let is_running = Arc::new(atomic::AtomicBool::new(true));
+        let is_running_clone = is_running.clone();
 
         let device_provider_weak = device_provider.downgrade();
         let mut first = true;
-        *thread_guard = Some(thread::spawn(move || {
-            let device_provider = match device_provider_weak.upgrade() {
-                None => return,
-                Some(device_provider) => device_provider,
-            };
+        let thread = thread::spawn(move || {
+            let is_running = is_running_clone;
 
-            let imp = DeviceProvider::from_instance(&device_provider);
-            {
-                let mut find_guard = imp.find.lock().unwrap();
-                if find_guard.is_some() {
-                    gst_log!(CAT, obj: &device_provider, "Already started");
-                    return;
-                }
+            let mut builder = ndi::FindInstance::builder();
+            if let Some(ref groups) = settings.groups {
+                builder = builder.groups(groups);
+            }
+            let extra_ips = settings
+                .extra_ips
+                .as_deref()
+                .map(ndi::normalize_address_list);
+            if let Some(ref extra_ips) = extra_ips {
+                builder = builder.extra_ips(extra_ips);
+            }
 
-                let find = match ndi::FindInstance::builder().build() {
-                    None => {
+            let mut find = match builder.build() {
+                None => {
+                    if let Some(device_provider) = device_provider_weak.upgrade() {
                         gst_error!(CAT, obj: &device_provider, "Failed to create Find instance");
-                        return;
                     }
-                    Some(find) => find,
-                };
-                *find_guard = Some(find);
-            }
+                    return;
+                }
+                Some(find) => find,
+            };
+
+            // Keeps running until stopped, announcing sources as they appear and disappear
+            while is_running.load(atomic::Ordering::SeqCst) {
+                let changed = find.wait_for_sources(if first { 1000 } else { 5000 });
+                first = false;
 
-            loop {
                 let device_provider = match device_provider_weak.upgrade() {
                     None => break,
                     Some(device_provider) => device_provider,
                 };
 
-                let imp = DeviceProvider::from_instance(&device_provider);
-                if !imp.is_running.load(atomic::Ordering::SeqCst) {
-                    break;
+                if !changed {
+                    gst_trace!(CAT, obj: &device_provider, "No new sources found");
+                    continue;
                 }
 
-                imp.poll(&device_provider, first);
-                first = false;
+                let imp = DeviceProvider::from_instance(&device_provider);
+                imp.update(&device_provider, &mut find, &settings, &is_running);
             }
-        }));
+        });
+        *thread_guard = Some((thread, is_running));
 
         Ok(())
     }
 
     fn stop(&self, _device_provider: &Self::Type) {
-        if let Some(_thread) = self.thread.lock().unwrap().take() {
-            self.is_running.store(false, atomic::Ordering::SeqCst);
+        if let Some((_thread, is_running)) = self.thread.lock().unwrap().take() {
             // Don't actually join because that might take a while
+            is_running.store(false, atomic::Ordering::SeqCst);
+
+            // The base class forgets all devices when stopped, so they have to be announced
+            // again after the next start
+            self.current_devices.lock().unwrap().clear();
         }
     }
 }
 
 impl DeviceProvider {
-    fn poll(&self, device_provider: &super::DeviceProvider, first: bool) {
-        let mut find_guard = self.find.lock().unwrap();
-        let find = match *find_guard {
-            None => return,
-            Some(ref mut find) => find,
-        };
-
-        if !find.wait_for_sources(if first { 1000 } else { 5000 }) {
-            gst_trace!(CAT, obj: device_provider, "No new sources found");
-            return;
-        }
-
+    fn update(
+        &self,
+        device_provider: &super::DeviceProvider,
+        find: &mut ndi::FindInstance,
+        settings: &Settings,
+        is_running: &atomic::AtomicBool,
+    ) {
         let sources = find.get_current_sources();
         let mut sources = sources.iter().map(|s| s.to_owned()).collect::<Vec<_>>();
 
         let mut current_devices_guard = self.current_devices.lock().unwrap();
+        // Stopped in the meantime
+        if !is_running.load(atomic::Ordering::SeqCst) {
+            return;
+        }
+
         let mut expired_devices = vec![];
         let mut remaining_sources = vec![];
 
@@ -185,7 +294,7 @@ impl DeviceProvider {
         // Now go through all new devices and announce them
         for source in sources {
             gst_log!(CAT, obj: device_provider, "Source {:?} appeared", source);
-            let device = super::Device::new(&source);
+            let device = super::Device::new(&source, settings);
             device_provider.device_add(&device);
             current_devices_guard.push(device);
         }
@@ -195,6 +304,7 @@ impl DeviceProvider {
 #[derive(Debug)]
 pub struct Device {
     source: OnceCell<ndi::Source<'static>>,
+    extra_ips: OnceCell<Option<String>>,
 }
 
 #[glib::object_subclass]
@@ -206,6 +316,7 @@ impl ObjectSubclass for Device {
     fn new() -> Self {
         Self {
             source: OnceCell::new(),
+            extra_ips: OnceCell::new(),
         }
     }
 }
@@ -227,6 +338,7 @@ impl DeviceImpl for Device {
                 ("name", &name),
                 ("ndi-name", &source_info.ndi_name()),
                 ("url-address", &source_info.url_address()),
+                ("extra-ips", self.extra_ips.get().unwrap()),
             ],
         )
         .unwrap()
@@ -235,10 +347,40 @@ impl DeviceImpl for Device {
 
         Ok(element)
     }
+
+    fn reconfigure_element(
+        &self,
+        _device: &Self::Type,
+        element: &gst::Element,
+    ) -> Result<(), gst::LoggableError> {
+        if !element.is::<crate::ndisrc::NdiSrc>() {
+            return Err(gst::loggable_error!(
+                CAT,
+                "Can only reconfigure ndisrc elements, not {}",
+                element.type_()
+            ));
+        }
+
+        // Only possible while the element is not connected yet
+        if element.current_state() > gst::State::Ready {
+            return Err(gst::loggable_error!(
+                CAT,
+                "Can't reconfigure element {} while running",
+                element.name()
+            ));
+        }
+
+        let source_info = self.source.get().unwrap();
+        element.set_property("ndi-name", source_info.ndi_name());
+        element.set_property("url-address", source_info.url_address());
+        element.set_property("extra-ips", self.extra_ips.get().unwrap());
+
+        Ok(())
+    }
 }
 
 impl super::Device {
-    fn new(source: &ndi::Source<'_>) -> super::Device {
+    fn new(source: &ndi::Source<'_>, settings: &Settings) -> super::Device {
         let display_name = source.ndi_name();
         let device_class = "Source/Audio/Video/Network";
 
@@ -247,11 +389,18 @@ impl super::Device {
         let templ = element_class.pad_template("src").unwrap();
         let caps = templ.caps();
 
-        // Put the url-address into the extra properties
-        let extra_properties = gst::Structure::builder("properties")
+        // Put the url-address and the discovery settings the source was found with into the
+        // extra properties
+        let mut extra_properties = gst::Structure::builder("properties")
             .field("ndi-name", &source.ndi_name())
-            .field("url-address", &source.url_address())
-            .build();
+            .field("url-address", &source.url_address());
+        if let Some(ref groups) = settings.groups {
+            extra_properties = extra_properties.field("groups", groups);
+        }
+        if let Some(ref extra_ips) = settings.extra_ips {
+            extra_properties = extra_properties.field("extra-ips", extra_ips);
+        }
+        let extra_properties = extra_properties.build();
 
         let device = glib::Object::new::<super::Device>(&[
             ("caps", &caps),
@@ -263,6 +412,10 @@ impl super::Device {
         let device_impl = Device::from_instance(&device);
 
         device_impl.source.set(source.to_owned()).unwrap();
+        device_impl
+            .extra_ips
+            .set(settings.extra_ips.clone())
+            .unwrap();
 
         device
     }
@@ -0,0 +1,120 @@
+use gst::gst_debug;
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time;
+
+use once_cell::sync::Lazy;
+
+use crate::ndi::{self, FindInstance};
+
+static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
+    gst::DebugCategory::new(
+        "ndifinder",
+        gst::DebugColorFlags::empty(),
+        Some("NewTek NDI finder"),
+    )
+});
+
+// NDI source discovery that can be shared between elements via a GstContext so that not every
+// element runs its own finder, each with its own mDNS traffic
+#[derive(Debug, Clone, glib::Boxed)]
+#[boxed_type(name = "GstNdiFinder")]
+pub struct Finder(Arc<FinderInner>);
+
+#[derive(Debug)]
+struct FinderInner {
+    show_local_sources: bool,
+    extra_ips: Option<String>,
+    sources: Mutex<Sources>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Sources {
+    // Incremented whenever the list of sources changes
+    generation: u64,
+    // NDI names and URLs/addresses of all currently known sources
+    sources: Vec<(String, String)>,
+}
+
+impl Finder {
+    pub fn new(show_local_sources: bool, extra_ips: Option<&str>) -> Option<Self> {
+        let extra_ips = extra_ips.map(ndi::normalize_address_list);
+
+        let mut builder = FindInstance::builder().show_local_sources(show_local_sources);
+        if let Some(ref extra_ips) = extra_ips {
+            builder = builder.extra_ips(extra_ips);
+        }
+        let mut find = builder.build()?;
+
+        let inner = Arc::new(FinderInner {
+            show_local_sources,
+            extra_ips,
+            sources: Mutex::new(Sources::default()),
+            cond: Condvar::new(),
+        });
+
+        // Runs until the last reference to the finder is gone
+        let inner_weak = Arc::downgrade(&inner);
+        thread::spawn(move || loop {
+            let changed = find.wait_for_sources(1000);
+
+            let inner = match inner_weak.upgrade() {
+                Some(inner) => inner,
+                None => break,
+            };
+
+            if !changed {
+                continue;
+            }
+
+            let sources = find
+                .get_current_sources()
+                .iter()
+                .map(|source| {
+                    let url_address = source.url_address();
+                    (
+                        source.ndi_name().to_string(),
+                        ndi::normalize_address(url_address)
+                            .unwrap_or_else(|| url_address.to_string()),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            gst_debug!(CAT, "Sources changed: {:?}", sources);
+
+            let mut guard = inner.sources.lock().unwrap();
+            guard.generation += 1;
+            guard.sources = sources;
+            inner.cond.notify_all();
+        });
+
+        Some(Finder(inner))
+    }
+
+    pub fn show_local_sources(&self) -> bool {
+        self.0.show_local_sources
+    }
+
+    pub fn extra_ips(&self) -> Option<&str> {
+        self.0.extra_ips.as_deref()
+    }
+
+    // Waits until the sources differ from the given generation or the timeout expires and
+    // returns the current generation with its sources
+    pub fn wait_for_change(
+        &self,
+        generation: u64,
+        timeout: time::Duration,
+    ) -> (u64, Vec<(String, String)>) {
+        let guard = self.0.sources.lock().unwrap();
+        let (guard, _) = self
+            .0
+            .cond
+            .wait_timeout_while(guard, timeout, |sources| sources.generation == generation)
+            .unwrap();
+
+        (guard.generation, guard.sources.clone())
+    }
+}
@@ -1,5 +1,10 @@
 mod device_provider;
+mod finder;
 pub mod ndi;
+#[cfg(feature = "test-backend")]
+mod ndifake;
+mod ndimetasrc;
+mod ndirouter;
 #[cfg(feature = "sink")]
 mod ndisink;
 #[cfg(feature = "sink")]
@@ -36,6 +41,67 @@ pub enum TimestampMode {
     ReceiveTime = 4,
 }
 
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstNdiAudioSlaveMethod")]
+pub enum AudioSlaveMethod {
+    #[enum_value(name = "Skew", nick = "skew")]
+    Skew = 0,
+    #[enum_value(name = "Resample", nick = "resample")]
+    Resample = 1,
+    #[enum_value(name = "None", nick = "none")]
+    None = 2,
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstNdiQueueLeaky")]
+pub enum QueueLeaky {
+    #[enum_value(name = "Drop oldest frames", nick = "downstream")]
+    Downstream = 0,
+    #[enum_value(name = "Drop new frames", nick = "upstream")]
+    Upstream = 1,
+}
+
+#[cfg(feature = "sink")]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstNdiLateStreamMode")]
+pub enum LateStreamMode {
+    #[enum_value(name = "Wait for both streams", nick = "wait")]
+    Wait = 0,
+    #[enum_value(name = "Send the other stream alone", nick = "skip")]
+    Skip = 1,
+    #[enum_value(name = "Pad with silence or black video", nick = "pad")]
+    Pad = 2,
+}
+
+#[cfg(feature = "sink")]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstNdiStartTimeSelection")]
+pub enum StartTimeSelection {
+    #[enum_value(name = "Start with the first buffer of either stream", nick = "first")]
+    First = 0,
+    #[enum_value(name = "Start with the first video frame", nick = "video")]
+    Video = 1,
+    #[enum_value(name = "Start once both streams have data", nick = "both")]
+    Both = 2,
+}
+
+#[cfg(feature = "sink")]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstNdiNameConflict")]
+pub enum NameConflict {
+    #[enum_value(name = "Ignore", nick = "ignore")]
+    Ignore = 0,
+    #[enum_value(name = "Append a number to the name", nick = "rename")]
+    Rename = 1,
+    #[enum_value(name = "Fail with an error", nick = "error")]
+    Error = 2,
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
 #[repr(u32)]
 #[enum_type(name = "GstNdiRecvColorFormat")]
@@ -76,6 +142,32 @@ pub enum RecvColorFormat {
     #[cfg(feature = "advanced-sdk")]
     #[enum_value(name = "Compressed v5 with audio", nick = "compressed-v5-with-audio")]
     CompressedV5WithAudio = 13,
+    #[enum_value(
+        name = "Depending on the formats accepted downstream of ndisrcdemux",
+        nick = "auto"
+    )]
+    Auto = 14,
+}
+
+impl RecvColorFormat {
+    // Color format that delivers the first of the given raw video formats NDI can deliver
+    pub fn for_formats<S: AsRef<str>>(formats: &[S]) -> Option<Self> {
+        let accepts = |names: &[&str]| {
+            formats
+                .iter()
+                .any(|format| names.contains(&format.as_ref()))
+        };
+
+        formats.iter().find_map(|format| match format.as_ref() {
+            "UYVY" if !accepts(&["BGRA", "BGRx"]) && accepts(&["RGBA", "RGBx"]) => {
+                Some(RecvColorFormat::UyvyRgba)
+            }
+            "UYVY" => Some(RecvColorFormat::UyvyBgra),
+            "BGRA" | "BGRx" => Some(RecvColorFormat::BgrxBgra),
+            "RGBA" | "RGBx" => Some(RecvColorFormat::RgbxRgba),
+            _ => None,
+        })
+    }
 }
 
 impl From<RecvColorFormat> for NDIlib_recv_color_format_e {
@@ -87,6 +179,8 @@ impl From<RecvColorFormat> for NDIlib_recv_color_format_e {
             RecvColorFormat::UyvyRgba => NDIlib_recv_color_format_UYVY_RGBA,
             RecvColorFormat::Fastest => NDIlib_recv_color_format_fastest,
             RecvColorFormat::Best => NDIlib_recv_color_format_best,
+            // Until the formats downstream are known
+            RecvColorFormat::Auto => NDIlib_recv_color_format_UYVY_BGRA,
             #[cfg(feature = "advanced-sdk")]
             RecvColorFormat::CompressedV1 => NDIlib_recv_color_format_ex_compressed,
             #[cfg(feature = "advanced-sdk")]
@@ -114,14 +208,14 @@ impl From<RecvColorFormat> for NDIlib_recv_color_format_e {
 }
 
 fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
-    if !ndi::initialize() {
-        return Err(glib::bool_error!("Cannot initialize NDI"));
-    }
-
+    // The NDI library is only loaded once an element is used so that the plugin can be
+    // registered even if it's not installed
     device_provider::register(plugin)?;
 
     ndisrc::register(plugin)?;
+    ndimetasrc::register(plugin)?;
     ndisrcdemux::register(plugin)?;
+    ndirouter::register(plugin)?;
 
     #[cfg(feature = "sink")]
     {
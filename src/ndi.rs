@@ -2,12 +2,145 @@ use crate::ndisys;
 use crate::ndisys::*;
 use std::ffi;
 use std::mem;
+use std::net;
+use std::path;
 use std::ptr;
+use std::sync::Arc;
 
+use once_cell::sync::OnceCell;
+
+#[cfg(feature = "sink")]
 use byte_slice_cast::*;
 
-pub fn initialize() -> bool {
-    unsafe { NDIlib_initialize() }
+// Loads and initializes the NDI library on first use, to be called before any other NDI API
+pub fn load() -> Result<(), glib::BoolError> {
+    load_from(None)
+}
+
+// Loads the library from the given file or directory if it wasn't loaded yet, falling back to
+// the default locations
+pub fn load_from(library_path: Option<&str>) -> Result<(), glib::BoolError> {
+    static INITIALIZED: OnceCell<()> = OnceCell::new();
+
+    INITIALIZED
+        .get_or_try_init(|| {
+            ndisys::load(library_path.map(path::Path::new))?;
+
+            if unsafe { NDIlib_initialize() } {
+                Ok(())
+            } else {
+                Err(String::from("Cannot initialize NDI"))
+            }
+        })
+        .map(|_| ())
+        .map_err(|err| glib::bool_error!("{}", err))
+}
+
+pub fn version() -> Option<String> {
+    unsafe {
+        NDIlib_version()
+            .filter(|version| !version.is_null())
+            .map(|version| ffi::CStr::from_ptr(version).to_string_lossy().into_owned())
+    }
+}
+
+// Brings an IP address with optional port into the form the NDI SDK uses, which also makes
+// different spellings of the same address comparable. IPv6 addresses can be given with or
+// without brackets but need them if a port is included, e.g. "[fe80::1]:5961"
+pub fn normalize_address(address: &str) -> Option<String> {
+    let address = address.trim();
+    if let Ok(addr) = address.parse::<net::SocketAddr>() {
+        return Some(addr.to_string());
+    }
+
+    match address.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Some(ip) => ip.parse::<net::Ipv6Addr>().ok().map(|ip| ip.to_string()),
+        None => address.parse::<net::IpAddr>().ok().map(|ip| ip.to_string()),
+    }
+}
+
+// Normalizes a comma separated list of addresses, keeping entries that are no IP address as is
+pub fn normalize_address_list(addresses: &str) -> String {
+    addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| normalize_address(address).unwrap_or_else(|| address.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Checks that the address is an IP address or host name with an optional port in the valid range
+pub fn validate_address(address: &str) -> Result<(), String> {
+    let address = address.trim();
+    if let Ok(addr) = address.parse::<net::SocketAddr>() {
+        return if addr.port() == 0 {
+            Err(format!("Invalid port 0 in '{}'", address))
+        } else {
+            Ok(())
+        };
+    }
+    if normalize_address(address).is_some() {
+        return Ok(());
+    }
+
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (address, None),
+    };
+
+    if host.is_empty()
+        || !host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err(format!("'{}' is no IP address or host name", address));
+    }
+
+    match port.map(str::parse::<u16>) {
+        Some(Ok(0)) | Some(Err(_)) => Err(format!(
+            "Invalid port in '{}', must be between 1 and 65535",
+            address
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_address_list(addresses: &str) -> Result<(), String> {
+    let mut addresses = addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .peekable();
+
+    if addresses.peek().is_none() {
+        return Err(String::from("No address given"));
+    }
+
+    addresses.try_for_each(validate_address)
+}
+
+// NDI names of sources always have the form "MACHINE (SOURCE)"
+pub fn validate_ndi_name(ndi_name: &str) -> Result<(), String> {
+    match ndi_name.split_once(" (") {
+        Some((machine, source))
+            if !machine.is_empty() && source.len() > 1 && source.ends_with(')') =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "'{}' is no NDI name of the form 'MACHINE (SOURCE)'",
+            ndi_name
+        )),
+    }
+}
+
+pub fn send_config_supported() -> bool {
+    ndisys::available::NDIlib_send_create_v2()
+}
+
+pub fn send_source_name_supported() -> bool {
+    ndisys::available::NDIlib_send_get_source_name()
 }
 
 #[derive(Debug)]
@@ -133,8 +266,10 @@ impl<'a> Source<'a> {
                 Source::Owned(ref source, _, _) => source,
             };
 
-            assert!(!ptr.p_ndi_name.is_null());
-            ffi::CStr::from_ptr(ptr.p_ndi_name).to_str().unwrap()
+            if ptr.p_ndi_name.is_null() {
+                return "";
+            }
+            ffi::CStr::from_ptr(ptr.p_ndi_name).to_str().unwrap_or("")
         }
     }
 
@@ -145,8 +280,12 @@ impl<'a> Source<'a> {
                 Source::Owned(ref source, _, _) => source,
             };
 
-            assert!(!ptr.p_url_address.is_null());
-            ffi::CStr::from_ptr(ptr.p_url_address).to_str().unwrap()
+            if ptr.p_url_address.is_null() {
+                return "";
+            }
+            ffi::CStr::from_ptr(ptr.p_url_address)
+                .to_str()
+                .unwrap_or("")
         }
     }
 
@@ -241,16 +380,24 @@ impl<'a> RecvBuilder<'a> {
             if ptr.is_null() {
                 None
             } else {
-                Some(RecvInstance(ptr::NonNull::new_unchecked(ptr)))
+                Some(RecvInstance(Arc::new(RecvInstanceInner(
+                    ptr::NonNull::new_unchecked(ptr),
+                ))))
             }
         }
     }
 }
 
+// Shared so that e.g. recording can be controlled while another thread is capturing
 #[derive(Debug, Clone)]
-pub struct RecvInstance(ptr::NonNull<::std::os::raw::c_void>);
+pub struct RecvInstance(Arc<RecvInstanceInner>);
+
+#[derive(Debug)]
+struct RecvInstanceInner(ptr::NonNull<::std::os::raw::c_void>);
 
-unsafe impl Send for RecvInstance {}
+unsafe impl Send for RecvInstanceInner {}
+
+unsafe impl Sync for RecvInstanceInner {}
 
 impl RecvInstance {
     pub fn builder<'a>(
@@ -269,36 +416,184 @@ impl RecvInstance {
     }
 
     pub fn set_tally(&self, tally: &Tally) -> bool {
-        unsafe { NDIlib_recv_set_tally(self.0.as_ptr(), &tally.0) }
+        unsafe { NDIlib_recv_set_tally(self.as_ptr(), &tally.0) }
     }
 
     pub fn send_metadata(&self, metadata: &MetadataFrame) -> bool {
-        unsafe { NDIlib_recv_send_metadata(self.0.as_ptr(), metadata.as_ptr()) }
+        unsafe { NDIlib_recv_send_metadata(self.as_ptr(), metadata.as_ptr()) }
+    }
+
+    // Passing neither a name nor an URL/address disconnects. Returns None if the name or
+    // URL/address contain a NUL byte.
+    pub fn connect(&self, ndi_name: Option<&str>, url_address: Option<&str>) -> Option<()> {
+        unsafe {
+            if ndi_name.is_none() && url_address.is_none() {
+                NDIlib_recv_connect(self.as_ptr(), ptr::null());
+                return Some(());
+            }
+
+            let ndi_name = ndi_name.map(ffi::CString::new).transpose().ok()?;
+            let url_address = url_address.map(ffi::CString::new).transpose().ok()?;
+            NDIlib_recv_connect(
+                self.as_ptr(),
+                &NDIlib_source_t {
+                    p_ndi_name: ndi_name
+                        .as_ref()
+                        .map(|s| s.as_ptr())
+                        .unwrap_or_else(ptr::null),
+                    p_url_address: url_address
+                        .as_ref()
+                        .map(|s| s.as_ptr())
+                        .unwrap_or_else(ptr::null),
+                },
+            );
+        }
+
+        Some(())
+    }
+
+    pub fn no_connections(&self) -> i32 {
+        unsafe { NDIlib_recv_get_no_connections(self.as_ptr()) }
     }
 
     pub fn get_queue(&self) -> Queue {
         unsafe {
             let mut queue = mem::MaybeUninit::uninit();
-            NDIlib_recv_get_queue(self.0.as_ptr(), queue.as_mut_ptr());
+            NDIlib_recv_get_queue(self.as_ptr(), queue.as_mut_ptr());
             Queue(queue.assume_init())
         }
     }
 
-    pub fn capture(&self, timeout_in_ms: u32) -> Result<Option<Frame>, ()> {
+    pub fn recording_start(&self, filename_hint: Option<&str>) -> bool {
+        unsafe {
+            let filename_hint = filename_hint.map(|s| ffi::CString::new(s).unwrap());
+            NDIlib_recv_recording_start(
+                self.as_ptr(),
+                filename_hint.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            )
+            .unwrap_or(false)
+        }
+    }
+
+    pub fn recording_stop(&self) -> bool {
+        unsafe { NDIlib_recv_recording_stop(self.as_ptr()).unwrap_or(false) }
+    }
+
+    pub fn recording_set_audio_level(&self, level_db: f32) -> bool {
+        unsafe { NDIlib_recv_recording_set_audio_level(self.as_ptr(), level_db).unwrap_or(false) }
+    }
+
+    pub fn recording_is_recording(&self) -> bool {
+        unsafe { NDIlib_recv_recording_is_recording(self.as_ptr()).unwrap_or(false) }
+    }
+
+    pub fn recording_filename(&self) -> Option<String> {
+        unsafe {
+            NDIlib_recv_recording_get_filename(self.as_ptr()).and_then(|s| self.take_string(s))
+        }
+    }
+
+    pub fn recording_error(&self) -> Option<String> {
+        unsafe { NDIlib_recv_recording_get_error(self.as_ptr()).and_then(|s| self.take_string(s)) }
+    }
+
+    pub fn recording_times(&self) -> Option<RecordingTimes> {
+        unsafe {
+            let mut times = mem::MaybeUninit::uninit();
+            if NDIlib_recv_recording_get_times(self.as_ptr(), times.as_mut_ptr()) == Some(true) {
+                Some(RecordingTimes(times.assume_init()))
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn web_control(&self) -> Option<String> {
+        unsafe { NDIlib_recv_get_web_control(self.as_ptr()).and_then(|s| self.take_string(s)) }
+    }
+
+    pub fn ptz_is_supported(&self) -> bool {
+        unsafe { NDIlib_recv_ptz_is_supported(self.as_ptr()).unwrap_or(false) }
+    }
+
+    pub fn recording_is_supported(&self) -> bool {
+        unsafe { NDIlib_recv_recording_is_supported(self.as_ptr()).unwrap_or(false) }
+    }
+
+    unsafe fn take_string(&self, s: *const ::std::os::raw::c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+
+        let res = ffi::CStr::from_ptr(s).to_string_lossy().into_owned();
+        // NDI SDK 3 has no separate function for strings but frees them like metadata
+        if NDIlib_recv_free_string(self.as_ptr(), s).is_none() {
+            let mut frame = NDIlib_metadata_frame_t {
+                length: 0,
+                timecode: 0,
+                p_data: s,
+            };
+            NDIlib_recv_free_metadata(self.as_ptr(), &mut frame);
+        }
+        Some(res)
+    }
+
+    fn as_ptr(&self) -> *mut ::std::os::raw::c_void {
+        (self.0).0.as_ptr()
+    }
+
+    // Video or audio frames that are not to be received are discarded by the SDK instead of
+    // being returned
+    pub fn capture(
+        &self,
+        timeout_in_ms: u32,
+        receive_video: bool,
+        receive_audio: bool,
+    ) -> Result<Option<Frame>, ()> {
         unsafe {
-            let ptr = self.0.as_ptr();
+            let ptr = self.as_ptr();
 
             let mut video_frame = mem::zeroed();
             let mut audio_frame = mem::zeroed();
             let mut metadata_frame = mem::zeroed();
 
-            let res = NDIlib_recv_capture_v3(
-                ptr,
-                &mut video_frame,
-                &mut audio_frame,
-                &mut metadata_frame,
-                timeout_in_ms,
-            );
+            let video_ptr = if receive_video {
+                &mut video_frame as *mut _
+            } else {
+                ptr::null_mut()
+            };
+
+            let res = if ndisys::available::NDIlib_recv_capture_v3() {
+                NDIlib_recv_capture_v3(
+                    ptr,
+                    video_ptr,
+                    if receive_audio {
+                        &mut audio_frame
+                    } else {
+                        ptr::null_mut()
+                    },
+                    &mut metadata_frame,
+                    timeout_in_ms,
+                )
+            } else {
+                let mut audio_frame_v2 = mem::zeroed();
+                let res = NDIlib_recv_capture_v2(
+                    ptr,
+                    video_ptr,
+                    if receive_audio {
+                        &mut audio_frame_v2
+                    } else {
+                        ptr::null_mut()
+                    },
+                    &mut metadata_frame,
+                    timeout_in_ms,
+                );
+                if let Some(NDIlib_frame_type_e::NDIlib_frame_type_audio) = res {
+                    audio_frame = audio_frame_from_v2(&audio_frame_v2);
+                }
+                res
+            }
+            .unwrap_or(NDIlib_frame_type_e::NDIlib_frame_type_error);
 
             match res {
                 NDIlib_frame_type_e::NDIlib_frame_type_audio => Ok(Some(Frame::Audio(
@@ -310,6 +605,9 @@ impl RecvInstance {
                 NDIlib_frame_type_e::NDIlib_frame_type_metadata => Ok(Some(Frame::Metadata(
                     MetadataFrame::Borrowed(metadata_frame, self),
                 ))),
+                NDIlib_frame_type_e::NDIlib_frame_type_status_change => {
+                    Ok(Some(Frame::StatusChange))
+                }
                 NDIlib_frame_type_e::NDIlib_frame_type_error => Err(()),
                 _ => Ok(None),
             }
@@ -317,20 +615,49 @@ impl RecvInstance {
     }
 }
 
-impl Drop for RecvInstance {
+impl Drop for RecvInstanceInner {
     fn drop(&mut self) {
         unsafe { NDIlib_recv_destroy(self.0.as_ptr() as *mut _) }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct RecordingTimes(NDIlib_recv_recording_time_t);
+
+impl RecordingTimes {
+    pub fn no_frames(&self) -> i64 {
+        self.0.no_frames
+    }
+
+    pub fn start_time(&self) -> i64 {
+        self.0.start_time
+    }
+
+    pub fn last_time(&self) -> i64 {
+        self.0.last_time
+    }
+}
+
 #[derive(Debug)]
 pub struct SendBuilder<'a> {
     ndi_name: &'a str,
+    groups: Option<&'a str>,
+    config: Option<&'a str>,
     clock_audio: bool,
     clock_video: bool,
 }
 
 impl<'a> SendBuilder<'a> {
+    pub fn groups(self, groups: Option<&'a str>) -> Self {
+        Self { groups, ..self }
+    }
+
+    // JSON configuration overriding the global NDI configuration for this sender, ignored if
+    // not supported by the NDI SDK
+    pub fn config(self, config: Option<&'a str>) -> Self {
+        Self { config, ..self }
+    }
+
     pub fn clock_audio(self) -> Self {
         Self {
             clock_audio: true,
@@ -348,24 +675,40 @@ impl<'a> SendBuilder<'a> {
     pub fn build(self) -> Option<SendInstance> {
         unsafe {
             let ndi_name = ffi::CString::new(self.ndi_name).unwrap();
-            let ptr = NDIlib_send_create(&NDIlib_send_create_t {
+            let groups = self.groups.map(|groups| ffi::CString::new(groups).unwrap());
+            let create_settings = NDIlib_send_create_t {
                 p_ndi_name: ndi_name.as_ptr(),
                 clock_video: self.clock_video,
                 clock_audio: self.clock_audio,
-                p_groups: ptr::null(),
-            });
+                p_groups: groups.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            };
+
+            let ptr = match self.config {
+                Some(config) if send_config_supported() => {
+                    let config = ffi::CString::new(config).unwrap();
+                    NDIlib_send_create_v2(&create_settings, config.as_ptr()).unwrap()
+                }
+                _ => NDIlib_send_create(&create_settings),
+            };
 
             if ptr.is_null() {
                 None
             } else {
-                Some(SendInstance(ptr::NonNull::new_unchecked(ptr)))
+                Some(SendInstance(ptr::NonNull::new_unchecked(ptr), None, None))
             }
         }
     }
 }
 
 #[derive(Debug)]
-pub struct SendInstance(ptr::NonNull<::std::os::raw::c_void>);
+pub struct SendInstance(
+    ptr::NonNull<::std::os::raw::c_void>,
+    // Frame currently being sent asynchronously, NDI accesses its memory until the next frame
+    // is sent or the instance is destroyed
+    Option<gst_video::VideoFrame<gst_video::video_frame::Readable>>,
+    // Same for frames converted into a layout GStreamer has no format for
+    Option<gst::MappedBuffer<gst::buffer::Readable>>,
+);
 
 unsafe impl Send for SendInstance {}
 
@@ -373,6 +716,8 @@ impl SendInstance {
     pub fn builder(ndi_name: &str) -> SendBuilder {
         SendBuilder {
             ndi_name,
+            groups: None,
+            config: None,
             clock_video: false,
             clock_audio: false,
         }
@@ -384,9 +729,190 @@ impl SendInstance {
         }
     }
 
+    // Returns the frame again if NDI can't send it as is, e.g. because its planes are not
+    // contiguous in memory
+    pub fn send_video_async(
+        &mut self,
+        frame: gst_video::VideoFrame<gst_video::video_frame::Readable>,
+        timecode: i64,
+    ) -> Result<(), gst_video::VideoFrame<gst_video::video_frame::Readable>> {
+        let sent = {
+            let frame_ref = frame.as_video_frame_ref();
+            let sent = match VideoFrame::try_from_video_frame(&frame_ref, timecode) {
+                // NDI keeps reading from the frame's memory until the next send or flush, so
+                // it's kept alive in self.1 until then
+                Ok(ndi_frame) => unsafe {
+                    NDIlib_send_send_video_async_v2(self.0.as_ptr(), ndi_frame.as_ptr());
+                    true
+                },
+                Err(_) => false,
+            };
+            sent
+        };
+        if !sent {
+            return Err(frame);
+        }
+
+        self.1 = Some(frame);
+        self.2 = None;
+
+        Ok(())
+    }
+
+    // For frames converted by the caller into a layout GStreamer has no format for, like P216.
+    // `data` is kept like the frame in send_video_async().
+    pub fn send_converted_video_async(
+        &mut self,
+        frame: &gst_video::VideoFrame<gst_video::video_frame::Readable>,
+        fourcc: NDIlib_FourCC_video_type_e,
+        data: gst::MappedBuffer<gst::buffer::Readable>,
+        stride: i32,
+        timecode: i64,
+    ) -> Option<()> {
+        {
+            let frame_ref = frame.as_video_frame_ref();
+            let ndi_frame = VideoFrame::try_from_converted_video_frame(
+                &frame_ref,
+                fourcc,
+                data.as_slice(),
+                stride,
+                timecode,
+            )
+            .ok()?;
+            unsafe {
+                NDIlib_send_send_video_async_v2(self.0.as_ptr(), ndi_frame.as_ptr());
+            }
+        }
+
+        self.1 = None;
+        self.2 = Some(data);
+
+        Some(())
+    }
+
+    // Sent synchronously as NDI only borrows the packet data during the call
+    #[cfg(all(feature = "sink", feature = "advanced-sdk"))]
+    pub fn send_compressed_video(
+        &mut self,
+        format: &CompressedVideoFormat,
+        packet: &CompressedPacket,
+        timecode: i64,
+    ) {
+        let data = packet.to_bytes();
+
+        let picture_aspect_ratio = format.par * gst::Fraction::new(format.width, format.height);
+        let picture_aspect_ratio =
+            picture_aspect_ratio.numer() as f32 / picture_aspect_ratio.denom() as f32;
+
+        let ndi_frame = NDIlib_video_frame_v2_t {
+            xres: format.width,
+            yres: format.height,
+            FourCC: format.fourcc,
+            frame_rate_N: format.fps.numer(),
+            frame_rate_D: format.fps.denom(),
+            picture_aspect_ratio,
+            frame_format_type: NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive,
+            timecode,
+            p_data: data.as_ptr() as *const ::std::os::raw::c_char,
+            line_stride_or_data_size_in_bytes: data.len() as i32,
+            p_metadata: ptr::null(),
+            timestamp: 0,
+        };
+
+        // This also waits until NDI is done with any previous asynchronously sent frame
+        unsafe {
+            NDIlib_send_send_video_v2(self.0.as_ptr(), &ndi_frame);
+        }
+        self.1 = None;
+        self.2 = None;
+    }
+
+    // Only planar float audio can be sent with NDI SDK 3.5, other frames are dropped there
     pub fn send_audio(&mut self, frame: &AudioFrame) {
         unsafe {
-            NDIlib_send_send_audio_v3(self.0.as_ptr(), frame.as_ptr());
+            if NDIlib_send_send_audio_v3(self.0.as_ptr(), frame.as_ptr()).is_none()
+                && (*frame.as_ptr()).FourCC == NDIlib_FourCC_audio_type_FLTp
+            {
+                NDIlib_send_send_audio_v2(self.0.as_ptr(), &audio_frame_to_v2(&*frame.as_ptr()));
+            }
+        }
+    }
+
+    pub fn send_metadata(&mut self, metadata: &MetadataFrame) {
+        unsafe {
+            NDIlib_send_send_metadata(self.0.as_ptr(), metadata.as_ptr());
+        }
+    }
+
+    pub fn capture_metadata(&self, timeout_in_ms: u32) -> Option<MetadataFrame<'_>> {
+        unsafe {
+            let mut metadata_frame = mem::zeroed();
+
+            match NDIlib_send_capture(self.0.as_ptr(), &mut metadata_frame, timeout_in_ms) {
+                NDIlib_frame_type_e::NDIlib_frame_type_metadata => {
+                    Some(MetadataFrame::BorrowedSend(metadata_frame, self))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    pub fn no_connections(&self, timeout_in_ms: u32) -> i32 {
+        unsafe { NDIlib_send_get_no_connections(self.0.as_ptr(), timeout_in_ms) }
+    }
+
+    pub fn clear_connection_metadata(&mut self) {
+        unsafe { NDIlib_send_clear_connection_metadata(self.0.as_ptr()) }
+    }
+
+    pub fn add_connection_metadata(&mut self, metadata: &MetadataFrame) {
+        unsafe {
+            NDIlib_send_add_connection_metadata(self.0.as_ptr(), metadata.as_ptr());
+        }
+    }
+
+    pub fn set_failover(&mut self, ndi_name: Option<&str>) {
+        unsafe {
+            match ndi_name {
+                None => NDIlib_send_set_failover(self.0.as_ptr(), ptr::null()),
+                Some(ndi_name) => {
+                    let ndi_name = ffi::CString::new(ndi_name).unwrap();
+                    NDIlib_send_set_failover(
+                        self.0.as_ptr(),
+                        &NDIlib_source_t {
+                            p_ndi_name: ndi_name.as_ptr(),
+                            p_url_address: ptr::null(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    // Full name of the sender as receivers see it, i.e. "MACHINE (NAME)"
+    pub fn source_name(&self) -> Option<String> {
+        unsafe {
+            let source = NDIlib_send_get_source_name(self.0.as_ptr())?;
+            if source.is_null() || (*source).p_ndi_name.is_null() {
+                return None;
+            }
+
+            Some(
+                ffi::CStr::from_ptr((*source).p_ndi_name)
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        }
+    }
+
+    pub fn tally(&self, timeout_in_ms: u32) -> Option<Tally> {
+        unsafe {
+            let mut tally = mem::MaybeUninit::uninit();
+            if NDIlib_send_get_tally(self.0.as_ptr(), tally.as_mut_ptr(), timeout_in_ms) {
+                Some(Tally(tally.assume_init()))
+            } else {
+                None
+            }
         }
     }
 }
@@ -397,6 +923,55 @@ impl Drop for SendInstance {
     }
 }
 
+#[derive(Debug)]
+pub struct RoutingInstance(ptr::NonNull<::std::os::raw::c_void>);
+
+unsafe impl Send for RoutingInstance {}
+
+impl RoutingInstance {
+    pub fn new(ndi_name: &str, groups: Option<&str>) -> Option<Self> {
+        unsafe {
+            let ndi_name = ffi::CString::new(ndi_name).unwrap();
+            let groups = groups.map(|groups| ffi::CString::new(groups).unwrap());
+            let ptr = NDIlib_routing_create(&NDIlib_routing_create_t {
+                p_ndi_name: ndi_name.as_ptr(),
+                p_groups: groups.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            });
+
+            if ptr.is_null() {
+                None
+            } else {
+                Some(RoutingInstance(ptr::NonNull::new_unchecked(ptr)))
+            }
+        }
+    }
+
+    pub fn change(&mut self, ndi_name: Option<&str>, url_address: Option<&str>) -> bool {
+        unsafe {
+            let ndi_name = ndi_name.map(|s| ffi::CString::new(s).unwrap());
+            let url_address = url_address.map(|s| ffi::CString::new(s).unwrap());
+
+            NDIlib_routing_change(
+                self.0.as_ptr(),
+                &NDIlib_source_t {
+                    p_ndi_name: ndi_name.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+                    p_url_address: url_address.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+                },
+            )
+        }
+    }
+
+    pub fn clear(&mut self) -> bool {
+        unsafe { NDIlib_routing_clear(self.0.as_ptr()) }
+    }
+}
+
+impl Drop for RoutingInstance {
+    fn drop(&mut self) {
+        unsafe { NDIlib_routing_destroy(self.0.as_ptr()) }
+    }
+}
+
 #[derive(Debug)]
 pub struct Tally(NDIlib_tally_t);
 unsafe impl Send for Tally {}
@@ -432,6 +1007,8 @@ pub enum Frame<'a> {
     Video(VideoFrame<'a>),
     Audio(AudioFrame<'a>),
     Metadata(MetadataFrame<'a>),
+    // Something about the source changed, e.g. its web control URL or PTZ capabilities
+    StatusChange,
 }
 
 #[derive(Debug)]
@@ -519,26 +1096,53 @@ impl<'a> VideoFrame<'a> {
         ]
         .contains(&fourcc)
         {
+            let stride = self.line_stride_or_data_size_in_bytes() as i64;
+            if self.xres() <= 0 || self.yres() <= 0 || stride <= 0 {
+                return None;
+            }
+
             // FIXME: Unclear if this is correct. Needs to be validated against an actual
             // interlaced stream
-            let frame_size = if self.frame_format_type()
+            let lines = if self.frame_format_type()
                 == NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_0
                 || self.frame_format_type()
                     == NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_1
             {
-                self.yres() * self.line_stride_or_data_size_in_bytes() / 2
+                self.yres() as i64 / 2
+            } else {
+                self.yres() as i64
+            };
+
+            // Chroma and alpha planes follow the luma plane in the same allocation
+            let frame_size = if fourcc == NDIlib_FourCC_video_type_NV12 {
+                stride * lines + stride * ((lines + 1) / 2)
+            } else if [NDIlib_FourCC_video_type_YV12, NDIlib_FourCC_video_type_I420]
+                .contains(&fourcc)
+            {
+                stride * lines + 2 * (stride / 2) * ((lines + 1) / 2)
+            } else if fourcc == NDIlib_FourCC_video_type_P216 {
+                2 * stride * lines
+            } else if fourcc == NDIlib_FourCC_video_type_PA16 {
+                3 * stride * lines
+            } else if fourcc == NDIlib_FourCC_video_type_UYVA {
+                stride * lines + self.xres() as i64 * lines
             } else {
-                self.yres() * self.line_stride_or_data_size_in_bytes()
+                stride * lines
             };
 
             return unsafe {
                 use std::slice;
                 match self {
                     VideoFrame::BorrowedRecv(ref frame, _)
-                    | VideoFrame::BorrowedGst(ref frame, _) => Some(slice::from_raw_parts(
-                        frame.p_data as *const u8,
-                        frame_size as usize,
-                    )),
+                    | VideoFrame::BorrowedGst(ref frame, _) => {
+                        if frame.p_data.is_null() {
+                            return None;
+                        }
+                        Some(slice::from_raw_parts(
+                            frame.p_data as *const u8,
+                            frame_size as usize,
+                        ))
+                    }
                 }
             };
         }
@@ -558,10 +1162,15 @@ impl<'a> VideoFrame<'a> {
                 use std::slice;
                 match self {
                     VideoFrame::BorrowedRecv(ref frame, _)
-                    | VideoFrame::BorrowedGst(ref frame, _) => Some(slice::from_raw_parts(
-                        frame.p_data as *const u8,
-                        frame.line_stride_or_data_size_in_bytes as usize,
-                    )),
+                    | VideoFrame::BorrowedGst(ref frame, _) => {
+                        if frame.p_data.is_null() || frame.line_stride_or_data_size_in_bytes <= 0 {
+                            return None;
+                        }
+                        Some(slice::from_raw_parts(
+                            frame.p_data as *const u8,
+                            frame.line_stride_or_data_size_in_bytes as usize,
+                        ))
+                    }
                 }
             };
         }
@@ -678,7 +1287,7 @@ impl<'a> VideoFrame<'a> {
                     if frame.p_metadata.is_null() {
                         None
                     } else {
-                        Some(ffi::CStr::from_ptr(frame.p_metadata).to_str().unwrap())
+                        ffi::CStr::from_ptr(frame.p_metadata).to_str().ok()
                     }
                 }
             }
@@ -733,16 +1342,6 @@ impl<'a> VideoFrame<'a> {
 
                 ndisys::NDIlib_FourCC_video_type_NV12
             }
-            gst_video::VideoFormat::Nv21 => {
-                if (frame.plane_data(1).unwrap().as_ptr() as usize)
-                    .checked_sub(frame.plane_data(0).unwrap().as_ptr() as usize)
-                    != Some(frame.height() as usize * frame.plane_stride()[0] as usize)
-                {
-                    return Err(());
-                }
-
-                ndisys::NDIlib_FourCC_video_type_NV12
-            }
             gst_video::VideoFormat::Yv12 => {
                 if (frame.plane_data(1).unwrap().as_ptr() as usize)
                     .checked_sub(frame.plane_data(0).unwrap().as_ptr() as usize)
@@ -767,6 +1366,23 @@ impl<'a> VideoFrame<'a> {
             _ => return Err(()),
         };
 
+        Self::try_from_converted_video_frame(
+            frame,
+            format,
+            frame.plane_data(0).unwrap(),
+            frame.plane_stride()[0],
+            timecode,
+        )
+    }
+
+    // Describes `data` in the given FourCC with the size, frame rate and interlacing of `frame`
+    fn try_from_converted_video_frame(
+        frame: &'a gst_video::VideoFrameRef<&'a gst::BufferRef>,
+        fourcc: NDIlib_FourCC_video_type_e,
+        data: &'a [u8],
+        stride: i32,
+        timecode: i64,
+    ) -> Result<Self, ()> {
         let frame_format_type = match frame.info().interlace_mode() {
             gst_video::VideoInterlaceMode::Progressive => {
                 NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive
@@ -774,16 +1390,30 @@ impl<'a> VideoFrame<'a> {
             gst_video::VideoInterlaceMode::Interleaved => {
                 NDIlib_frame_format_type_e::NDIlib_frame_format_type_interleaved
             }
-            // FIXME: Is this correct?
+            gst_video::VideoInterlaceMode::Mixed
+                if frame
+                    .flags()
+                    .contains(gst_video::VideoFrameFlags::INTERLACED) =>
+            {
+                NDIlib_frame_format_type_e::NDIlib_frame_format_type_interleaved
+            }
+            gst_video::VideoInterlaceMode::Mixed => {
+                NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive
+            }
+            // Field 0 is the top field with the even lines, field 1 the bottom field
             #[cfg(feature = "interlaced-fields")]
             gst_video::VideoInterlaceMode::Alternate
-                if frame.flags().contains(gst_video::VideoFrameFlags::TFF) =>
+                if frame
+                    .flags()
+                    .contains(gst_video::VideoFrameFlags::TOP_FIELD) =>
             {
                 NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_0
             }
             #[cfg(feature = "interlaced-fields")]
             gst_video::VideoInterlaceMode::Alternate
-                if !frame.flags().contains(gst_video::VideoFrameFlags::TFF) =>
+                if frame
+                    .flags()
+                    .contains(gst_video::VideoFrameFlags::BOTTOM_FIELD) =>
             {
                 NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_1
             }
@@ -798,14 +1428,14 @@ impl<'a> VideoFrame<'a> {
         let ndi_frame = NDIlib_video_frame_v2_t {
             xres: frame.width() as i32,
             yres: frame.height() as i32,
-            FourCC: format,
+            FourCC: fourcc,
             frame_rate_N: frame.info().fps().numer(),
             frame_rate_D: frame.info().fps().denom(),
             picture_aspect_ratio,
             frame_format_type,
             timecode,
-            p_data: frame.plane_data(0).unwrap().as_ptr() as *const ::std::os::raw::c_char,
-            line_stride_or_data_size_in_bytes: frame.plane_stride()[0],
+            p_data: data.as_ptr() as *const ::std::os::raw::c_char,
+            line_stride_or_data_size_in_bytes: stride,
             p_metadata: ptr::null(),
             timestamp: 0,
         };
@@ -819,7 +1449,7 @@ impl<'a> Drop for VideoFrame<'a> {
     fn drop(&mut self) {
         if let VideoFrame::BorrowedRecv(ref mut frame, recv) = *self {
             unsafe {
-                NDIlib_recv_free_video_v2(recv.0.as_ptr() as *mut _, frame);
+                NDIlib_recv_free_video_v2(recv.as_ptr(), frame);
             }
         }
     }
@@ -830,7 +1460,7 @@ pub enum AudioFrame<'a> {
     Owned(
         NDIlib_audio_frame_v3_t,
         Option<ffi::CString>,
-        Option<Vec<f32>>,
+        Option<OwnedAudioData>,
     ),
     BorrowedRecv(NDIlib_audio_frame_v3_t, &'a RecvInstance),
 }
@@ -882,28 +1512,38 @@ impl<'a> AudioFrame<'a> {
 
             let fourcc = self.fourcc();
 
+            let frame = match self {
+                AudioFrame::BorrowedRecv(ref frame, _) | AudioFrame::Owned(ref frame, _, _) => {
+                    frame
+                }
+            };
+
+            if frame.p_data.is_null() {
+                return None;
+            }
+
             if [NDIlib_FourCC_audio_type_FLTp].contains(&fourcc) {
-                return match self {
-                    AudioFrame::BorrowedRecv(ref frame, _) | AudioFrame::Owned(ref frame, _, _) => {
-                        Some(slice::from_raw_parts(
-                            frame.p_data as *const u8,
-                            (frame.no_channels * frame.channel_stride_or_data_size_in_bytes)
-                                as usize,
-                        ))
-                    }
-                };
+                if frame.no_channels < 0 || frame.channel_stride_or_data_size_in_bytes < 0 {
+                    return None;
+                }
+
+                return Some(slice::from_raw_parts(
+                    frame.p_data as *const u8,
+                    frame.no_channels as usize
+                        * frame.channel_stride_or_data_size_in_bytes as usize,
+                ));
             }
 
             #[cfg(feature = "advanced-sdk")]
             if [NDIlib_FourCC_audio_type_Opus].contains(&fourcc) {
-                return match self {
-                    AudioFrame::BorrowedRecv(ref frame, _) | AudioFrame::Owned(ref frame, _, _) => {
-                        Some(slice::from_raw_parts(
-                            frame.p_data as *const u8,
-                            frame.channel_stride_or_data_size_in_bytes as usize,
-                        ))
-                    }
-                };
+                if frame.channel_stride_or_data_size_in_bytes < 0 {
+                    return None;
+                }
+
+                return Some(slice::from_raw_parts(
+                    frame.p_data as *const u8,
+                    frame.channel_stride_or_data_size_in_bytes as usize,
+                ));
             }
 
             None
@@ -987,7 +1627,7 @@ impl<'a> AudioFrame<'a> {
                     if frame.p_metadata.is_null() {
                         None
                     } else {
-                        Some(ffi::CStr::from_ptr(frame.p_metadata).to_str().unwrap())
+                        ffi::CStr::from_ptr(frame.p_metadata).to_str().ok()
                     }
                 }
             }
@@ -1008,41 +1648,49 @@ impl<'a> AudioFrame<'a> {
         }
     }
 
+    #[cfg(feature = "sink")]
     pub fn try_from_buffer(
         info: &gst_audio::AudioInfo,
         buffer: &gst::BufferRef,
         timecode: i64,
     ) -> Result<Self, ()> {
-        if info.format() != gst_audio::AUDIO_FORMAT_F32 {
-            return Err(());
-        }
-
-        let map = buffer.map_readable().map_err(|_| ())?;
-        let src_data = map.as_slice_of::<f32>().map_err(|_| ())?;
-
-        let no_samples = src_data.len() as i32 / info.channels() as i32;
-        let channel_stride_or_data_size_in_bytes = no_samples * mem::size_of::<f32>() as i32;
-        let mut dest_data =
-            Vec::<f32>::with_capacity(no_samples as usize * info.channels() as usize);
-
-        assert_eq!(dest_data.capacity(), src_data.len());
-
-        unsafe {
-            let dest_ptr = dest_data.as_mut_ptr();
-
-            for (i, samples) in src_data.chunks_exact(info.channels() as usize).enumerate() {
-                for (c, sample) in samples.iter().enumerate() {
-                    ptr::write(dest_ptr.add(c * no_samples as usize + i), *sample);
+        let audio_buffer =
+            gst_audio::AudioBufferRef::from_buffer_ref_readable(buffer, info).map_err(|_| ())?;
+
+        let no_channels = info.channels() as usize;
+        let no_samples = audio_buffer.n_samples();
+        let channel_stride_or_data_size_in_bytes = (no_samples * mem::size_of::<f32>()) as i32;
+
+        // NDI only takes planar float samples, so convert everything else into that
+        let planes = (0..audio_buffer.n_planes())
+            .map(|plane| {
+                let data = audio_buffer.plane_data(plane).map_err(|_| ())?;
+                match info.format() {
+                    gst_audio::AUDIO_FORMAT_F32 => {
+                        Ok(Samples::F32(data.as_slice_of::<f32>().map_err(|_| ())?))
+                    }
+                    gst_audio::AUDIO_FORMAT_S16 => {
+                        Ok(Samples::S16(data.as_slice_of::<i16>().map_err(|_| ())?))
+                    }
+                    _ => Err(()),
                 }
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+
+        let mut dest_data = vec![0.0f32; no_samples * no_channels];
+        for (c, dest) in dest_data.chunks_exact_mut(no_samples.max(1)).enumerate() {
+            for (i, sample) in dest.iter_mut().enumerate() {
+                *sample = match info.layout() {
+                    gst_audio::AudioLayout::NonInterleaved => planes[c].get(i),
+                    _ => planes[0].get(i * no_channels + c),
+                };
             }
-
-            dest_data.set_len(no_samples as usize * info.channels() as usize);
         }
 
         let dest = NDIlib_audio_frame_v3_t {
             sample_rate: info.rate() as i32,
             no_channels: info.channels() as i32,
-            no_samples,
+            no_samples: no_samples as i32,
             timecode,
             FourCC: NDIlib_FourCC_audio_type_FLTp,
             p_data: dest_data.as_mut_ptr(),
@@ -1051,7 +1699,117 @@ impl<'a> AudioFrame<'a> {
             timestamp: 0,
         };
 
-        Ok(AudioFrame::Owned(dest, None, Some(dest_data)))
+        Ok(AudioFrame::Owned(
+            dest,
+            None,
+            Some(OwnedAudioData::Samples(dest_data)),
+        ))
+    }
+
+    // Compressed audio is passed through as is, AAC wrapped into a compressed packet
+    #[cfg(all(feature = "sink", feature = "advanced-sdk"))]
+    pub fn try_from_compressed_buffer(
+        fourcc: NDIlib_FourCC_audio_type_e,
+        sample_rate: i32,
+        no_channels: i32,
+        no_samples: i32,
+        buffer: &gst::BufferRef,
+        codec_data: Option<&[u8]>,
+        timecode: i64,
+    ) -> Result<Self, ()> {
+        let map = buffer.map_readable().map_err(|_| ())?;
+
+        let data = if fourcc == NDIlib_FourCC_audio_type_AAC {
+            CompressedPacket {
+                fourcc: NDIlib_compressed_FourCC_type_AAC,
+                pts: timecode,
+                dts: timecode,
+                key_frame: true,
+                data: map.as_slice(),
+                extra_data: codec_data,
+            }
+            .to_bytes()
+        } else if fourcc == NDIlib_FourCC_audio_type_Opus {
+            map.as_slice().to_vec()
+        } else {
+            return Err(());
+        };
+
+        let dest = NDIlib_audio_frame_v3_t {
+            sample_rate,
+            no_channels,
+            no_samples,
+            timecode,
+            FourCC: fourcc,
+            p_data: data.as_ptr() as *const f32,
+            channel_stride_or_data_size_in_bytes: data.len() as i32,
+            p_metadata: ptr::null(),
+            timestamp: 0,
+        };
+
+        Ok(AudioFrame::Owned(
+            dest,
+            None,
+            Some(OwnedAudioData::Bytes(data)),
+        ))
+    }
+}
+
+// Keeps the data of owned audio frames alive
+#[derive(Debug)]
+pub enum OwnedAudioData {
+    Samples(Vec<f32>),
+    #[cfg(all(feature = "sink", feature = "advanced-sdk"))]
+    Bytes(Vec<u8>),
+}
+
+#[cfg(feature = "sink")]
+enum Samples<'a> {
+    F32(&'a [f32]),
+    S16(&'a [i16]),
+}
+
+#[cfg(feature = "sink")]
+impl<'a> Samples<'a> {
+    fn get(&self, idx: usize) -> f32 {
+        match self {
+            Samples::F32(samples) => samples[idx],
+            Samples::S16(samples) => samples[idx] as f32 / 32768.0,
+        }
+    }
+}
+
+// NDI SDK 3.5 only knows planar float audio, which is what the v2 frames contain
+fn audio_frame_from_v2(frame: &NDIlib_audio_frame_v2_t) -> NDIlib_audio_frame_v3_t {
+    NDIlib_audio_frame_v3_t {
+        sample_rate: frame.sample_rate,
+        no_channels: frame.no_channels,
+        no_samples: frame.no_samples,
+        timecode: frame.timecode,
+        FourCC: NDIlib_FourCC_audio_type_FLTp,
+        p_data: frame.p_data,
+        channel_stride_or_data_size_in_bytes: frame.channel_stride_in_bytes,
+        p_metadata: frame.p_metadata,
+        timestamp: frame.timestamp,
+    }
+}
+
+fn audio_frame_to_v2(frame: &NDIlib_audio_frame_v3_t) -> NDIlib_audio_frame_v2_t {
+    NDIlib_audio_frame_v2_t {
+        sample_rate: frame.sample_rate,
+        no_channels: frame.no_channels,
+        no_samples: frame.no_samples,
+        timecode: frame.timecode,
+        p_data: frame.p_data,
+        channel_stride_in_bytes: frame.channel_stride_or_data_size_in_bytes,
+        p_metadata: frame.p_metadata,
+        timestamp: frame.timestamp,
+    }
+}
+
+unsafe fn free_recv_audio(recv: NDIlib_recv_instance_t, frame: &mut NDIlib_audio_frame_v3_t) {
+    if NDIlib_recv_free_audio_v3(recv, frame).is_none() {
+        NDIlib_recv_free_audio_v2(recv, &audio_frame_to_v2(frame));
     }
 }
 
@@ -1060,8 +1818,79 @@ impl<'a> Drop for AudioFrame<'a> {
     fn drop(&mut self) {
         if let AudioFrame::BorrowedRecv(ref mut frame, recv) = *self {
             unsafe {
-                NDIlib_recv_free_audio_v3(recv.0.as_ptr() as *mut _, frame);
+                free_recv_audio(recv.as_ptr(), frame);
+            }
+        }
+    }
+}
+
+impl<'a> AudioFrame<'a> {
+    // Takes over a raw audio frame captured from a receiver so that it can outlive the capture
+    // call, other frames are returned unchanged
+    pub fn into_owned_recv(self) -> Result<OwnedRecvAudioFrame, Self> {
+        let owned = match self {
+            AudioFrame::BorrowedRecv(frame, recv)
+                if frame.FourCC == NDIlib_FourCC_audio_type_FLTp
+                    && !frame.p_data.is_null()
+                    && frame.no_channels >= 0
+                    && frame.channel_stride_or_data_size_in_bytes >= 0 =>
+            {
+                OwnedRecvAudioFrame(frame, recv.clone())
             }
+            _ => return Err(self),
+        };
+
+        // Freed by the owned frame from now on
+        mem::forget(self);
+
+        Ok(owned)
+    }
+}
+
+// Audio frame of a receiver that is freed when dropped, which allows wrapping the samples
+// in a buffer without copying
+#[derive(Debug)]
+pub struct OwnedRecvAudioFrame(NDIlib_audio_frame_v3_t, RecvInstance);
+
+unsafe impl Send for OwnedRecvAudioFrame {}
+
+impl OwnedRecvAudioFrame {
+    pub fn no_channels(&self) -> i32 {
+        self.0.no_channels
+    }
+
+    pub fn no_samples(&self) -> i32 {
+        self.0.no_samples
+    }
+
+    pub fn channel_stride(&self) -> i32 {
+        self.0.channel_stride_or_data_size_in_bytes
+    }
+
+    pub fn timecode(&self) -> i64 {
+        self.0.timecode
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+}
+
+impl AsRef<[u8]> for OwnedRecvAudioFrame {
+    fn as_ref(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.0.p_data as *const u8,
+                self.0.no_channels as usize * self.0.channel_stride_or_data_size_in_bytes as usize,
+            )
+        }
+    }
+}
+
+impl Drop for OwnedRecvAudioFrame {
+    fn drop(&mut self) {
+        unsafe {
+            free_recv_audio(self.1.as_ptr(), &mut self.0);
         }
     }
 }
@@ -1076,10 +1905,63 @@ pub struct CompressedPacket<'a> {
     pub extra_data: Option<&'a [u8]>,
 }
 
+// H.264 or HEVC video that is sent as NDI|HX without decoding
+#[cfg(all(feature = "sink", feature = "advanced-sdk"))]
+#[derive(Debug, Clone)]
+pub struct CompressedVideoFormat {
+    pub fourcc: NDIlib_FourCC_video_type_e,
+    pub width: i32,
+    pub height: i32,
+    pub fps: gst::Fraction,
+    pub par: gst::Fraction,
+}
+
+#[cfg(all(feature = "sink", feature = "advanced-sdk"))]
+impl<'a> CompressedPacket<'a> {
+    // Serializes the packet as NDIlib_compressed_packet_t followed by the data and extra data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use byteorder::{LittleEndian, WriteBytesExt};
+
+        let extra_data = self.extra_data.unwrap_or(&[]);
+        let mut bytes = Vec::with_capacity(
+            ndisys::NDIlib_compressed_packet_version_0 as usize
+                + self.data.len()
+                + extra_data.len(),
+        );
+
+        // Writing into a Vec can't fail
+        bytes
+            .write_u32::<LittleEndian>(ndisys::NDIlib_compressed_packet_version_0)
+            .unwrap();
+        bytes.write_u32::<LittleEndian>(self.fourcc).unwrap();
+        bytes.write_i64::<LittleEndian>(self.pts).unwrap();
+        bytes.write_i64::<LittleEndian>(self.dts).unwrap();
+        bytes.write_u64::<LittleEndian>(0).unwrap();
+        bytes
+            .write_u32::<LittleEndian>(if self.key_frame {
+                ndisys::NDIlib_compressed_packet_flags_keyframe
+            } else {
+                0
+            })
+            .unwrap();
+        bytes
+            .write_u32::<LittleEndian>(self.data.len() as u32)
+            .unwrap();
+        bytes
+            .write_u32::<LittleEndian>(extra_data.len() as u32)
+            .unwrap();
+        bytes.extend_from_slice(self.data);
+        bytes.extend_from_slice(extra_data);
+
+        bytes
+    }
+}
+
 #[derive(Debug)]
 pub enum MetadataFrame<'a> {
     Owned(NDIlib_metadata_frame_t, Option<ffi::CString>),
     Borrowed(NDIlib_metadata_frame_t, &'a RecvInstance),
+    BorrowedSend(NDIlib_metadata_frame_t, &'a SendInstance),
 }
 
 impl<'a> MetadataFrame<'a> {
@@ -1105,7 +1987,9 @@ impl<'a> MetadataFrame<'a> {
     pub fn timecode(&self) -> i64 {
         match self {
             MetadataFrame::Owned(ref frame, _) => frame.timecode,
-            MetadataFrame::Borrowed(ref frame, _) => frame.timecode,
+            MetadataFrame::Borrowed(ref frame, _) | MetadataFrame::BorrowedSend(ref frame, _) => {
+                frame.timecode
+            }
         }
     }
 
@@ -1115,22 +1999,21 @@ impl<'a> MetadataFrame<'a> {
                 MetadataFrame::Owned(_, ref metadata) => {
                     metadata.as_ref().map(|s| s.to_str().unwrap())
                 }
-                MetadataFrame::Borrowed(ref frame, _) => {
+                MetadataFrame::Borrowed(ref frame, _)
+                | MetadataFrame::BorrowedSend(ref frame, _) => {
                     if frame.p_data.is_null() || frame.length == 0 {
                         None
-                    } else if frame.length != 0 {
+                    } else if frame.length > 0 {
                         use std::slice;
 
-                        Some(
-                            ffi::CStr::from_bytes_with_nul_unchecked(slice::from_raw_parts(
-                                frame.p_data as *const u8,
-                                frame.length as usize,
-                            ))
-                            .to_str()
-                            .unwrap(),
-                        )
+                        // The length includes the terminating NUL but don't rely on the sender
+                        // getting that right
+                        let data =
+                            slice::from_raw_parts(frame.p_data as *const u8, frame.length as usize);
+                        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                        std::str::from_utf8(&data[..len]).ok()
                     } else {
-                        Some(ffi::CStr::from_ptr(frame.p_data).to_str().unwrap())
+                        ffi::CStr::from_ptr(frame.p_data).to_str().ok()
                     }
                 }
             }
@@ -1141,6 +2024,7 @@ impl<'a> MetadataFrame<'a> {
         match self {
             MetadataFrame::Owned(ref frame, _) => frame,
             MetadataFrame::Borrowed(ref frame, _) => frame,
+            MetadataFrame::BorrowedSend(ref frame, _) => frame,
         }
     }
 }
@@ -1160,10 +2044,14 @@ impl<'a> Default for MetadataFrame<'a> {
 
 impl<'a> Drop for MetadataFrame<'a> {
     fn drop(&mut self) {
-        if let MetadataFrame::Borrowed(ref mut frame, recv) = *self {
-            unsafe {
-                NDIlib_recv_free_metadata(recv.0.as_ptr() as *mut _, frame);
-            }
+        match *self {
+            MetadataFrame::Borrowed(ref mut frame, recv) => unsafe {
+                NDIlib_recv_free_metadata(recv.as_ptr(), frame);
+            },
+            MetadataFrame::BorrowedSend(ref frame, send) => unsafe {
+                NDIlib_send_free_metadata(send.0.as_ptr() as *mut _, frame);
+            },
+            MetadataFrame::Owned(..) => (),
         }
     }
 }
@@ -1182,3 +2070,72 @@ impl Queue {
         self.0.metadata_frames
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_addresses() {
+        assert_eq!(
+            normalize_address(" 192.168.1.10 "),
+            Some(String::from("192.168.1.10"))
+        );
+        assert_eq!(
+            normalize_address("192.168.1.10:5961"),
+            Some(String::from("192.168.1.10:5961"))
+        );
+        assert_eq!(
+            normalize_address("fe80:0:0:0:0:0:0:1"),
+            Some(String::from("fe80::1"))
+        );
+        assert_eq!(
+            normalize_address("[fe80::1]"),
+            Some(String::from("fe80::1"))
+        );
+        assert_eq!(
+            normalize_address("[fe80::0:1]:5961"),
+            Some(String::from("[fe80::1]:5961"))
+        );
+        assert_eq!(normalize_address("ndi-host"), None);
+        assert_eq!(normalize_address("fe80::1:5961:"), None);
+
+        assert_eq!(
+            normalize_address_list(" 10.0.0.1 ,ndi-host,, [::1]:5961"),
+            "10.0.0.1,ndi-host,[::1]:5961"
+        );
+    }
+
+    #[test]
+    fn validate_addresses() {
+        assert!(validate_address("10.0.0.1").is_ok());
+        assert!(validate_address("10.0.0.1:5961").is_ok());
+        assert!(validate_address("[fe80::1]:5961").is_ok());
+        assert!(validate_address("fe80::1").is_ok());
+        assert!(validate_address("ndi-host.local").is_ok());
+        assert!(validate_address("ndi-host:5961").is_ok());
+
+        assert!(validate_address("10.0.0.1:0").is_err());
+        assert!(validate_address("ndi-host:0").is_err());
+        assert!(validate_address("ndi-host:65536").is_err());
+        assert!(validate_address("ndi-host:port").is_err());
+        assert!(validate_address("ndi host").is_err());
+        assert!(validate_address(":5961").is_err());
+        assert!(validate_address("").is_err());
+
+        assert!(validate_address_list("10.0.0.1, ndi-host:5961").is_ok());
+        assert!(validate_address_list("10.0.0.1, ndi host").is_err());
+        assert!(validate_address_list(" , ").is_err());
+    }
+
+    #[test]
+    fn validate_ndi_names() {
+        assert!(validate_ndi_name("MACHINE (Source)").is_ok());
+        assert!(validate_ndi_name("MACHINE (Source (2))").is_ok());
+
+        assert!(validate_ndi_name("MACHINE").is_err());
+        assert!(validate_ndi_name(" (Source)").is_err());
+        assert!(validate_ndi_name("MACHINE (Source").is_err());
+        assert!(validate_ndi_name("MACHINE(Source)").is_err());
+    }
+}
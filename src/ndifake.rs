@@ -0,0 +1,632 @@
+#![allow(non_snake_case)]
+
+// In-process stand-in for the NDI library, used instead of loading the SDK when building with
+// the `test-backend` feature. It announces a single source that produces SMPTE color bars and
+// a sine tone in real time, and can be told to misbehave via GST_NDI_FAKE_OPTIONS, a comma
+// separated list of key=value pairs:
+//
+//  width, height     video size, default 320x240
+//  fps               frame rate as N or N/D, default 30/1
+//  rate, channels    audio format, default 48000 Hz stereo
+//  freq              sine frequency in Hz, default 1000
+//  jitter            maximum random delay of each frame in ms
+//  drift             sender clock drift in ppm
+//  drop              drop every Nth video frame
+//  timecode-jump     make the timecodes jump forward by one second every N video frames
+//  resize            halve/restore the video size every N video frames
+//
+// With the lowest bandwidth, video is produced at half the size like the proxy stream of a
+// real sender.
+
+use std::env;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ndisys::*;
+
+const SOURCE_NAME: &[u8] = b"FAKE (Test Source)\0";
+const SOURCE_URL: &[u8] = b"127.0.0.1:5961\0";
+const VERSION: &[u8] = b"fake\0";
+
+// 75% SMPTE color bars as Y, Cb, Cr
+const BARS: [(u8, u8, u8); 7] = [
+    (180, 128, 128),
+    (162, 44, 142),
+    (131, 156, 44),
+    (112, 72, 58),
+    (84, 184, 198),
+    (65, 100, 212),
+    (35, 212, 114),
+];
+
+#[derive(Debug, Clone)]
+struct Options {
+    width: i32,
+    height: i32,
+    fps_n: i32,
+    fps_d: i32,
+    rate: i32,
+    channels: i32,
+    freq: f64,
+    jitter: u64,
+    drift: f64,
+    drop: u64,
+    timecode_jump: u64,
+    resize: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 320,
+            height: 240,
+            fps_n: 30,
+            fps_d: 1,
+            rate: 48_000,
+            channels: 2,
+            freq: 1000.0,
+            jitter: 0,
+            drift: 0.0,
+            drop: 0,
+            timecode_jump: 0,
+            resize: 0,
+        }
+    }
+}
+
+impl Options {
+    fn from_env() -> Self {
+        let mut options = Options::default();
+
+        let value = match env::var("GST_NDI_FAKE_OPTIONS") {
+            Ok(value) => value,
+            Err(_) => return options,
+        };
+
+        for (key, value) in value
+            .split(',')
+            .filter_map(|option| option.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+        {
+            match key {
+                "width" => options.width = value.parse().unwrap_or(options.width).max(2) & !1,
+                "height" => options.height = value.parse().unwrap_or(options.height).max(1),
+                "fps" => {
+                    let (n, d) = value.split_once('/').unwrap_or((value, "1"));
+                    if let (Ok(n), Ok(d)) = (n.parse::<i32>(), d.parse::<i32>()) {
+                        if n > 0 && d > 0 {
+                            options.fps_n = n;
+                            options.fps_d = d;
+                        }
+                    }
+                }
+                "rate" => options.rate = value.parse().unwrap_or(options.rate).max(1),
+                "channels" => options.channels = value.parse().unwrap_or(options.channels).max(1),
+                "freq" => options.freq = value.parse().unwrap_or(options.freq),
+                "jitter" => options.jitter = value.parse().unwrap_or(options.jitter),
+                "drift" => options.drift = value.parse().unwrap_or(options.drift),
+                "drop" => options.drop = value.parse().unwrap_or(options.drop),
+                "timecode-jump" => options.timecode_jump = value.parse().unwrap_or(0),
+                "resize" => options.resize = value.parse().unwrap_or(options.resize),
+                _ => (),
+            }
+        }
+
+        options
+    }
+
+    // Sender time of the given video frame in 100ns units
+    fn frame_time(&self, frame: u64) -> i64 {
+        (frame as i128 * 10_000_000 * self.fps_d as i128 / self.fps_n as i128) as i64
+    }
+
+    // Wall clock offset from the start at which the sender produces the given sender time
+    fn wall_time(&self, time: i64) -> Duration {
+        Duration::from_nanos((time as f64 * 100.0 / (1.0 + self.drift / 1_000_000.0)) as u64)
+    }
+}
+
+struct FakeFind {
+    sources: [NDIlib_source_t; 1],
+    announced: bool,
+}
+
+struct FakeRecv {
+    options: Options,
+    bandwidth: NDIlib_recv_bandwidth_e,
+    start: Option<Instant>,
+    video_frames: u64,
+    audio_samples: u64,
+    random: u64,
+}
+
+impl FakeRecv {
+    fn next_random(&mut self) -> u64 {
+        // xorshift, good enough for jitter
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    fn jitter(&mut self) -> Duration {
+        if self.options.jitter == 0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_micros(self.next_random() % (self.options.jitter * 1000))
+    }
+
+    fn samples_per_frame(&self) -> u64 {
+        (self.options.rate as u64 * self.options.fps_d as u64 / self.options.fps_n as u64).max(1)
+    }
+
+    fn audio_time(&self) -> i64 {
+        (self.audio_samples as i128 * 10_000_000 / self.options.rate as i128) as i64
+    }
+
+    fn timecode(&self, time: i64) -> i64 {
+        if self.options.timecode_jump == 0 {
+            return time;
+        }
+
+        time + (self.video_frames / self.options.timecode_jump) as i64 * 10_000_000
+    }
+
+    fn video_size(&self) -> (i32, i32) {
+        let (width, height) = if self.bandwidth == NDIlib_recv_bandwidth_lowest {
+            (
+                (self.options.width / 2).max(2) & !1,
+                (self.options.height / 2).max(1),
+            )
+        } else {
+            (self.options.width, self.options.height)
+        };
+
+        if self.options.resize != 0 && (self.video_frames / self.options.resize) % 2 == 1 {
+            ((width / 2).max(2) & !1, (height / 2).max(1))
+        } else {
+            (width, height)
+        }
+    }
+
+    fn fill_video(&mut self, frame: &mut NDIlib_video_frame_v2_t) {
+        let (width, height) = self.video_size();
+        let stride = width as usize * 2;
+
+        let mut line = vec![0u8; stride];
+        for (x, pixel) in line.chunks_exact_mut(4).enumerate() {
+            let (y, u, v) = BARS[x * 2 * BARS.len() / width as usize];
+            pixel.copy_from_slice(&[u, y, v, y]);
+        }
+
+        let mut data = Vec::with_capacity(stride * height as usize);
+        for _ in 0..height {
+            data.extend_from_slice(&line);
+        }
+
+        let time = self.options.frame_time(self.video_frames);
+
+        *frame = NDIlib_video_frame_v2_t {
+            xres: width,
+            yres: height,
+            FourCC: NDIlib_FourCC_video_type_UYVY,
+            frame_rate_N: self.options.fps_n,
+            frame_rate_D: self.options.fps_d,
+            picture_aspect_ratio: 0.0,
+            frame_format_type: NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive,
+            timecode: self.timecode(time),
+            p_data: Box::into_raw(data.into_boxed_slice()) as *const c_char,
+            line_stride_or_data_size_in_bytes: stride as c_int,
+            p_metadata: ptr::null(),
+            timestamp: time,
+        };
+    }
+
+    fn fill_audio(&mut self, frame: &mut NDIlib_audio_frame_v3_t) {
+        let samples = self.samples_per_frame() as usize;
+        let channels = self.options.channels as usize;
+
+        let mut data = vec![0f32; samples * channels];
+        for (i, sample) in data[..samples].iter_mut().enumerate() {
+            let t = (self.audio_samples + i as u64) as f64 / self.options.rate as f64;
+            *sample = (0.5 * (2.0 * std::f64::consts::PI * self.options.freq * t).sin()) as f32;
+        }
+        for channel in 1..channels {
+            data.copy_within(..samples, channel * samples);
+        }
+
+        let time = self.audio_time();
+
+        *frame = NDIlib_audio_frame_v3_t {
+            sample_rate: self.options.rate,
+            no_channels: channels as c_int,
+            no_samples: samples as c_int,
+            timecode: self.timecode(time),
+            FourCC: NDIlib_FourCC_audio_type_FLTp,
+            p_data: Box::into_raw(data.into_boxed_slice()) as *const f32,
+            channel_stride_or_data_size_in_bytes: (samples * 4) as c_int,
+            p_metadata: ptr::null(),
+            timestamp: time,
+        };
+    }
+}
+
+struct FakeSend;
+
+struct FakeRouting;
+
+unsafe extern "C" fn initialize() -> bool {
+    true
+}
+
+unsafe extern "C" fn destroy() {}
+
+unsafe extern "C" fn version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+unsafe extern "C" fn find_create_v2(
+    _p_create_settings: *const NDIlib_find_create_t,
+) -> NDIlib_find_instance_t {
+    Box::into_raw(Box::new(FakeFind {
+        sources: [NDIlib_source_t {
+            p_ndi_name: SOURCE_NAME.as_ptr() as *const c_char,
+            p_url_address: SOURCE_URL.as_ptr() as *const c_char,
+        }],
+        announced: false,
+    })) as NDIlib_find_instance_t
+}
+
+unsafe extern "C" fn find_destroy(p_instance: NDIlib_find_instance_t) {
+    drop(Box::from_raw(p_instance as *mut FakeFind));
+}
+
+unsafe extern "C" fn find_wait_for_sources(
+    p_instance: NDIlib_find_instance_t,
+    timeout_in_ms: u32,
+) -> bool {
+    let find = &mut *(p_instance as *mut FakeFind);
+    if !find.announced {
+        find.announced = true;
+        return true;
+    }
+
+    thread::sleep(Duration::from_millis(timeout_in_ms as u64));
+    false
+}
+
+unsafe extern "C" fn find_get_current_sources(
+    p_instance: NDIlib_find_instance_t,
+    p_no_sources: *mut u32,
+) -> *const NDIlib_source_t {
+    let find = &mut *(p_instance as *mut FakeFind);
+    find.announced = true;
+    *p_no_sources = find.sources.len() as u32;
+    find.sources.as_ptr()
+}
+
+unsafe extern "C" fn recv_create_v3(
+    p_create_settings: *const NDIlib_recv_create_v3_t,
+) -> NDIlib_recv_instance_t {
+    let settings = &*p_create_settings;
+    let connected = !settings.source_to_connect_to.p_ndi_name.is_null()
+        || !settings.source_to_connect_to.p_url_address.is_null();
+
+    Box::into_raw(Box::new(FakeRecv {
+        options: Options::from_env(),
+        bandwidth: settings.bandwidth,
+        start: if connected {
+            Some(Instant::now())
+        } else {
+            None
+        },
+        video_frames: 0,
+        audio_samples: 0,
+        random: 0x2545_f491_4f6c_dd1d,
+    })) as NDIlib_recv_instance_t
+}
+
+unsafe extern "C" fn recv_destroy(p_instance: NDIlib_recv_instance_t) {
+    drop(Box::from_raw(p_instance as *mut FakeRecv));
+}
+
+unsafe extern "C" fn recv_connect(
+    p_instance: NDIlib_recv_instance_t,
+    p_src: *const NDIlib_source_t,
+) {
+    let recv = &mut *(p_instance as *mut FakeRecv);
+    recv.video_frames = 0;
+    recv.audio_samples = 0;
+    recv.start = if p_src.is_null() {
+        None
+    } else {
+        Some(Instant::now())
+    };
+}
+
+unsafe extern "C" fn recv_set_tally(
+    _p_instance: NDIlib_recv_instance_t,
+    _p_tally: *const NDIlib_tally_t,
+) -> bool {
+    true
+}
+
+unsafe extern "C" fn recv_send_metadata(
+    _p_instance: NDIlib_recv_instance_t,
+    _p_metadata: *const NDIlib_metadata_frame_t,
+) -> bool {
+    true
+}
+
+unsafe extern "C" fn recv_get_no_connections(p_instance: NDIlib_recv_instance_t) -> c_int {
+    let recv = &*(p_instance as *const FakeRecv);
+    recv.start.is_some() as c_int
+}
+
+unsafe extern "C" fn recv_capture_v3(
+    p_instance: NDIlib_recv_instance_t,
+    p_video_data: *mut NDIlib_video_frame_v2_t,
+    p_audio_data: *mut NDIlib_audio_frame_v3_t,
+    _p_metadata: *mut NDIlib_metadata_frame_t,
+    timeout_in_ms: u32,
+) -> NDIlib_frame_type_e {
+    let recv = &mut *(p_instance as *mut FakeRecv);
+    let deadline = Instant::now() + Duration::from_millis(timeout_in_ms as u64);
+
+    let start = match recv.start {
+        Some(start) if recv.bandwidth != NDIlib_recv_bandwidth_metadata_only => start,
+        _ => {
+            thread::sleep(Duration::from_millis(timeout_in_ms as u64));
+            return NDIlib_frame_type_e::NDIlib_frame_type_none;
+        }
+    };
+
+    let want_video = !p_video_data.is_null() && recv.bandwidth != NDIlib_recv_bandwidth_audio_only;
+    let want_audio = !p_audio_data.is_null();
+
+    loop {
+        if want_video
+            && recv.options.drop > 1
+            && recv.video_frames % recv.options.drop == recv.options.drop - 1
+        {
+            recv.video_frames += 1;
+            continue;
+        }
+
+        let video_due = if want_video {
+            let time = recv.options.frame_time(recv.video_frames);
+            Some(start + recv.options.wall_time(time) + recv.jitter())
+        } else {
+            None
+        };
+        let audio_due = if want_audio {
+            Some(start + recv.options.wall_time(recv.audio_time()))
+        } else {
+            None
+        };
+
+        let (due, is_video) = match (video_due, audio_due) {
+            (Some(video), Some(audio)) if audio < video => (audio, false),
+            (Some(video), _) => (video, true),
+            (None, Some(audio)) => (audio, false),
+            (None, None) => {
+                thread::sleep(Duration::from_millis(timeout_in_ms as u64));
+                return NDIlib_frame_type_e::NDIlib_frame_type_none;
+            }
+        };
+
+        if due > deadline {
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+            return NDIlib_frame_type_e::NDIlib_frame_type_none;
+        }
+
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+
+        return if is_video {
+            recv.fill_video(&mut *p_video_data);
+            recv.video_frames += 1;
+            NDIlib_frame_type_e::NDIlib_frame_type_video
+        } else {
+            recv.fill_audio(&mut *p_audio_data);
+            recv.audio_samples += recv.samples_per_frame();
+            NDIlib_frame_type_e::NDIlib_frame_type_audio
+        };
+    }
+}
+
+unsafe extern "C" fn recv_free_video_v2(
+    _p_instance: NDIlib_recv_instance_t,
+    p_video_data: *mut NDIlib_video_frame_v2_t,
+) {
+    let frame = &mut *p_video_data;
+    if !frame.p_data.is_null() {
+        let size = frame.line_stride_or_data_size_in_bytes as usize * frame.yres as usize;
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            frame.p_data as *mut u8,
+            size,
+        )));
+        frame.p_data = ptr::null();
+    }
+}
+
+unsafe extern "C" fn recv_free_audio_v3(
+    _p_instance: NDIlib_recv_instance_t,
+    p_audio_data: *mut NDIlib_audio_frame_v3_t,
+) {
+    let frame = &mut *p_audio_data;
+    if !frame.p_data.is_null() {
+        let size = frame.no_samples as usize * frame.no_channels as usize;
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            frame.p_data as *mut f32,
+            size,
+        )));
+        frame.p_data = ptr::null();
+    }
+}
+
+unsafe extern "C" fn recv_free_metadata(
+    _p_instance: NDIlib_recv_instance_t,
+    _p_metadata: *mut NDIlib_metadata_frame_t,
+) {
+}
+
+unsafe extern "C" fn recv_get_queue(
+    _p_instance: NDIlib_recv_instance_t,
+    p_total: *mut NDIlib_recv_queue_t,
+) {
+    *p_total = NDIlib_recv_queue_t {
+        video_frames: 0,
+        audio_frames: 0,
+        metadata_frames: 0,
+    };
+}
+
+unsafe extern "C" fn send_create(
+    _p_create_settings: *const NDIlib_send_create_t,
+) -> NDIlib_send_instance_t {
+    Box::into_raw(Box::new(FakeSend)) as NDIlib_send_instance_t
+}
+
+unsafe extern "C" fn send_destroy(p_instance: NDIlib_send_instance_t) {
+    drop(Box::from_raw(p_instance as *mut FakeSend));
+}
+
+unsafe extern "C" fn send_send_video_v2(
+    _p_instance: NDIlib_send_instance_t,
+    _p_video_data: *const NDIlib_video_frame_v2_t,
+) {
+}
+
+unsafe extern "C" fn send_send_audio_v3(
+    _p_instance: NDIlib_send_instance_t,
+    _p_audio_data: *const NDIlib_audio_frame_v3_t,
+) {
+}
+
+unsafe extern "C" fn send_send_metadata(
+    _p_instance: NDIlib_send_instance_t,
+    _p_metadata: *const NDIlib_metadata_frame_t,
+) {
+}
+
+unsafe extern "C" fn send_capture(
+    _p_instance: NDIlib_send_instance_t,
+    _p_metadata: *mut NDIlib_metadata_frame_t,
+    timeout_in_ms: u32,
+) -> NDIlib_frame_type_e {
+    thread::sleep(Duration::from_millis(timeout_in_ms as u64));
+    NDIlib_frame_type_e::NDIlib_frame_type_none
+}
+
+unsafe extern "C" fn send_free_metadata(
+    _p_instance: NDIlib_send_instance_t,
+    _p_metadata: *const NDIlib_metadata_frame_t,
+) {
+}
+
+unsafe extern "C" fn send_get_no_connections(
+    _p_instance: NDIlib_send_instance_t,
+    _timeout_in_ms: u32,
+) -> c_int {
+    0
+}
+
+unsafe extern "C" fn send_clear_connection_metadata(_p_instance: NDIlib_send_instance_t) {}
+
+unsafe extern "C" fn send_add_connection_metadata(
+    _p_instance: NDIlib_send_instance_t,
+    _p_metadata: *const NDIlib_metadata_frame_t,
+) {
+}
+
+unsafe extern "C" fn send_set_failover(
+    _p_instance: NDIlib_send_instance_t,
+    _p_failover_source: *const NDIlib_source_t,
+) {
+}
+
+unsafe extern "C" fn send_get_tally(
+    _p_instance: NDIlib_send_instance_t,
+    p_tally: *mut NDIlib_tally_t,
+    timeout_in_ms: u32,
+) -> bool {
+    thread::sleep(Duration::from_millis(timeout_in_ms as u64));
+    *p_tally = NDIlib_tally_t {
+        on_program: false,
+        on_preview: false,
+    };
+    false
+}
+
+unsafe extern "C" fn routing_create(
+    _p_create_settings: *const NDIlib_routing_create_t,
+) -> NDIlib_routing_instance_t {
+    Box::into_raw(Box::new(FakeRouting)) as NDIlib_routing_instance_t
+}
+
+unsafe extern "C" fn routing_destroy(p_instance: NDIlib_routing_instance_t) {
+    drop(Box::from_raw(p_instance as *mut FakeRouting));
+}
+
+unsafe extern "C" fn routing_change(
+    _p_instance: NDIlib_routing_instance_t,
+    _p_source: *const NDIlib_source_t,
+) -> bool {
+    true
+}
+
+unsafe extern "C" fn routing_clear(_p_instance: NDIlib_routing_instance_t) -> bool {
+    true
+}
+
+// Looks up a fake by its SDK name, functions that are not faked are reported as missing
+pub fn symbol(name: &str) -> *mut c_void {
+    match name {
+        "NDIlib_initialize" => initialize as *mut c_void,
+        "NDIlib_destroy" => destroy as *mut c_void,
+        "NDIlib_version" => version as *mut c_void,
+        "NDIlib_find_create_v2" => find_create_v2 as *mut c_void,
+        "NDIlib_find_destroy" => find_destroy as *mut c_void,
+        "NDIlib_find_wait_for_sources" => find_wait_for_sources as *mut c_void,
+        "NDIlib_find_get_current_sources" => find_get_current_sources as *mut c_void,
+        "NDIlib_recv_create_v3" => recv_create_v3 as *mut c_void,
+        "NDIlib_recv_destroy" => recv_destroy as *mut c_void,
+        "NDIlib_recv_connect" => recv_connect as *mut c_void,
+        "NDIlib_recv_set_tally" => recv_set_tally as *mut c_void,
+        "NDIlib_recv_send_metadata" => recv_send_metadata as *mut c_void,
+        "NDIlib_recv_get_no_connections" => recv_get_no_connections as *mut c_void,
+        "NDIlib_recv_capture_v3" => recv_capture_v3 as *mut c_void,
+        "NDIlib_recv_free_video_v2" => recv_free_video_v2 as *mut c_void,
+        "NDIlib_recv_free_audio_v3" => recv_free_audio_v3 as *mut c_void,
+        "NDIlib_recv_free_metadata" => recv_free_metadata as *mut c_void,
+        "NDIlib_recv_get_queue" => recv_get_queue as *mut c_void,
+        "NDIlib_send_create" => send_create as *mut c_void,
+        "NDIlib_send_destroy" => send_destroy as *mut c_void,
+        "NDIlib_send_send_video_v2" | "NDIlib_send_send_video_async_v2" => {
+            send_send_video_v2 as *mut c_void
+        }
+        "NDIlib_send_send_audio_v3" => send_send_audio_v3 as *mut c_void,
+        "NDIlib_send_send_metadata" => send_send_metadata as *mut c_void,
+        "NDIlib_send_capture" => send_capture as *mut c_void,
+        "NDIlib_send_free_metadata" => send_free_metadata as *mut c_void,
+        "NDIlib_send_get_no_connections" => send_get_no_connections as *mut c_void,
+        "NDIlib_send_clear_connection_metadata" => send_clear_connection_metadata as *mut c_void,
+        "NDIlib_send_add_connection_metadata" => send_add_connection_metadata as *mut c_void,
+        "NDIlib_send_set_failover" => send_set_failover as *mut c_void,
+        "NDIlib_send_get_tally" => send_get_tally as *mut c_void,
+        "NDIlib_routing_create" => routing_create as *mut c_void,
+        "NDIlib_routing_destroy" => routing_destroy as *mut c_void,
+        "NDIlib_routing_change" => routing_change as *mut c_void,
+        "NDIlib_routing_clear" => routing_clear as *mut c_void,
+        _ => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,479 @@
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst::{gst_debug, gst_log};
+use gst_base::prelude::*;
+use gst_base::subclass::base_src::CreateSuccess;
+use gst_base::subclass::prelude::*;
+
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Mutex;
+use std::time;
+
+use once_cell::sync::Lazy;
+
+use crate::ndi::{Frame, RecvInstance};
+use crate::ndisys;
+use crate::DEFAULT_RECEIVER_NDI_NAME;
+
+static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
+    gst::DebugCategory::new(
+        "ndimetasrc",
+        gst::DebugColorFlags::empty(),
+        Some("NewTek NDI Metadata Source"),
+    )
+});
+
+#[derive(Debug, Clone)]
+struct Settings {
+    ndi_name: Option<String>,
+    url_address: Option<String>,
+    receiver_ndi_name: String,
+    post_messages: bool,
+    output_buffers: bool,
+    timeout: u32,
+    library_path: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ndi_name: None,
+            url_address: None,
+            receiver_ndi_name: DEFAULT_RECEIVER_NDI_NAME.clone(),
+            post_messages: false,
+            output_buffers: true,
+            timeout: 0,
+            library_path: None,
+        }
+    }
+}
+
+// Receives only the metadata of a source, e.g. for tally or control monitors watching many
+// sources at once without pulling any audio or video
+pub struct NdiMetaSrc {
+    settings: Mutex<Settings>,
+    recv: Mutex<Option<RecvInstance>>,
+    flushing: AtomicBool,
+    // Time since which no frame was received while a sender was connected, None while no
+    // sender is connected
+    last_frame: Mutex<Option<time::Instant>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for NdiMetaSrc {
+    const NAME: &'static str = "NdiMetaSrc";
+    type Type = super::NdiMetaSrc;
+    type ParentType = gst_base::BaseSrc;
+
+    fn new() -> Self {
+        Self {
+            settings: Mutex::new(Default::default()),
+            recv: Mutex::new(None),
+            flushing: AtomicBool::new(false),
+            last_frame: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for NdiMetaSrc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+            vec![
+                glib::ParamSpecString::new(
+                    "ndi-name",
+                    "NDI Name",
+                    "NDI stream name of the sender",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "url-address",
+                    "URL/Address",
+                    "URL/address and port of the sender, e.g. 127.0.0.1:5961",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "receiver-ndi-name",
+                    "Receiver NDI Name",
+                    "NDI stream name of this receiver",
+                    Some(&*DEFAULT_RECEIVER_NDI_NAME),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "post-messages",
+                    "Post Messages",
+                    "Post an element message for every received metadata frame",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "output-buffers",
+                    "Output Buffers",
+                    "Output a buffer for every received metadata frame, disable to only post messages",
+                    true,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt::new(
+                    "timeout",
+                    "Timeout",
+                    "Push EOS if no frames are received for this many ms while a sender is connected (0 = disabled)",
+                    0,
+                    u32::MAX,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "ndi-library-path",
+                    "NDI Library Path",
+                    "File or directory to load the NDI library from, tried before NDI_LIBRARY_PATH and the default locations. Only has an effect if the library wasn't loaded yet in this process",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn constructed(&self, obj: &Self::Type) {
+        self.parent_constructed(obj);
+
+        obj.set_live(true);
+        obj.set_format(gst::Format::Time);
+    }
+
+    fn set_property(
+        &self,
+        obj: &Self::Type,
+        _id: usize,
+        value: &glib::Value,
+        pspec: &glib::ParamSpec,
+    ) {
+        match pspec.name() {
+            "ndi-name" => {
+                let mut settings = self.settings.lock().unwrap();
+                let ndi_name = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing ndi-name from {:?} to {:?}",
+                    settings.ndi_name,
+                    ndi_name,
+                );
+                settings.ndi_name = ndi_name;
+            }
+            "url-address" => {
+                let mut settings = self.settings.lock().unwrap();
+                let url_address = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing url-address from {:?} to {:?}",
+                    settings.url_address,
+                    url_address,
+                );
+                settings.url_address = url_address;
+            }
+            "receiver-ndi-name" => {
+                let mut settings = self.settings.lock().unwrap();
+                let receiver_ndi_name = value.get::<Option<String>>().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing receiver-ndi-name from {:?} to {:?}",
+                    settings.receiver_ndi_name,
+                    receiver_ndi_name,
+                );
+                settings.receiver_ndi_name =
+                    receiver_ndi_name.unwrap_or_else(|| DEFAULT_RECEIVER_NDI_NAME.clone());
+            }
+            "post-messages" => {
+                let mut settings = self.settings.lock().unwrap();
+                let post_messages = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing post-messages from {} to {}",
+                    settings.post_messages,
+                    post_messages,
+                );
+                settings.post_messages = post_messages;
+            }
+            "output-buffers" => {
+                let mut settings = self.settings.lock().unwrap();
+                let output_buffers = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing output-buffers from {} to {}",
+                    settings.output_buffers,
+                    output_buffers,
+                );
+                settings.output_buffers = output_buffers;
+            }
+            "timeout" => {
+                let mut settings = self.settings.lock().unwrap();
+                let timeout = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing timeout from {} to {}",
+                    settings.timeout,
+                    timeout,
+                );
+                settings.timeout = timeout;
+            }
+            "ndi-library-path" => {
+                let mut settings = self.settings.lock().unwrap();
+                let library_path = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing ndi-library-path from {:?} to {:?}",
+                    settings.library_path,
+                    library_path,
+                );
+                settings.library_path = library_path;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.ndi_name.to_value()
+            }
+            "url-address" => {
+                let settings = self.settings.lock().unwrap();
+                settings.url_address.to_value()
+            }
+            "receiver-ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.receiver_ndi_name.to_value()
+            }
+            "post-messages" => {
+                let settings = self.settings.lock().unwrap();
+                settings.post_messages.to_value()
+            }
+            "output-buffers" => {
+                let settings = self.settings.lock().unwrap();
+                settings.output_buffers.to_value()
+            }
+            "timeout" => {
+                let settings = self.settings.lock().unwrap();
+                settings.timeout.to_value()
+            }
+            "ndi-library-path" => {
+                let settings = self.settings.lock().unwrap();
+                settings.library_path.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for NdiMetaSrc {}
+
+impl ElementImpl for NdiMetaSrc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: Lazy<gst::subclass::ElementMetadata> = Lazy::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "NewTek NDI Metadata Source",
+                "Source/Network",
+                "Receives only the metadata of a NewTek NDI source",
+                "Sebastian Dröge <sebastian@centricular.com>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: Lazy<Vec<gst::PadTemplate>> = Lazy::new(|| {
+            let src_pad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &gst::Caps::builder("application/x-ndi-metadata").build(),
+            )
+            .unwrap();
+
+            vec![src_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl BaseSrcImpl for NdiMetaSrc {
+    fn negotiate(&self, element: &Self::Type) -> Result<(), gst::LoggableError> {
+        element
+            .set_caps(&gst::Caps::builder("application/x-ndi-metadata").build())
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to negotiate caps",))
+    }
+
+    fn unlock(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        gst_debug!(CAT, obj: element, "Unlocking",);
+        self.flushing.store(true, atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn unlock_stop(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        gst_debug!(CAT, obj: element, "Stop unlocking",);
+        self.flushing.store(false, atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn start(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        let library_path = self.settings.lock().unwrap().library_path.clone();
+        crate::ndi::load_from(library_path.as_deref())
+            .map_err(|err| gst::error_msg!(gst::LibraryError::Init, ["{}", err]))?;
+
+        let settings = self.settings.lock().unwrap().clone();
+        if settings.ndi_name.is_none() && settings.url_address.is_none() {
+            return Err(gst::error_msg!(
+                gst::LibraryError::Settings,
+                ["No NDI name or URL/address given"]
+            ));
+        }
+
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Connecting to NDI source with NDI name '{:?}' and URL/Address {:?}",
+            settings.ndi_name,
+            settings.url_address,
+        );
+
+        let recv = RecvInstance::builder(
+            settings.ndi_name.as_deref(),
+            settings.url_address.as_deref(),
+            &settings.receiver_ndi_name,
+        )
+        .bandwidth(ndisys::NDIlib_recv_bandwidth_metadata_only)
+        .build()
+        .ok_or_else(|| {
+            gst::error_msg!(
+                gst::ResourceError::NotFound,
+                ["Failed to connect to source"]
+            )
+        })?;
+
+        *self.recv.lock().unwrap() = Some(recv);
+        *self.last_frame.lock().unwrap() = None;
+
+        Ok(())
+    }
+
+    fn stop(&self, _element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        *self.recv.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn is_seekable(&self, _element: &Self::Type) -> bool {
+        false
+    }
+
+    fn create(
+        &self,
+        element: &Self::Type,
+        _offset: u64,
+        _buffer: Option<&mut gst::BufferRef>,
+        _length: u32,
+    ) -> Result<CreateSuccess, gst::FlowError> {
+        let recv = match *self.recv.lock().unwrap() {
+            Some(ref recv) => recv.clone(),
+            None => return Err(gst::FlowError::Flushing),
+        };
+        let (post_messages, output_buffers, timeout) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                settings.post_messages,
+                settings.output_buffers,
+                settings.timeout,
+            )
+        };
+
+        loop {
+            if self.flushing.load(atomic::Ordering::SeqCst) {
+                return Err(gst::FlowError::Flushing);
+            }
+
+            let res = recv.capture(50, true, true);
+
+            {
+                // Only time while a sender is connected counts, the timer starts once connected
+                let mut last_frame = self.last_frame.lock().unwrap();
+                let now = time::Instant::now();
+                if recv.no_connections() == 0 {
+                    *last_frame = None;
+                } else {
+                    if matches!(res, Ok(Some(Frame::Metadata(_)))) {
+                        *last_frame = Some(now);
+                    }
+
+                    let elapsed = now.saturating_duration_since(*last_frame.get_or_insert(now));
+                    if timeout > 0 && elapsed.as_millis() >= timeout as u128 {
+                        gst_debug!(
+                            CAT,
+                            obj: element,
+                            "No frames received for {}ms -- signalling EOS",
+                            elapsed.as_millis()
+                        );
+                        return Err(gst::FlowError::Eos);
+                    }
+                }
+            }
+
+            let frame = match res {
+                Ok(Some(Frame::Metadata(frame))) => frame,
+                Ok(_) => continue,
+                Err(_) => {
+                    gst::element_error!(
+                        element,
+                        gst::ResourceError::Read,
+                        ["Error receiving frame"]
+                    );
+                    return Err(gst::FlowError::Error);
+                }
+            };
+
+            let metadata = match frame.metadata() {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+
+            gst_log!(
+                CAT,
+                obj: element,
+                "Received metadata at timecode {}: {}",
+                frame.timecode(),
+                metadata,
+            );
+
+            if post_messages {
+                let s = gst::Structure::builder("ndi-metadata")
+                    .field("metadata", metadata)
+                    .field("timecode", frame.timecode())
+                    .build();
+                let _ =
+                    element.post_message(gst::message::Element::builder(s).src(element).build());
+            }
+
+            if !output_buffers {
+                continue;
+            }
+
+            let mut buffer = gst::Buffer::from_mut_slice(metadata.as_bytes().to_vec());
+            buffer
+                .get_mut()
+                .unwrap()
+                .set_pts(element.current_running_time());
+
+            return Ok(CreateSuccess::NewBuffer(buffer));
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use glib::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct NdiMetaSrc(ObjectSubclass<imp::NdiMetaSrc>) @extends gst_base::BaseSrc, gst::Element, gst::Object;
+}
+
+unsafe impl Send for NdiMetaSrc {}
+unsafe impl Sync for NdiMetaSrc {}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "ndimetasrc",
+        gst::Rank::None,
+        NdiMetaSrc::static_type(),
+    )
+}
@@ -0,0 +1,270 @@
+use glib::subclass::prelude::*;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst::{gst_debug, gst_error, gst_info, gst_warning};
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::ndi::RoutingInstance;
+
+static DEFAULT_ROUTER_NDI_NAME: Lazy<String> = Lazy::new(|| {
+    format!(
+        "GStreamer NDI Router {}-{}",
+        env!("CARGO_PKG_VERSION"),
+        env!("COMMIT_ID")
+    )
+});
+
+#[derive(Debug)]
+struct Settings {
+    ndi_name: String,
+    groups: Option<String>,
+    source_ndi_name: Option<String>,
+    source_url_address: Option<String>,
+    library_path: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ndi_name: DEFAULT_ROUTER_NDI_NAME.clone(),
+            groups: None,
+            source_ndi_name: None,
+            source_url_address: None,
+            library_path: None,
+        }
+    }
+}
+
+pub struct NdiRouter {
+    settings: Mutex<Settings>,
+    routing: Mutex<Option<RoutingInstance>>,
+}
+
+static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
+    gst::DebugCategory::new(
+        "ndirouter",
+        gst::DebugColorFlags::empty(),
+        Some("NDI Router"),
+    )
+});
+
+#[glib::object_subclass]
+impl ObjectSubclass for NdiRouter {
+    const NAME: &'static str = "NdiRouter";
+    type Type = super::NdiRouter;
+    type ParentType = gst::Element;
+
+    fn new() -> Self {
+        Self {
+            settings: Mutex::new(Default::default()),
+            routing: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for NdiRouter {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+            vec![
+                glib::ParamSpecString::new(
+                    "ndi-name",
+                    "NDI Name",
+                    "NDI Name under which the routed source is published",
+                    Some(DEFAULT_ROUTER_NDI_NAME.as_ref()),
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_READY,
+                ),
+                glib::ParamSpecString::new(
+                    "groups",
+                    "Groups",
+                    "Comma-separated list of NDI groups to announce the routed source in",
+                    None,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_READY,
+                ),
+                glib::ParamSpecString::new(
+                    "source-ndi-name",
+                    "Source NDI Name",
+                    "NDI source to route to, unset to route to nothing",
+                    None,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_PLAYING,
+                ),
+                glib::ParamSpecString::new(
+                    "source-url-address",
+                    "Source URL/Address",
+                    "URL/address and port of the NDI source to route to",
+                    None,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_PLAYING,
+                ),
+                glib::ParamSpecString::new(
+                    "ndi-library-path",
+                    "NDI Library Path",
+                    "File or directory to load the NDI library from, tried before NDI_LIBRARY_PATH and the default locations. Only has an effect if the library wasn't loaded yet in this process",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(
+        &self,
+        obj: &Self::Type,
+        _id: usize,
+        value: &glib::Value,
+        pspec: &glib::ParamSpec,
+    ) {
+        match pspec.name() {
+            "ndi-name" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.ndi_name = value
+                    .get::<String>()
+                    .unwrap_or_else(|_| DEFAULT_ROUTER_NDI_NAME.clone());
+            }
+            "groups" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.groups = value.get().unwrap();
+            }
+            "source-ndi-name" => {
+                let mut routing = self.routing.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.source_ndi_name = value.get().unwrap();
+
+                if let Some(ref mut routing) = *routing {
+                    self.update_route(obj, routing, &settings);
+                }
+            }
+            "source-url-address" => {
+                let mut routing = self.routing.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.source_url_address = value.get().unwrap();
+
+                if let Some(ref mut routing) = *routing {
+                    self.update_route(obj, routing, &settings);
+                }
+            }
+            "ndi-library-path" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.library_path = value.get().unwrap();
+            }
+            _ => unimplemented!(),
+        };
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.ndi_name.to_value()
+            }
+            "groups" => {
+                let settings = self.settings.lock().unwrap();
+                settings.groups.to_value()
+            }
+            "source-ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.source_ndi_name.to_value()
+            }
+            "source-url-address" => {
+                let settings = self.settings.lock().unwrap();
+                settings.source_url_address.to_value()
+            }
+            "ndi-library-path" => {
+                let settings = self.settings.lock().unwrap();
+                settings.library_path.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for NdiRouter {}
+
+impl ElementImpl for NdiRouter {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: Lazy<gst::subclass::ElementMetadata> = Lazy::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "NDI Router",
+                "Generic",
+                "Publishes an NDI source that can be rerouted to other NDI sources",
+                "Sebastian Dröge <sebastian@centricular.com>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn change_state(
+        &self,
+        element: &Self::Type,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::NullToReady {
+            let library_path = self.settings.lock().unwrap().library_path.clone();
+            if let Err(err) = crate::ndi::load_from(library_path.as_deref()) {
+                gst::element_error!(element, gst::LibraryError::Init, ["{}", err]);
+                return Err(gst::StateChangeError);
+            }
+
+            let mut routing_storage = self.routing.lock().unwrap();
+            let settings = self.settings.lock().unwrap();
+
+            let mut routing = RoutingInstance::new(&settings.ndi_name, settings.groups.as_deref())
+                .ok_or_else(|| {
+                    gst::element_error!(
+                        element,
+                        gst::ResourceError::OpenWrite,
+                        ["Could not create routing instance"]
+                    );
+                    gst::StateChangeError
+                })?;
+
+            self.update_route(element, &mut routing, &settings);
+            *routing_storage = Some(routing);
+            gst_info!(CAT, obj: element, "Created routing instance {}", settings.ndi_name);
+        }
+
+        let res = self.parent_change_state(element, transition)?;
+
+        if transition == gst::StateChange::ReadyToNull {
+            *self.routing.lock().unwrap() = None;
+            gst_info!(CAT, obj: element, "Destroyed routing instance");
+        }
+
+        Ok(res)
+    }
+}
+
+impl NdiRouter {
+    fn update_route(
+        &self,
+        element: &super::NdiRouter,
+        routing: &mut RoutingInstance,
+        settings: &Settings,
+    ) {
+        if settings.source_ndi_name.is_none() && settings.source_url_address.is_none() {
+            gst_debug!(CAT, obj: element, "Clearing route");
+            if !routing.clear() {
+                gst_warning!(CAT, obj: element, "Failed to clear route");
+            }
+            return;
+        }
+
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Routing to {:?} ({:?})",
+            settings.source_ndi_name,
+            settings.source_url_address
+        );
+        if !routing.change(
+            settings.source_ndi_name.as_deref(),
+            settings.source_url_address.as_deref(),
+        ) {
+            gst_error!(CAT, obj: element, "Failed to change route");
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use glib::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct NdiRouter(ObjectSubclass<imp::NdiRouter>) @extends gst::Element, gst::Object;
+}
+
+unsafe impl Send for NdiRouter {}
+unsafe impl Sync for NdiRouter {}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "ndirouter",
+        gst::Rank::None,
+        NdiRouter::static_type(),
+    )
+}
@@ -5,11 +5,16 @@ use gst::{gst_debug, gst_error, gst_info, gst_trace};
 use gst_base::prelude::*;
 use gst_base::subclass::prelude::*;
 
-use std::sync::Mutex;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use once_cell::sync::Lazy;
 
-use crate::ndi::SendInstance;
+use crate::ndi::{FindInstance, SendInstance};
+use crate::NameConflict;
 
 static DEFAULT_SENDER_NDI_NAME: Lazy<String> = Lazy::new(|| {
     format!(
@@ -19,28 +24,208 @@ static DEFAULT_SENDER_NDI_NAME: Lazy<String> = Lazy::new(|| {
     )
 });
 
-#[derive(Debug)]
+const DEFAULT_MULTICAST_PREFIX: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 0);
+const DEFAULT_MULTICAST_NETMASK: Ipv4Addr = Ipv4Addr::new(255, 255, 0, 0);
+
+// How long to look for existing sources with the same name before creating the sender
+const FIND_SOURCES_TIMEOUT_MS: u32 = 500;
+
+// How often the tally, connections and metadata from receivers are polled while the sink is
+// started
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
 struct Settings {
     ndi_name: String,
+    groups: Option<String>,
+    failover_ndi_name: Option<String>,
+    render_when_unconnected: bool,
+    web_control: Option<String>,
+    ptz: bool,
+    connection_metadata: Option<String>,
+    multicast: bool,
+    multicast_prefix: Ipv4Addr,
+    multicast_netmask: Ipv4Addr,
+    multicast_ttl: u32,
+    premultiplied_alpha: bool,
+    name_conflict: NameConflict,
+    enable_video: bool,
+    enable_audio: bool,
+    library_path: Option<String>,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
             ndi_name: DEFAULT_SENDER_NDI_NAME.clone(),
+            groups: None,
+            failover_ndi_name: None,
+            render_when_unconnected: true,
+            web_control: None,
+            ptz: false,
+            connection_metadata: None,
+            multicast: false,
+            multicast_prefix: DEFAULT_MULTICAST_PREFIX,
+            multicast_netmask: DEFAULT_MULTICAST_NETMASK,
+            multicast_ttl: 1,
+            premultiplied_alpha: false,
+            name_conflict: NameConflict::Ignore,
+            enable_video: true,
+            enable_audio: true,
+            library_path: None,
+        }
+    }
+}
+
+// AAC or Opus audio that is passed through without decoding
+#[cfg(feature = "advanced-sdk")]
+#[derive(Debug)]
+struct CompressedAudioInfo {
+    fourcc: crate::ndisys::NDIlib_FourCC_audio_type_e,
+    rate: i32,
+    channels: i32,
+    codec_data: Option<gst::Buffer>,
+}
+
+#[cfg(feature = "advanced-sdk")]
+impl CompressedAudioInfo {
+    fn from_caps(caps: &gst::CapsRef) -> Option<Self> {
+        let s = caps.structure(0)?;
+        let rate = s.get::<i32>("rate").ok()?;
+        let channels = s.get::<i32>("channels").ok()?;
+
+        match s.name() {
+            "audio/mpeg" => Some(CompressedAudioInfo {
+                fourcc: crate::ndisys::NDIlib_FourCC_audio_type_AAC,
+                rate,
+                channels,
+                codec_data: Some(s.get::<gst::Buffer>("codec_data").ok()?),
+            }),
+            "audio/x-opus" => Some(CompressedAudioInfo {
+                fourcc: crate::ndisys::NDIlib_FourCC_audio_type_Opus,
+                rate,
+                channels,
+                codec_data: None,
+            }),
+            _ => None,
         }
     }
+
+    // Number of samples in the buffer, from its duration or otherwise the usual frame size
+    fn samples(&self, buffer: &gst::BufferRef) -> i32 {
+        match buffer.duration() {
+            Some(duration) => duration
+                .nseconds()
+                .mul_div_round(self.rate as u64, gst::ClockTime::SECOND.nseconds())
+                .unwrap_or(0) as i32,
+            None if self.fourcc == crate::ndisys::NDIlib_FourCC_audio_type_AAC => 1024,
+            None => self.rate / 50,
+        }
+    }
+}
+
+// H.264 or HEVC in byte-stream format that is passed through to NDI|HX receivers
+#[cfg(feature = "advanced-sdk")]
+#[derive(Debug)]
+struct CompressedVideoInfo {
+    format: crate::ndi::CompressedVideoFormat,
+    // SPS/PPS (and VPS) of the last key frame, sent as extra data with every key frame
+    parameter_sets: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "advanced-sdk")]
+impl CompressedVideoInfo {
+    fn from_caps(caps: &gst::CapsRef) -> Option<Self> {
+        let s = caps.structure(0)?;
+        let fourcc = match s.name() {
+            "video/x-h264" => crate::ndisys::NDIlib_FourCC_video_type_ex_H264_highest_bandwidth,
+            "video/x-h265" => crate::ndisys::NDIlib_FourCC_video_type_ex_HEVC_highest_bandwidth,
+            _ => return None,
+        };
+
+        Some(CompressedVideoInfo {
+            format: crate::ndi::CompressedVideoFormat {
+                fourcc,
+                width: s.get::<i32>("width").ok()?,
+                height: s.get::<i32>("height").ok()?,
+                fps: s.get::<gst::Fraction>("framerate").ok()?,
+                par: s
+                    .get::<gst::Fraction>("pixel-aspect-ratio")
+                    .unwrap_or_else(|_| gst::Fraction::new(1, 1)),
+            },
+            parameter_sets: None,
+        })
+    }
+
+    fn packet_fourcc(&self) -> crate::ndisys::NDIlib_compressed_FourCC_type_e {
+        if self.format.fourcc == crate::ndisys::NDIlib_FourCC_video_type_ex_H264_highest_bandwidth {
+            crate::ndisys::NDIlib_compressed_FourCC_type_H264
+        } else {
+            crate::ndisys::NDIlib_compressed_FourCC_type_HEVC
+        }
+    }
+
+    // Splits an access unit into its parameter set NAL units and all other NAL units
+    fn split_parameter_sets(&self, data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let is_h264 =
+            self.format.fourcc == crate::ndisys::NDIlib_FourCC_video_type_ex_H264_highest_bandwidth;
+
+        let mut starts = Vec::new();
+        let mut i = 0;
+        while i + 3 <= data.len() {
+            if data[i..i + 3] == [0, 0, 1] {
+                // Include the leading zero of 4 byte start codes
+                starts.push(if i > 0 && data[i - 1] == 0 { i - 1 } else { i });
+                i += 3;
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut parameter_sets = Vec::new();
+        let mut rest = Vec::with_capacity(data.len());
+        if starts.first() != Some(&0) {
+            rest.extend_from_slice(&data[..starts.first().copied().unwrap_or(data.len())]);
+        }
+        for (idx, start) in starts.iter().enumerate() {
+            let end = starts.get(idx + 1).copied().unwrap_or(data.len());
+            let nal = &data[*start..end];
+            let header_pos = if nal[2] == 1 { 3 } else { 4 };
+            let is_parameter_set = match nal.get(header_pos) {
+                Some(header) if is_h264 => matches!(header & 0x1f, 7 | 8),
+                Some(header) => matches!((header >> 1) & 0x3f, 32..=34),
+                None => false,
+            };
+
+            if is_parameter_set {
+                parameter_sets.extend_from_slice(nal);
+            } else {
+                rest.extend_from_slice(nal);
+            }
+        }
+
+        (parameter_sets, rest)
+    }
 }
 
 struct State {
     send: SendInstance,
     video_info: Option<gst_video::VideoInfo>,
     audio_info: Option<gst_audio::AudioInfo>,
+    #[cfg(feature = "advanced-sdk")]
+    compressed_audio_info: Option<CompressedAudioInfo>,
+    #[cfg(feature = "advanced-sdk")]
+    compressed_video_info: Option<CompressedVideoInfo>,
+    on_program: bool,
+    on_preview: bool,
+    connections: i32,
 }
 
 pub struct NdiSink {
     settings: Mutex<Settings>,
     state: Mutex<Option<State>>,
+    // Polls the tally and connections independent of the data flow
+    poll_thread: Mutex<Option<(thread::JoinHandle<()>, Arc<AtomicBool>)>>,
 }
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
@@ -57,6 +242,7 @@ impl ObjectSubclass for NdiSink {
         Self {
             settings: Mutex::new(Default::default()),
             state: Mutex::new(Default::default()),
+            poll_thread: Mutex::new(None),
         }
     }
 }
@@ -64,31 +250,335 @@ impl ObjectSubclass for NdiSink {
 impl ObjectImpl for NdiSink {
     fn properties() -> &'static [glib::ParamSpec] {
         static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
-            vec![glib::ParamSpecString::new(
-                "ndi-name",
-                "NDI Name",
-                "NDI Name to use",
-                Some(DEFAULT_SENDER_NDI_NAME.as_ref()),
-                glib::ParamFlags::READWRITE,
-            )]
+            vec![
+                glib::ParamSpecString::new(
+                    "ndi-name",
+                    "NDI Name",
+                    "NDI Name to use",
+                    Some(DEFAULT_SENDER_NDI_NAME.as_ref()),
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_PLAYING,
+                ),
+                glib::ParamSpecString::new(
+                    "groups",
+                    "Groups",
+                    "Comma-separated list of NDI groups to announce the sender in",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "failover-ndi-name",
+                    "Failover NDI Name",
+                    "NDI source receivers should switch to if this sender goes away",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "web-control",
+                    "Web Control",
+                    "URL of a web page for controlling the sender, advertised to receivers",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "ptz",
+                    "PTZ",
+                    "Advertise PTZ support to receivers, PTZ commands are received as metadata",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "connection-metadata",
+                    "Connection Metadata",
+                    "Additional XML metadata sent to every receiver on connection",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "render-when-unconnected",
+                    "Render When Unconnected",
+                    "Send frames even if no receiver is connected",
+                    true,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecInt::new(
+                    "connections",
+                    "Connections",
+                    "Number of receivers currently connected",
+                    0,
+                    i32::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "on-program",
+                    "On Program",
+                    "Whether a receiver currently has this sender on program",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "on-preview",
+                    "On Preview",
+                    "Whether a receiver currently has this sender on preview",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "multicast",
+                    "Multicast",
+                    "Send the stream via multicast instead of unicast to each receiver (requires NDI SDK 5.5)",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "multicast-prefix",
+                    "Multicast Prefix",
+                    "Network prefix of the multicast address range",
+                    Some("239.255.0.0"),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "multicast-netmask",
+                    "Multicast Netmask",
+                    "Netmask of the multicast address range",
+                    Some("255.255.0.0"),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt::new(
+                    "multicast-ttl",
+                    "Multicast TTL",
+                    "Time-to-live of multicast packets",
+                    1,
+                    255,
+                    1,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "premultiplied-alpha",
+                    "Premultiplied Alpha",
+                    "Whether BGRA/RGBA input has premultiplied alpha, NDI expects straight alpha",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecEnum::new(
+                    "name-conflict",
+                    "Name Conflict",
+                    "What to do if a source with the same NDI name already exists on this machine",
+                    NameConflict::static_type(),
+                    NameConflict::Ignore as i32,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "enable-video",
+                    "Enable Video",
+                    "Send video, can be changed while running",
+                    true,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_PLAYING,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "enable-audio",
+                    "Enable Audio",
+                    "Send audio, can be changed while running",
+                    true,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_PLAYING,
+                ),
+                glib::ParamSpecString::new(
+                    "ndi-library-path",
+                    "NDI Library Path",
+                    "File or directory to load the NDI library from, tried before NDI_LIBRARY_PATH and the default locations. Only has an effect if the library wasn't loaded yet in this process",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+            ]
         });
 
         PROPERTIES.as_ref()
     }
 
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: Lazy<Vec<glib::subclass::Signal>> = Lazy::new(|| {
+            vec![
+                glib::subclass::Signal::builder(
+                    "send-metadata",
+                    &[String::static_type().into()],
+                    bool::static_type().into(),
+                )
+                .action()
+                .class_handler(|_, args| {
+                    let element = args[0].get::<super::NdiSink>().expect("signal arg");
+                    let metadata = args[1].get::<String>().expect("signal arg");
+                    let imp = NdiSink::from_instance(&element);
+
+                    Some(imp.send_metadata(&element, &metadata).to_value())
+                })
+                .build(),
+                glib::subclass::Signal::builder(
+                    "metadata-received",
+                    &[String::static_type().into()],
+                    glib::types::Type::UNIT.into(),
+                )
+                .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
     fn set_property(
         &self,
-        _obj: &Self::Type,
+        obj: &Self::Type,
         _id: usize,
         value: &glib::Value,
         pspec: &glib::ParamSpec,
     ) {
         match pspec.name() {
             "ndi-name" => {
+                let settings = {
+                    let mut settings = self.settings.lock().unwrap();
+                    let ndi_name = value
+                        .get::<String>()
+                        .unwrap_or_else(|_| DEFAULT_SENDER_NDI_NAME.clone());
+                    if ndi_name == settings.ndi_name {
+                        return;
+                    }
+                    settings.ndi_name = ndi_name;
+                    settings.clone()
+                };
+
+                if self.state.lock().unwrap().is_none() {
+                    return;
+                }
+
+                // Renaming while running replaces the sender, receivers have to reconnect to
+                // the new name. The old sender is only dropped once the new one exists. It is
+                // created without holding the state lock as checking for name conflicts can
+                // take a while
+                match self.create_sender(obj, &settings) {
+                    Ok(send) => {
+                        if let Some(ref mut state) = *self.state.lock().unwrap() {
+                            gst_info!(CAT, obj: obj, "Renamed sender to '{}'", settings.ndi_name);
+                            state.send = send;
+                        }
+                    }
+                    Err(err) => {
+                        gst::element_warning!(
+                            obj,
+                            gst::ResourceError::OpenWrite,
+                            ("Failed to rename sender to '{}'", settings.ndi_name),
+                            ["{}", err]
+                        );
+                    }
+                }
+            }
+            "groups" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.groups = value.get().unwrap();
+            }
+            "render-when-unconnected" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.render_when_unconnected = value.get().unwrap();
+            }
+            "failover-ndi-name" => {
+                let mut state_storage = self.state.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.failover_ndi_name = value.get().unwrap();
+
+                if let Some(ref mut state) = *state_storage {
+                    state
+                        .send
+                        .set_failover(settings.failover_ndi_name.as_deref());
+                }
+            }
+            "web-control" => {
+                let mut state_storage = self.state.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.web_control = value.get().unwrap();
+
+                if let Some(ref mut state) = *state_storage {
+                    update_connection_metadata(&mut state.send, &settings);
+                }
+            }
+            "ptz" => {
+                let mut state_storage = self.state.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.ptz = value.get().unwrap();
+
+                if let Some(ref mut state) = *state_storage {
+                    update_connection_metadata(&mut state.send, &settings);
+                }
+            }
+            "connection-metadata" => {
+                let mut state_storage = self.state.lock().unwrap();
+                let mut settings = self.settings.lock().unwrap();
+                settings.connection_metadata = value.get().unwrap();
+
+                if let Some(ref mut state) = *state_storage {
+                    update_connection_metadata(&mut state.send, &settings);
+                }
+            }
+            "multicast" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.multicast = value.get().unwrap();
+            }
+            "multicast-prefix" => {
+                let mut settings = self.settings.lock().unwrap();
+                let multicast_prefix = value.get::<Option<String>>().unwrap();
+                settings.multicast_prefix =
+                    match multicast_prefix.as_deref().map(str::parse::<Ipv4Addr>) {
+                        None => DEFAULT_MULTICAST_PREFIX,
+                        Some(Ok(multicast_prefix)) if multicast_prefix.is_multicast() => {
+                            multicast_prefix
+                        }
+                        Some(Ok(multicast_prefix)) => {
+                            glib::g_warning!(
+                                "ndisink",
+                                "Invalid multicast-prefix: {} is not a multicast address",
+                                multicast_prefix
+                            );
+                            return;
+                        }
+                        Some(Err(err)) => {
+                            glib::g_warning!("ndisink", "Invalid multicast-prefix: {}", err);
+                            return;
+                        }
+                    };
+            }
+            "multicast-netmask" => {
+                let mut settings = self.settings.lock().unwrap();
+                let multicast_netmask = value.get::<Option<String>>().unwrap();
+                settings.multicast_netmask =
+                    match multicast_netmask.as_deref().map(str::parse::<Ipv4Addr>) {
+                        None => DEFAULT_MULTICAST_NETMASK,
+                        Some(Ok(multicast_netmask)) => multicast_netmask,
+                        Some(Err(err)) => {
+                            glib::g_warning!("ndisink", "Invalid multicast-netmask: {}", err);
+                            return;
+                        }
+                    };
+            }
+            "multicast-ttl" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.multicast_ttl = value.get().unwrap();
+            }
+            "premultiplied-alpha" => {
                 let mut settings = self.settings.lock().unwrap();
-                settings.ndi_name = value
-                    .get::<String>()
-                    .unwrap_or_else(|_| DEFAULT_SENDER_NDI_NAME.clone());
+                settings.premultiplied_alpha = value.get().unwrap();
+            }
+            "name-conflict" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.name_conflict = value.get().unwrap();
+            }
+            "enable-video" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.enable_video = value.get().unwrap();
+            }
+            "enable-audio" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.enable_audio = value.get().unwrap();
+            }
+            "ndi-library-path" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.library_path = value.get().unwrap();
             }
             _ => unimplemented!(),
         };
@@ -100,6 +590,82 @@ impl ObjectImpl for NdiSink {
                 let settings = self.settings.lock().unwrap();
                 settings.ndi_name.to_value()
             }
+            "groups" => {
+                let settings = self.settings.lock().unwrap();
+                settings.groups.to_value()
+            }
+            "failover-ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.failover_ndi_name.to_value()
+            }
+            "web-control" => {
+                let settings = self.settings.lock().unwrap();
+                settings.web_control.to_value()
+            }
+            "ptz" => {
+                let settings = self.settings.lock().unwrap();
+                settings.ptz.to_value()
+            }
+            "connection-metadata" => {
+                let settings = self.settings.lock().unwrap();
+                settings.connection_metadata.to_value()
+            }
+            "render-when-unconnected" => {
+                let settings = self.settings.lock().unwrap();
+                settings.render_when_unconnected.to_value()
+            }
+            "multicast" => {
+                let settings = self.settings.lock().unwrap();
+                settings.multicast.to_value()
+            }
+            "multicast-prefix" => {
+                let settings = self.settings.lock().unwrap();
+                settings.multicast_prefix.to_string().to_value()
+            }
+            "multicast-netmask" => {
+                let settings = self.settings.lock().unwrap();
+                settings.multicast_netmask.to_string().to_value()
+            }
+            "multicast-ttl" => {
+                let settings = self.settings.lock().unwrap();
+                settings.multicast_ttl.to_value()
+            }
+            "premultiplied-alpha" => {
+                let settings = self.settings.lock().unwrap();
+                settings.premultiplied_alpha.to_value()
+            }
+            "name-conflict" => {
+                let settings = self.settings.lock().unwrap();
+                settings.name_conflict.to_value()
+            }
+            "connections" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .as_ref()
+                    .map(|state| state.connections)
+                    .unwrap_or(0)
+                    .to_value()
+            }
+            "on-program" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .as_ref()
+                    .map(|state| state.on_program)
+                    .unwrap_or(false)
+                    .to_value()
+            }
+            "on-preview" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .as_ref()
+                    .map(|state| state.on_preview)
+                    .unwrap_or(false)
+                    .to_value()
+            }
+            "ndi-library-path" => {
+                let settings = self.settings.lock().unwrap();
+                settings.library_path.to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -138,6 +704,9 @@ impl ElementImpl for NdiSink {
                                 &gst_video::VideoFormat::Bgrx.to_str(),
                                 &gst_video::VideoFormat::Rgba.to_str(),
                                 &gst_video::VideoFormat::Rgbx.to_str(),
+                                &gst_video::VideoFormat::I42210le.to_str(),
+                                &gst_video::VideoFormat::I42212le.to_str(),
+                                &gst_video::VideoFormat::A42210le.to_str(),
                             ]),
                         )
                         .field("width", &gst::IntRange::<i32>::new(1, std::i32::MAX))
@@ -153,14 +722,74 @@ impl ElementImpl for NdiSink {
                 )
                 .structure(
                     gst::Structure::builder("audio/x-raw")
-                        .field("format", &gst_audio::AUDIO_FORMAT_F32.to_str())
+                        .field(
+                            "format",
+                            gst::List::new([
+                                gst_audio::AUDIO_FORMAT_F32.to_str(),
+                                gst_audio::AUDIO_FORMAT_S16.to_str(),
+                            ]),
+                        )
                         .field("rate", &gst::IntRange::<i32>::new(1, i32::MAX))
                         .field("channels", &gst::IntRange::<i32>::new(1, i32::MAX))
-                        .field("layout", &"interleaved")
+                        .field("layout", gst::List::new(["interleaved", "non-interleaved"]))
                         .build(),
                 )
                 .build();
 
+            // Pre-encoded audio and video is passed through to NDI|HX receivers
+            #[cfg(feature = "advanced-sdk")]
+            let caps = {
+                let mut caps = caps;
+                {
+                    let caps = caps.get_mut().unwrap();
+                    caps.append_structure(
+                        gst::Structure::builder("audio/mpeg")
+                            .field("mpegversion", 4i32)
+                            .field("stream-format", "raw")
+                            .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+                            .field("channels", gst::IntRange::<i32>::new(1, i32::MAX))
+                            .build(),
+                    );
+                    for name in ["video/x-h264", "video/x-h265"] {
+                        caps.append_structure(
+                            gst::Structure::builder(name)
+                                .field("stream-format", "byte-stream")
+                                .field("alignment", "au")
+                                .field("width", gst::IntRange::<i32>::new(1, i32::MAX))
+                                .field("height", gst::IntRange::<i32>::new(1, i32::MAX))
+                                .field(
+                                    "framerate",
+                                    gst::FractionRange::new(
+                                        gst::Fraction::new(1, i32::MAX),
+                                        gst::Fraction::new(i32::MAX, 1),
+                                    ),
+                                )
+                                .build(),
+                        );
+                    }
+                    caps.append_structure(
+                        gst::Structure::builder("audio/x-opus")
+                            .field("channel-mapping-family", 0i32)
+                            .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+                            .field("channels", gst::IntRange::<i32>::new(1, 2))
+                            .build(),
+                    );
+                }
+                caps
+            };
+
+            // Separate fields are sent as NDI field 0/1 frames
+            #[cfg(feature = "interlaced-fields")]
+            let caps = {
+                let mut caps = caps;
+                let mut s = caps.structure(0).unwrap().to_owned();
+                s.set("interlace-mode", "alternate");
+                caps.get_mut()
+                    .unwrap()
+                    .append_structure_full(s, Some(gst::CapsFeatures::new(&["format:Interlaced"])));
+                caps
+            };
+
             let sink_pad_template = gst::PadTemplate::new(
                 "sink",
                 gst::PadDirection::Sink,
@@ -175,32 +804,314 @@ impl ElementImpl for NdiSink {
     }
 }
 
-impl BaseSinkImpl for NdiSink {
-    fn start(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+impl NdiSink {
+    fn send_metadata(&self, element: &super::NdiSink, metadata: &str) -> bool {
         let mut state_storage = self.state.lock().unwrap();
-        let settings = self.settings.lock().unwrap();
+        let state = match &mut *state_storage {
+            None => {
+                gst_error!(CAT, obj: element, "Can't send metadata before starting");
+                return false;
+            }
+            Some(ref mut state) => state,
+        };
+
+        gst_trace!(CAT, obj: element, "Sending metadata {}", metadata);
+        let frame = crate::ndi::MetadataFrame::new(
+            crate::ndisys::NDIlib_send_timecode_synthesize,
+            Some(metadata),
+        );
+        state.send.send_metadata(&frame);
+
+        true
+    }
 
-        let send = SendInstance::builder(&settings.ndi_name)
-            .build()
-            .ok_or_else(|| {
+    fn receive_metadata(&self, element: &super::NdiSink) {
+        loop {
+            let metadata = {
+                let state_storage = self.state.lock().unwrap();
+                let state = match &*state_storage {
+                    None => return,
+                    Some(ref state) => state,
+                };
+
+                let frame = match state.send.capture_metadata(0) {
+                    None => return,
+                    Some(frame) => frame,
+                };
+                let metadata = frame.metadata().map(String::from);
+                metadata
+            };
+
+            if let Some(metadata) = metadata {
+                gst_debug!(CAT, obj: element, "Received metadata {}", metadata);
+                element.emit_by_name::<()>("metadata-received", &[&metadata]);
+            }
+        }
+    }
+
+    fn update_tally(&self, element: &super::NdiSink) {
+        let mut state_storage = self.state.lock().unwrap();
+        let state = match &mut *state_storage {
+            None => return,
+            Some(ref mut state) => state,
+        };
+
+        let tally = match state.send.tally(0) {
+            None => return,
+            Some(tally) => tally,
+        };
+
+        let program_changed = state.on_program != tally.on_program();
+        let preview_changed = state.on_preview != tally.on_preview();
+        if !program_changed && !preview_changed {
+            return;
+        }
+
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Tally changed to program {} preview {}",
+            tally.on_program(),
+            tally.on_preview()
+        );
+        state.on_program = tally.on_program();
+        state.on_preview = tally.on_preview();
+        drop(state_storage);
+
+        if program_changed {
+            element.notify("on-program");
+        }
+        if preview_changed {
+            element.notify("on-preview");
+        }
+
+        let _ = element.post_message(
+            gst::message::Element::builder(
+                gst::Structure::builder("ndi-tally")
+                    .field("on-program", tally.on_program())
+                    .field("on-preview", tally.on_preview())
+                    .build(),
+            )
+            .src(element)
+            .build(),
+        );
+    }
+
+    fn update_connections(&self, element: &super::NdiSink) {
+        let mut state_storage = self.state.lock().unwrap();
+        let state = match &mut *state_storage {
+            None => return,
+            Some(ref mut state) => state,
+        };
+
+        let connections = state.send.no_connections(0).max(0);
+        if connections == state.connections {
+            return;
+        }
+
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Connections changed from {} to {}",
+            state.connections,
+            connections
+        );
+        state.connections = connections;
+        drop(state_storage);
+
+        element.notify("connections");
+    }
+
+    // Returns the NDI timecode in 100ns units for the buffer, preferring upstream timecodes
+    // over the running time of the buffer
+    fn timecode(&self, element: &super::NdiSink, buffer: &gst::BufferRef) -> i64 {
+        if let Some(meta) = buffer.meta::<gst_video::VideoTimeCodeMeta>() {
+            let tc = meta.tc();
+            let daily_jam = tc
+                .latest_daily_jam()
+                .map(|daily_jam| daily_jam.to_unix() * 10_000_000)
+                .unwrap_or(0);
+
+            gst_trace!(CAT, obj: element, "Using timecode {}", tc);
+            return daily_jam + (tc.time_since_daily_jam().nseconds() / 100) as i64;
+        }
+
+        element
+            .segment()
+            .downcast::<gst::ClockTime>()
+            .ok()
+            .and_then(|segment| {
+                segment
+                    .to_running_time(buffer.pts())
+                    .zip(element.base_time())
+            })
+            .and_then(|(running_time, base_time)| running_time.checked_add(base_time))
+            .map(|time| (time.nseconds() / 100) as i64)
+            .unwrap_or(crate::ndisys::NDIlib_send_timecode_synthesize)
+    }
+
+    // Creates the sender with the configured name, resolving name conflicts if requested
+    fn create_sender(
+        &self,
+        element: &super::NdiSink,
+        settings: &Settings,
+    ) -> Result<SendInstance, gst::ErrorMessage> {
+        if settings.multicast && !crate::ndi::send_config_supported() {
+            gst::element_warning!(
+                element,
+                gst::LibraryError::Settings,
+                ["Multicast sending requires NDI SDK 5.5 or newer"]
+            );
+        }
+
+        let config = if settings.multicast {
+            Some(format!(
+                r#"{{"ndi": {{"multicast": {{"send": {{"enable": true, "netprefix": "{}", "netmask": "{}", "ttl": {}}}}}}}}}"#,
+                settings.multicast_prefix, settings.multicast_netmask, settings.multicast_ttl
+            ))
+        } else {
+            None
+        };
+        let existing_sources = if settings.name_conflict == NameConflict::Ignore {
+            Vec::new()
+        } else if !crate::ndi::send_source_name_supported() {
+            gst::element_warning!(
+                element,
+                gst::LibraryError::Settings,
+                ["Checking for NDI name conflicts is not supported by this NDI SDK version"]
+            );
+            Vec::new()
+        } else {
+            existing_source_names(settings.groups.as_deref())
+        };
+
+        let mut ndi_name = settings.ndi_name.clone();
+        let mut send = loop {
+            let builder = SendInstance::builder(&ndi_name)
+                .groups(settings.groups.as_deref())
+                .config(config.as_deref());
+
+            let send = builder.build().ok_or_else(|| {
                 gst::error_msg!(
                     gst::ResourceError::OpenWrite,
                     ["Could not create send instance"]
                 )
             })?;
 
+            // Only the full "MACHINE (NAME)" source name tells if it collides on this machine
+            let source_name = match send.source_name() {
+                Some(source_name) if existing_sources.contains(&source_name) => source_name,
+                _ => break send,
+            };
+            drop(send);
+
+            if settings.name_conflict == NameConflict::Error {
+                return Err(gst::error_msg!(
+                    gst::ResourceError::Busy,
+                    ["An NDI source named '{}' already exists", source_name]
+                ));
+            }
+
+            let suffix = ndi_name[settings.ndi_name.len()..]
+                .trim()
+                .parse::<u32>()
+                .unwrap_or(1);
+            ndi_name = format!("{} {}", settings.ndi_name, suffix + 1);
+            gst_debug!(
+                CAT,
+                obj: element,
+                "NDI source '{}' already exists, trying '{}'",
+                source_name,
+                ndi_name
+            );
+        };
+
+        if ndi_name != settings.ndi_name {
+            gst::element_warning!(
+                element,
+                gst::ResourceError::Busy,
+                (
+                    "NDI name '{}' is already in use, sending as '{}'",
+                    settings.ndi_name,
+                    ndi_name
+                )
+            );
+        }
+
+        if let Some(ref failover_ndi_name) = settings.failover_ndi_name {
+            send.set_failover(Some(failover_ndi_name));
+        }
+        update_connection_metadata(&mut send, settings);
+
+        Ok(send)
+    }
+}
+
+impl BaseSinkImpl for NdiSink {
+    fn start(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        let library_path = self.settings.lock().unwrap().library_path.clone();
+        crate::ndi::load_from(library_path.as_deref())
+            .map_err(|err| gst::error_msg!(gst::LibraryError::Init, ["{}", err]))?;
+
+        let mut state_storage = self.state.lock().unwrap();
+        let settings = self.settings.lock().unwrap();
+
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Using NDI SDK version {}",
+            crate::ndi::version().as_deref().unwrap_or("unknown")
+        );
+
+        let send = self.create_sender(element, &settings)?;
+
         let state = State {
             send,
             video_info: None,
             audio_info: None,
+            #[cfg(feature = "advanced-sdk")]
+            compressed_audio_info: None,
+            #[cfg(feature = "advanced-sdk")]
+            compressed_video_info: None,
+            on_program: false,
+            on_preview: false,
+            connections: 0,
         };
         *state_storage = Some(state);
+        drop(settings);
+        drop(state_storage);
+
+        let is_running = Arc::new(AtomicBool::new(true));
+        let is_running_clone = is_running.clone();
+        let element_weak = element.downgrade();
+        let thread = thread::spawn(move || {
+            while is_running_clone.load(atomic::Ordering::SeqCst) {
+                let element = match element_weak.upgrade() {
+                    None => break,
+                    Some(element) => element,
+                };
+                let imp = NdiSink::from_instance(&element);
+                imp.update_tally(&element);
+                imp.update_connections(&element);
+                imp.receive_metadata(&element);
+                drop(element);
+
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        *self.poll_thread.lock().unwrap() = Some((thread, is_running));
+
         gst_info!(CAT, obj: element, "Started");
 
         Ok(())
     }
 
     fn stop(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        if let Some((thread, is_running)) = self.poll_thread.lock().unwrap().take() {
+            is_running.store(false, atomic::Ordering::SeqCst);
+            let _ = thread.join();
+        }
+
         let mut state_storage = self.state.lock().unwrap();
 
         *state_storage = None;
@@ -217,6 +1128,21 @@ impl BaseSinkImpl for NdiSink {
         Ok(())
     }
 
+    fn event(&self, element: &Self::Type, event: gst::Event) -> bool {
+        // Metadata to be sent in stream order, e.g. from an application or an ndisrc upstream
+        if let gst::EventView::CustomDownstream(ev) = event.view() {
+            if let Some(s) = ev.structure() {
+                if s.name() == "GstNdiMetadata" {
+                    if let Ok(data) = s.get::<&str>("data") {
+                        self.send_metadata(element, data);
+                    }
+                }
+            }
+        }
+
+        self.parent_event(element, event)
+    }
+
     fn set_caps(&self, element: &Self::Type, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
         gst_debug!(CAT, obj: element, "Setting caps {}", caps);
 
@@ -226,6 +1152,12 @@ impl BaseSinkImpl for NdiSink {
             Some(ref mut state) => state,
         };
 
+        #[cfg(feature = "advanced-sdk")]
+        {
+            state.compressed_audio_info = None;
+            state.compressed_video_info = None;
+        }
+
         let s = caps.structure(0).unwrap();
         if s.name() == "video/x-raw" {
             let info = gst_video::VideoInfo::from_caps(caps)
@@ -233,6 +1165,25 @@ impl BaseSinkImpl for NdiSink {
 
             state.video_info = Some(info);
             state.audio_info = None;
+        } else if s.name() != "audio/x-raw" {
+            #[cfg(feature = "advanced-sdk")]
+            {
+                if s.name().starts_with("video/") {
+                    let info = CompressedVideoInfo::from_caps(caps)
+                        .ok_or_else(|| gst::loggable_error!(CAT, "Couldn't parse caps {}", caps))?;
+
+                    state.compressed_video_info = Some(info);
+                } else {
+                    let info = CompressedAudioInfo::from_caps(caps)
+                        .ok_or_else(|| gst::loggable_error!(CAT, "Couldn't parse caps {}", caps))?;
+
+                    state.compressed_audio_info = Some(info);
+                }
+                state.audio_info = None;
+                state.video_info = None;
+            }
+            #[cfg(not(feature = "advanced-sdk"))]
+            return Err(gst::loggable_error!(CAT, "Unsupported caps {}", caps));
         } else {
             let info = gst_audio::AudioInfo::from_caps(caps)
                 .map_err(|_| gst::loggable_error!(CAT, "Couldn't parse caps {}", caps))?;
@@ -244,19 +1195,212 @@ impl BaseSinkImpl for NdiSink {
         Ok(())
     }
 
+    // Offer buffers with the default, contiguous plane layout that can be passed to NDI as-is
+    // so that upstream can render into them directly and no copy is needed before sending
+    fn propose_allocation(
+        &self,
+        element: &Self::Type,
+        mut query: gst::query::Allocation<&mut gst::QueryRef>,
+    ) -> Result<(), gst::LoggableError> {
+        let (caps, need_pool) = query.get_owned();
+        if caps
+            .structure(0)
+            .map(|s| s.name() != "video/x-raw")
+            .unwrap_or(true)
+        {
+            return self.parent_propose_allocation(element, query);
+        }
+
+        let info = gst_video::VideoInfo::from_caps(&caps)
+            .map_err(|_| gst::loggable_error!(CAT, "Couldn't parse caps {}", caps))?;
+        let size = info.size() as u32;
+
+        if need_pool {
+            let pool = gst_video::VideoBufferPool::new();
+            let mut config = pool.config();
+            config.set_params(Some(&caps), size, 2, 0);
+            config.set_allocator(
+                None,
+                Some(&gst::AllocationParams::new(
+                    gst::MemoryFlags::empty(),
+                    15,
+                    0,
+                    0,
+                )),
+            );
+            config.add_option(&gst_video::BUFFER_POOL_OPTION_VIDEO_META);
+            pool.set_config(config)
+                .map_err(|_| gst::loggable_error!(CAT, "Failed to configure buffer pool"))?;
+
+            gst_debug!(
+                CAT,
+                obj: element,
+                "Proposing buffer pool for caps {} with size {}",
+                caps,
+                size
+            );
+            query.add_allocation_pool(Some(&pool), size, 2, 0);
+        } else {
+            query.add_allocation_pool(gst::BufferPool::NONE, size, 2, 0);
+        }
+
+        query.add_allocation_meta::<gst_video::VideoMeta>(None);
+
+        Ok(())
+    }
+
     fn render(
         &self,
         element: &Self::Type,
         buffer: &gst::Buffer,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let (render_when_unconnected, premultiplied_alpha, enable_video, enable_audio) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                settings.render_when_unconnected,
+                settings.premultiplied_alpha,
+                settings.enable_video,
+                settings.enable_audio,
+            )
+        };
+
         let mut state_storage = self.state.lock().unwrap();
         let state = match &mut *state_storage {
             None => return Err(gst::FlowError::Error),
             Some(ref mut state) => state,
         };
 
+        if !render_when_unconnected && state.send.no_connections(0) == 0 {
+            gst_trace!(CAT, obj: element, "No receivers connected, dropping buffer");
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        #[cfg(feature = "advanced-sdk")]
+        if let Some(ref info) = state.compressed_audio_info {
+            if !enable_audio {
+                gst_trace!(CAT, obj: element, "Audio disabled, dropping buffer");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let timecode = self.timecode(element, buffer);
+
+            let codec_data = info
+                .codec_data
+                .as_ref()
+                .map(|codec_data| codec_data.map_readable())
+                .transpose()
+                .map_err(|_| {
+                    gst_error!(CAT, obj: element, "Failed to map codec data");
+                    gst::FlowError::Error
+                })?;
+            let frame = crate::ndi::AudioFrame::try_from_compressed_buffer(
+                info.fourcc,
+                info.rate,
+                info.channels,
+                info.samples(buffer),
+                buffer,
+                codec_data.as_ref().map(|map| map.as_slice()),
+                timecode,
+            )
+            .map_err(|_| {
+                gst_error!(CAT, obj: element, "Unsupported compressed audio frame");
+                gst::FlowError::NotNegotiated
+            })?;
+
+            gst_trace!(
+                CAT,
+                obj: element,
+                "Sending compressed audio buffer {:?} with timecode {} and format {:?}",
+                buffer,
+                if timecode < 0 {
+                    gst::ClockTime::NONE.display()
+                } else {
+                    Some(gst::ClockTime::from_nseconds(timecode as u64 * 100)).display()
+                },
+                info,
+            );
+            state.send.send_audio(&frame);
+
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        #[cfg(feature = "advanced-sdk")]
+        if let Some(ref mut info) = state.compressed_video_info {
+            if !enable_video {
+                gst_trace!(CAT, obj: element, "Video disabled, dropping buffer");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let timecode = self.timecode(element, buffer);
+            let key_frame = !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT);
+
+            let map = buffer.map_readable().map_err(|_| {
+                gst_error!(CAT, obj: element, "Failed to map buffer");
+                gst::FlowError::Error
+            })?;
+
+            let (parameter_sets, data) = if key_frame {
+                let (parameter_sets, data) = info.split_parameter_sets(map.as_slice());
+                if !parameter_sets.is_empty() {
+                    info.parameter_sets = Some(parameter_sets);
+                }
+                (
+                    info.parameter_sets.as_deref(),
+                    std::borrow::Cow::Owned(data),
+                )
+            } else {
+                (None, std::borrow::Cow::Borrowed(map.as_slice()))
+            };
+
+            // Receivers can only start decoding with a key frame and its parameter sets
+            if info.parameter_sets.is_none() {
+                gst_trace!(CAT, obj: element, "Waiting for key frame, dropping buffer");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            // Decoding timestamp relative to the timecode, in 100ns units like the timecode
+            let dts = match (buffer.pts(), buffer.dts()) {
+                (Some(pts), Some(dts)) if dts < pts => {
+                    timecode.saturating_sub(((pts - dts).nseconds() / 100) as i64)
+                }
+                _ => timecode,
+            };
+
+            let packet = crate::ndi::CompressedPacket {
+                fourcc: info.packet_fourcc(),
+                pts: timecode,
+                dts,
+                key_frame,
+                data: &data,
+                extra_data: parameter_sets,
+            };
+
+            gst_trace!(
+                CAT,
+                obj: element,
+                "Sending compressed video buffer {:?} with timecode {} and format {:?}",
+                buffer,
+                if timecode < 0 {
+                    gst::ClockTime::NONE.display()
+                } else {
+                    Some(gst::ClockTime::from_nseconds(timecode as u64 * 100)).display()
+                },
+                info.format,
+            );
+            state
+                .send
+                .send_compressed_video(&info.format, &packet, timecode);
+
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
         if let Some(ref info) = state.video_info {
-            if let Some(audio_meta) = buffer.meta::<crate::ndisinkmeta::NdiSinkAudioMeta>() {
+            let audio_meta = if enable_audio {
+                buffer.meta::<crate::ndisinkmeta::NdiSinkAudioMeta>()
+            } else {
+                None
+            };
+            if let Some(audio_meta) = audio_meta {
                 for (buffer, info, timecode) in audio_meta.buffers() {
                     let frame = crate::ndi::AudioFrame::try_from_buffer(info, buffer, *timecode)
                         .map_err(|_| {
@@ -281,31 +1425,28 @@ impl BaseSinkImpl for NdiSink {
             }
 
             // Skip empty/gap buffers from ndisinkcombiner
-            if buffer.size() != 0 {
-                let timecode = element
-                    .segment()
-                    .downcast::<gst::ClockTime>()
-                    .ok()
-                    .and_then(|segment| {
-                        segment
-                            .to_running_time(buffer.pts())
-                            .zip(element.base_time())
-                    })
-                    .and_then(|(running_time, base_time)| running_time.checked_add(base_time))
-                    .map(|time| (time.nseconds() / 100) as i64)
-                    .unwrap_or(crate::ndisys::NDIlib_send_timecode_synthesize);
-
-                let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, info)
+            if enable_video && buffer.size() != 0 {
+                let timecode = self.timecode(element, buffer);
+
+                let frame = gst_video::VideoFrame::from_buffer_readable(buffer.clone(), info)
                     .map_err(|_| {
                         gst_error!(CAT, obj: element, "Failed to map buffer");
                         gst::FlowError::Error
                     })?;
 
-                let frame = crate::ndi::VideoFrame::try_from_video_frame(&frame, timecode)
-                    .map_err(|_| {
-                        gst_error!(CAT, obj: element, "Unsupported video frame");
-                        gst::FlowError::NotNegotiated
-                    })?;
+                let frame = if premultiplied_alpha
+                    && matches!(
+                        info.format(),
+                        gst_video::VideoFormat::Bgra | gst_video::VideoFormat::Rgba
+                    ) {
+                    gst_trace!(CAT, obj: element, "Converting to straight alpha");
+                    unpremultiply_video_frame(&frame).ok_or_else(|| {
+                        gst_error!(CAT, obj: element, "Failed to convert video frame");
+                        gst::FlowError::Error
+                    })?
+                } else {
+                    frame
+                };
 
                 gst_trace!(
                     CAT,
@@ -319,21 +1460,37 @@ impl BaseSinkImpl for NdiSink {
                     },
                     info
                 );
-                state.send.send_video(&frame);
+
+                // 10 and 12 bit 4:2:2 is sent as 16 bit P216, or PA16 with alpha
+                if let Some((fourcc, data, stride)) = convert_to_p216(&frame) {
+                    state
+                        .send
+                        .send_converted_video_async(&frame, fourcc, data, stride, timecode)
+                        .ok_or_else(|| {
+                            gst_error!(CAT, obj: element, "Unsupported video frame");
+                            gst::FlowError::NotNegotiated
+                        })?;
+                // NDI requires the planes to be in contiguous memory and has no NV21, copy the
+                // frame into the default layout of the caps otherwise
+                } else if let Err(frame) = state.send.send_video_async(frame, timecode) {
+                    gst_trace!(CAT, obj: element, "Copying video frame to contiguous memory");
+                    let frame = copy_video_frame(&frame, info).ok_or_else(|| {
+                        gst_error!(CAT, obj: element, "Failed to copy video frame");
+                        gst::FlowError::Error
+                    })?;
+                    state.send.send_video_async(frame, timecode).map_err(|_| {
+                        gst_error!(CAT, obj: element, "Unsupported video frame");
+                        gst::FlowError::NotNegotiated
+                    })?;
+                }
             }
         } else if let Some(ref info) = state.audio_info {
-            let timecode = element
-                .segment()
-                .downcast::<gst::ClockTime>()
-                .ok()
-                .and_then(|segment| {
-                    segment
-                        .to_running_time(buffer.pts())
-                        .zip(element.base_time())
-                })
-                .and_then(|(running_time, base_time)| running_time.checked_add(base_time))
-                .map(|time| (time.nseconds() / 100) as i64)
-                .unwrap_or(crate::ndisys::NDIlib_send_timecode_synthesize);
+            if !enable_audio {
+                gst_trace!(CAT, obj: element, "Audio disabled, dropping buffer");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let timecode = self.timecode(element, buffer);
 
             let frame =
                 crate::ndi::AudioFrame::try_from_buffer(info, buffer, timecode).map_err(|_| {
@@ -361,3 +1518,235 @@ impl BaseSinkImpl for NdiSink {
         Ok(gst::FlowSuccess::Ok)
     }
 }
+
+// Names of all sources that are currently announced, including those on this machine
+fn existing_source_names(groups: Option<&str>) -> Vec<String> {
+    let mut builder = FindInstance::builder().show_local_sources(true);
+    if let Some(groups) = groups {
+        builder = builder.groups(groups);
+    }
+    let mut find = match builder.build() {
+        Some(find) => find,
+        None => return Vec::new(),
+    };
+
+    // Local sources usually show up right away, so don't delay starting any longer than this
+    find.wait_for_sources(FIND_SOURCES_TIMEOUT_MS);
+
+    find.get_current_sources()
+        .iter()
+        .map(|source| source.ndi_name().to_string())
+        .collect()
+}
+
+// Metadata that receivers get on connection, e.g. to find out about the sender's capabilities
+fn update_connection_metadata(send: &mut SendInstance, settings: &Settings) {
+    send.clear_connection_metadata();
+
+    if settings.web_control.is_some() || settings.ptz {
+        let mut capabilities = String::from("<ndi_capabilities");
+        if let Some(ref web_control) = settings.web_control {
+            capabilities.push_str(&format!(
+                " web_control=\"{}\"",
+                glib::markup_escape_text(web_control)
+            ));
+        }
+        if settings.ptz {
+            capabilities.push_str(" ntk_ptz=\"true\"");
+        }
+        capabilities.push_str("/>");
+
+        send.add_connection_metadata(&crate::ndi::MetadataFrame::new(
+            crate::ndisys::NDIlib_send_timecode_synthesize,
+            Some(&capabilities),
+        ));
+    }
+
+    if let Some(ref metadata) = settings.connection_metadata {
+        send.add_connection_metadata(&crate::ndi::MetadataFrame::new(
+            crate::ndisys::NDIlib_send_timecode_synthesize,
+            Some(metadata),
+        ));
+    }
+}
+
+fn copy_video_frame(
+    frame: &gst_video::VideoFrame<gst_video::video_frame::Readable>,
+    info: &gst_video::VideoInfo,
+) -> Option<gst_video::VideoFrame<gst_video::video_frame::Readable>> {
+    // NV21 is sent as NV12 with the chroma bytes swapped
+    let info = if info.format() == gst_video::VideoFormat::Nv21 {
+        let mut builder = gst_video::VideoInfo::builder(
+            gst_video::VideoFormat::Nv12,
+            info.width(),
+            info.height(),
+        )
+        .fps(info.fps())
+        .par(info.par())
+        .interlace_mode(info.interlace_mode());
+        if info.field_order() != gst_video::VideoFieldOrder::Unknown {
+            builder = builder.field_order(info.field_order());
+        }
+        builder.build().ok()?
+    } else {
+        info.clone()
+    };
+
+    let mut buffer = gst::Buffer::with_size(info.size()).ok()?;
+    frame
+        .buffer()
+        .copy_into(
+            buffer.get_mut().unwrap(),
+            gst::BufferCopyFlags::FLAGS | gst::BufferCopyFlags::TIMESTAMPS,
+            0,
+            None,
+        )
+        .ok()?;
+
+    let mut dest = gst_video::VideoFrame::from_buffer_writable(buffer, &info).ok()?;
+    if frame.format() == gst_video::VideoFormat::Nv21 {
+        for plane in 0..2 {
+            let src_stride = frame.plane_stride()[plane as usize] as usize;
+            let dest_stride = dest.plane_stride()[plane as usize] as usize;
+            let line_bytes = frame.comp_width(0) as usize;
+            let src = frame.plane_data(plane).ok()?;
+            let dest = dest.plane_data_mut(plane).ok()?;
+
+            for (dest, src) in dest.chunks_mut(dest_stride).zip(src.chunks(src_stride)) {
+                let len = line_bytes.min(dest.len()).min(src.len());
+                if plane == 0 {
+                    dest[..len].copy_from_slice(&src[..len]);
+                } else {
+                    for (dest, src) in dest[..len].chunks_exact_mut(2).zip(src.chunks_exact(2)) {
+                        dest[0] = src[1];
+                        dest[1] = src[0];
+                    }
+                }
+            }
+        }
+    } else {
+        frame.copy(&mut dest).ok()?;
+    }
+
+    gst_video::VideoFrame::from_buffer_readable(dest.into_buffer(), &info).ok()
+}
+
+// Converts planar 10 and 12 bit 4:2:2 to 16 bit semi-planar P216, or PA16 with an additional
+// alpha plane, by shifting the samples up to 16 bits. Returns None for all other formats.
+fn convert_to_p216(
+    frame: &gst_video::VideoFrame<gst_video::video_frame::Readable>,
+) -> Option<(
+    crate::ndisys::NDIlib_FourCC_video_type_e,
+    gst::MappedBuffer<gst::buffer::Readable>,
+    i32,
+)> {
+    let fourcc = match frame.format() {
+        gst_video::VideoFormat::I42210le | gst_video::VideoFormat::I42212le => {
+            crate::ndisys::NDIlib_FourCC_video_type_P216
+        }
+        gst_video::VideoFormat::A42210le => crate::ndisys::NDIlib_FourCC_video_type_PA16,
+        _ => return None,
+    };
+
+    let shift = 16 - frame.format_info().depth()[0];
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let chroma_width = width.div_ceil(2);
+    // All planes have the same stride, with one U and V pair for every two luma samples
+    let stride = chroma_width * 4;
+
+    // Luma, chroma and alpha instead of separate U and V planes
+    let mut data = vec![0u8; stride * height * (frame.n_planes() as usize - 1)];
+    let (luma, rest) = data.split_at_mut(stride * height);
+    let (chroma, alpha) = rest.split_at_mut(stride * height);
+
+    let lines = |plane: u32| {
+        let stride = frame.plane_stride()[plane as usize] as usize;
+        frame
+            .plane_data(plane)
+            .unwrap_or_default()
+            .chunks(stride)
+            .take(height)
+    };
+
+    for (plane, dest) in [(0, luma), (3, alpha)] {
+        if plane >= frame.n_planes() {
+            continue;
+        }
+        for (dest, src) in dest.chunks_exact_mut(stride).zip(lines(plane)) {
+            for (dest, src) in dest
+                .chunks_exact_mut(2)
+                .zip(src.chunks_exact(2))
+                .take(width)
+            {
+                let sample = u16::from_le_bytes([src[0], src[1]]) << shift;
+                dest.copy_from_slice(&sample.to_le_bytes());
+            }
+        }
+    }
+
+    for ((dest, u), v) in chroma.chunks_exact_mut(stride).zip(lines(1)).zip(lines(2)) {
+        for ((dest, u), v) in dest
+            .chunks_exact_mut(4)
+            .zip(u.chunks_exact(2))
+            .zip(v.chunks_exact(2))
+            .take(chroma_width)
+        {
+            let u = u16::from_le_bytes([u[0], u[1]]) << shift;
+            let v = u16::from_le_bytes([v[0], v[1]]) << shift;
+            dest[..2].copy_from_slice(&u.to_le_bytes());
+            dest[2..].copy_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    let data = gst::Buffer::from_mut_slice(data)
+        .into_mapped_buffer_readable()
+        .ok()?;
+
+    Some((fourcc, data, stride as i32))
+}
+
+// Divides the color components of BGRA/RGBA pixels by their alpha value
+fn unpremultiply_video_frame(
+    frame: &gst_video::VideoFrame<gst_video::video_frame::Readable>,
+) -> Option<gst_video::VideoFrame<gst_video::video_frame::Readable>> {
+    let info = frame.info();
+
+    let mut buffer = gst::Buffer::with_size(frame.buffer().size()).ok()?;
+    {
+        let buffer = buffer.get_mut().unwrap();
+        frame
+            .buffer()
+            .copy_into(
+                buffer,
+                gst::BufferCopyFlags::FLAGS
+                    | gst::BufferCopyFlags::TIMESTAMPS
+                    | gst::BufferCopyFlags::META,
+                0,
+                None,
+            )
+            .ok()?;
+        buffer
+            .copy_from_slice(0, &frame.buffer().map_readable().ok()?)
+            .ok()?;
+    }
+
+    let mut dest = gst_video::VideoFrame::from_buffer_writable(buffer, info).ok()?;
+    let stride = dest.plane_stride()[0] as usize;
+    let line_bytes = dest.width() as usize * 4;
+    let data = dest.plane_data_mut(0).ok()?;
+    for line in data.chunks_mut(stride) {
+        let len = line_bytes.min(line.len());
+        for pixel in line[..len].chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            if alpha == 0 || alpha == 255 {
+                continue;
+            }
+            for c in &mut pixel[..3] {
+                *c = ((*c as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+
+    gst_video::VideoFrame::from_buffer_readable(dest.into_buffer(), info).ok()
+}
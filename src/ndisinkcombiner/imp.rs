@@ -11,6 +11,8 @@ use once_cell::sync::Lazy;
 use std::mem;
 use std::sync::Mutex;
 
+use crate::{LateStreamMode, StartTimeSelection};
+
 static CAT: once_cell::sync::Lazy<gst::DebugCategory> = once_cell::sync::Lazy::new(|| {
     gst::DebugCategory::new(
         "ndisinkcombiner",
@@ -19,6 +21,23 @@ static CAT: once_cell::sync::Lazy<gst::DebugCategory> = once_cell::sync::Lazy::n
     )
 });
 
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    max_av_skew: u64,
+    late_stream: LateStreamMode,
+    start_time_selection: StartTimeSelection,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            max_av_skew: u64::MAX,
+            late_stream: LateStreamMode::Wait,
+            start_time_selection: StartTimeSelection::First,
+        }
+    }
+}
+
 struct State {
     // Note that this applies to the currently pending buffer on the pad and *not*
     // to the current_video_buffer below!
@@ -26,11 +45,21 @@ struct State {
     audio_info: Option<gst_audio::AudioInfo>,
     current_video_buffer: Option<(gst::Buffer, gst::ClockTime)>,
     current_audio_buffers: Vec<(gst::Buffer, gst_audio::AudioInfo, i64)>,
+    // If the first buffer of each stream was received already
+    video_started: bool,
+    audio_started: bool,
+    // Running time of the first video frame that was not dropped
+    first_video_running_time: Option<gst::ClockTime>,
+    // Running time of the first buffer on any pad, from which the deadline for a late stream is
+    // calculated, and whether that deadline has passed
+    first_running_time: Option<gst::ClockTime>,
+    timed_out: bool,
 }
 
 pub struct NdiSinkCombiner {
     video_pad: gst_base::AggregatorPad,
     audio_pad: Mutex<Option<gst_base::AggregatorPad>>,
+    settings: Mutex<Settings>,
     state: Mutex<Option<State>>,
 }
 
@@ -49,12 +78,113 @@ impl ObjectSubclass for NdiSinkCombiner {
         Self {
             video_pad,
             audio_pad: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
             state: Mutex::new(None),
         }
     }
 }
 
 impl ObjectImpl for NdiSinkCombiner {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+            vec![
+                glib::ParamSpecUInt64::new(
+                    "max-av-skew",
+                    "Max A/V Skew",
+                    "Drop audio that ends more than this many nanoseconds before the video frame it would be sent with (-1 = unlimited)",
+                    0,
+                    u64::MAX,
+                    u64::MAX,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecEnum::new(
+                    "late-stream",
+                    "Late Stream",
+                    "How to handle audio or video starting later than the other stream",
+                    LateStreamMode::static_type(),
+                    LateStreamMode::Wait as i32,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecEnum::new(
+                    "start-time-selection",
+                    "Start Time Selection",
+                    "Which stream the output starts with, earlier buffers of the other stream are dropped",
+                    StartTimeSelection::static_type(),
+                    StartTimeSelection::First as i32,
+                    glib::ParamFlags::READWRITE,
+                ),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(
+        &self,
+        obj: &Self::Type,
+        _id: usize,
+        value: &glib::Value,
+        pspec: &glib::ParamSpec,
+    ) {
+        match pspec.name() {
+            "max-av-skew" => {
+                let mut settings = self.settings.lock().unwrap();
+                let max_av_skew = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing max-av-skew from {} to {}",
+                    settings.max_av_skew,
+                    max_av_skew,
+                );
+                settings.max_av_skew = max_av_skew;
+            }
+            "late-stream" => {
+                let mut settings = self.settings.lock().unwrap();
+                let late_stream = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing late-stream from {:?} to {:?}",
+                    settings.late_stream,
+                    late_stream,
+                );
+                settings.late_stream = late_stream;
+            }
+            "start-time-selection" => {
+                let mut settings = self.settings.lock().unwrap();
+                let start_time_selection = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing start-time-selection from {:?} to {:?}",
+                    settings.start_time_selection,
+                    start_time_selection,
+                );
+                settings.start_time_selection = start_time_selection;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "max-av-skew" => {
+                let settings = self.settings.lock().unwrap();
+                settings.max_av_skew.to_value()
+            }
+            "late-stream" => {
+                let settings = self.settings.lock().unwrap();
+                settings.late_stream.to_value()
+            }
+            "start-time-selection" => {
+                let settings = self.settings.lock().unwrap();
+                settings.start_time_selection.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+
     fn constructed(&self, obj: &Self::Type) {
         obj.add_pad(&self.video_pad).unwrap();
 
@@ -93,6 +223,9 @@ impl ElementImpl for NdiSinkCombiner {
                         &gst_video::VideoFormat::Bgrx.to_str(),
                         &gst_video::VideoFormat::Rgba.to_str(),
                         &gst_video::VideoFormat::Rgbx.to_str(),
+                        &gst_video::VideoFormat::I42210le.to_str(),
+                        &gst_video::VideoFormat::I42212le.to_str(),
+                        &gst_video::VideoFormat::A42210le.to_str(),
                     ]),
                 )
                 .field("width", &gst::IntRange::<i32>::new(1, i32::MAX))
@@ -105,6 +238,17 @@ impl ElementImpl for NdiSinkCombiner {
                     ),
                 )
                 .build();
+
+            #[cfg(feature = "interlaced-fields")]
+            let caps = {
+                let mut caps = caps;
+                let mut s = caps.structure(0).unwrap().to_owned();
+                s.set("interlace-mode", "alternate");
+                caps.get_mut()
+                    .unwrap()
+                    .append_structure_full(s, Some(gst::CapsFeatures::new(&["format:Interlaced"])));
+                caps
+            };
             let src_pad_template = gst::PadTemplate::with_gtype(
                 "src",
                 gst::PadDirection::Src,
@@ -124,10 +268,16 @@ impl ElementImpl for NdiSinkCombiner {
             .unwrap();
 
             let caps = gst::Caps::builder("audio/x-raw")
-                .field("format", &gst_audio::AUDIO_FORMAT_F32.to_str())
+                .field(
+                    "format",
+                    gst::List::new([
+                        gst_audio::AUDIO_FORMAT_F32.to_str(),
+                        gst_audio::AUDIO_FORMAT_S16.to_str(),
+                    ]),
+                )
                 .field("rate", &gst::IntRange::<i32>::new(1, i32::MAX))
                 .field("channels", &gst::IntRange::<i32>::new(1, i32::MAX))
-                .field("layout", &"interleaved")
+                .field("layout", gst::List::new(["interleaved", "non-interleaved"]))
                 .build();
             let audio_sink_pad_template = gst::PadTemplate::with_gtype(
                 "audio",
@@ -195,6 +345,11 @@ impl AggregatorImpl for NdiSinkCombiner {
             video_info: None,
             current_video_buffer: None,
             current_audio_buffers: Vec::new(),
+            video_started: false,
+            audio_started: false,
+            first_video_running_time: None,
+            first_running_time: None,
+            timed_out: false,
         });
 
         gst_debug!(CAT, obj: agg, "Started");
@@ -212,6 +367,18 @@ impl AggregatorImpl for NdiSinkCombiner {
     }
 
     fn next_time(&self, _agg: &Self::Type) -> Option<gst::ClockTime> {
+        // Time out in live pipelines once the latency has passed after the first buffer while a
+        // stream that isn't waited for didn't start yet
+        if self.settings.lock().unwrap().late_stream != LateStreamMode::Wait {
+            let has_audio_pad = self.audio_pad.lock().unwrap().is_some();
+            if let Some(ref state) = *self.state.lock().unwrap() {
+                let started = state.video_started && (state.audio_started || !has_audio_pad);
+                if !started && !state.timed_out {
+                    return state.first_running_time;
+                }
+            }
+        }
+
         // FIXME: What to do here? We don't really know when the next buffer is expected
         gst::ClockTime::NONE
     }
@@ -247,12 +414,16 @@ impl AggregatorImpl for NdiSinkCombiner {
             duration.display(),
         );
 
-        let state_storage = self.state.lock().unwrap();
-        let state = match &*state_storage {
-            Some(ref state) => state,
+        let mut state_storage = self.state.lock().unwrap();
+        let state = match &mut *state_storage {
+            Some(ref mut state) => state,
             None => return None,
         };
 
+        if state.first_running_time.is_none() {
+            state.first_running_time = segment.to_running_time(pts);
+        }
+
         let duration = if duration.is_some() {
             duration
         } else if let Some(ref audio_info) = state.audio_info {
@@ -317,9 +488,26 @@ impl AggregatorImpl for NdiSinkCombiner {
         agg: &Self::Type,
         timeout: bool,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
-        // FIXME: Can't really happen because we always return NONE from get_next_time() but that
-        // should be improved!
-        assert!(!timeout);
+        let settings = *self.settings.lock().unwrap();
+        let (video_started, audio_started, timed_out) = match *self.state.lock().unwrap() {
+            Some(ref mut state) => {
+                // Timeouts only happen while waiting for a late stream, see next_time()
+                if timeout {
+                    gst_debug!(CAT, obj: agg, "Timed out waiting for late stream");
+                    state.timed_out = true;
+                }
+                (state.video_started, state.audio_started, state.timed_out)
+            }
+            None => return Err(gst::FlowError::Flushing),
+        };
+        // Don't wait for a stream that didn't start yet if configured so. In live pipelines
+        // the stream is late once the latency has passed after the first buffer, otherwise
+        // there's nothing to wait for.
+        let deadline_passed = timed_out || agg.latency().is_none();
+        let video_late =
+            !video_started && settings.late_stream != LateStreamMode::Wait && deadline_passed;
+        let audio_late =
+            !audio_started && settings.late_stream != LateStreamMode::Wait && deadline_passed;
 
         // Because peek_buffer() can call into clip() and that would take the state lock again,
         // first try getting buffers from both pads here
@@ -341,13 +529,14 @@ impl AggregatorImpl for NdiSinkCombiner {
 
                 Some((video_buffer, video_segment))
             }
-            None if !self.video_pad.is_eos() => {
+            None if !self.video_pad.is_eos() && !video_late => {
                 gst_trace!(CAT, obj: agg, "Waiting for video buffer");
                 return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
             }
             None => None,
         };
 
+        let skip_audio = audio_late && video_buffer_and_segment.is_some();
         let audio_buffer_segment_and_pad;
         if let Some(audio_pad) = self.audio_pad.lock().unwrap().clone() {
             audio_buffer_segment_and_pad = match audio_pad.peek_buffer() {
@@ -374,7 +563,7 @@ impl AggregatorImpl for NdiSinkCombiner {
 
                     Some((audio_buffer, audio_segment, audio_pad))
                 }
-                None if !audio_pad.is_eos() => {
+                None if !audio_pad.is_eos() && !skip_audio => {
                     gst_trace!(CAT, obj: agg, "Waiting for audio buffer");
                     return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
                 }
@@ -392,11 +581,44 @@ impl AggregatorImpl for NdiSinkCombiner {
 
         let (mut current_video_buffer, current_video_running_time_end, next_video_buffer) =
             if let Some((video_buffer, video_segment)) = video_buffer_and_segment {
-                let video_running_time = video_segment.to_running_time(video_buffer.pts()).unwrap();
+                let video_running_time = match video_segment.to_running_time(video_buffer.pts()) {
+                    Some(running_time) => running_time,
+                    None => {
+                        gst::element_error!(
+                            agg,
+                            gst::StreamError::Format,
+                            ["Video buffer without valid timestamp"]
+                        );
+                        return Err(gst::FlowError::Error);
+                    }
+                };
 
                 match state.current_video_buffer {
+                    None if settings.start_time_selection == StartTimeSelection::Both
+                        && !state.audio_started
+                        && audio_buffer_segment_and_pad
+                            .as_ref()
+                            .and_then(|(buffer, segment, _)| segment.to_running_time(buffer.pts()))
+                            .zip(video_buffer.duration())
+                            .map(|(audio_running_time, duration)| {
+                                video_running_time + duration <= audio_running_time
+                            })
+                            .unwrap_or(false) =>
+                    {
+                        gst_debug!(
+                            CAT,
+                            obj: agg,
+                            "Dropping video buffer {:?} before audio starts",
+                            video_buffer
+                        );
+                        drop(state_storage);
+                        self.video_pad.drop_buffer();
+                        return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
+                    }
                     None => {
                         gst_trace!(CAT, obj: agg, "First video buffer, waiting for second");
+                        state.video_started = true;
+                        state.first_video_running_time = Some(video_running_time);
                         state.current_video_buffer = Some((video_buffer, video_running_time));
                         drop(state_storage);
                         self.video_pad.drop_buffer();
@@ -410,6 +632,24 @@ impl AggregatorImpl for NdiSinkCombiner {
                 }
             } else {
                 match (&state.current_video_buffer, &audio_buffer_segment_and_pad) {
+                    (None, None) if !self.video_pad.is_eos() => {
+                        gst_trace!(CAT, obj: agg, "Waiting for video buffer");
+                        return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
+                    }
+                    (None, Some((_, _, ref audio_pad)))
+                        if !self.video_pad.is_eos()
+                            && (settings.late_stream != LateStreamMode::Pad
+                                || settings.start_time_selection == StartTimeSelection::Video
+                                || state.video_info.is_none()) =>
+                    {
+                        // Without video caps the sink can't send anything yet
+                        gst_trace!(CAT, obj: agg, "Video didn't start yet, dropping audio");
+                        state.audio_started = true;
+                        let audio_pad = audio_pad.clone();
+                        drop(state_storage);
+                        audio_pad.drop_buffer();
+                        return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
+                    }
                     (None, None) => {
                         gst_trace!(
                             CAT,
@@ -419,10 +659,21 @@ impl AggregatorImpl for NdiSinkCombiner {
                         return Err(gst::FlowError::Eos);
                     }
                     (None, Some((ref audio_buffer, ref audio_segment, _))) => {
-                        // Create an empty dummy buffer for attaching the audio. This is going to
-                        // be dropped by the sink later.
+                        // Create a black frame for attaching the audio until the video starts,
+                        // or an empty dummy buffer after video EOS. The latter is going to be
+                        // dropped by the sink later.
                         let audio_running_time =
-                            audio_segment.to_running_time(audio_buffer.pts()).unwrap();
+                            match audio_segment.to_running_time(audio_buffer.pts()) {
+                                Some(running_time) => running_time,
+                                None => {
+                                    gst::element_error!(
+                                        agg,
+                                        gst::StreamError::Format,
+                                        ["Audio buffer without valid timestamp"]
+                                    );
+                                    return Err(gst::FlowError::Error);
+                                }
+                            };
 
                         let video_segment = self.video_pad.segment();
                         let video_segment = match video_segment.downcast::<gst::ClockTime>() {
@@ -444,10 +695,17 @@ impl AggregatorImpl for NdiSinkCombiner {
                             return Err(gst::FlowError::Eos);
                         }
 
-                        let mut buffer = gst::Buffer::new();
+                        let mut buffer = match state.video_info {
+                            Some(ref video_info) if !self.video_pad.is_eos() => {
+                                gst_trace!(CAT, obj: agg, "Video didn't start yet, sending black");
+                                black_frame(video_info)
+                            }
+                            _ => gst::Buffer::new(),
+                        };
                         {
                             let buffer = buffer.get_mut().unwrap();
                             buffer.set_pts(video_pts);
+                            buffer.set_duration(audio_buffer.duration());
                         }
 
                         (buffer, gst::ClockTime::NONE, None)
@@ -473,6 +731,49 @@ impl AggregatorImpl for NdiSinkCombiner {
             let audio_running_time_end = audio_running_time
                 .zip(duration)
                 .and_then(|(running_time, duration)| running_time.checked_add(duration));
+            state.audio_started = true;
+
+            if settings.start_time_selection != StartTimeSelection::First
+                && audio_running_time_end
+                    .zip(state.first_video_running_time)
+                    .map(|(audio, video)| audio <= video)
+                    .unwrap_or(false)
+            {
+                gst_debug!(
+                    CAT,
+                    obj: agg,
+                    "Dropping audio buffer {:?} ending before the first video frame",
+                    audio_buffer,
+                );
+                drop(state_storage);
+                audio_pad.drop_buffer();
+                return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
+            }
+
+            let current_video_running_time = state
+                .current_video_buffer
+                .as_ref()
+                .map(|(_, running_time)| *running_time);
+            if settings.max_av_skew != u64::MAX
+                && audio_running_time_end
+                    .zip(current_video_running_time)
+                    .map(|(audio, video)| {
+                        audio.nseconds().saturating_add(settings.max_av_skew) < video.nseconds()
+                    })
+                    .unwrap_or(false)
+            {
+                gst_debug!(
+                    CAT,
+                    obj: agg,
+                    "Dropping audio buffer {:?} ending at {} for video at {}",
+                    audio_buffer,
+                    audio_running_time_end.display(),
+                    current_video_running_time.display(),
+                );
+                drop(state_storage);
+                audio_pad.drop_buffer();
+                return Err(gst_base::AGGREGATOR_FLOW_NEED_DATA);
+            }
 
             if audio_running_time_end
                 .zip(current_video_running_time_end)
@@ -512,7 +813,39 @@ impl AggregatorImpl for NdiSinkCombiner {
             // far
         }
 
-        let audio_buffers = mem::take(&mut state.current_audio_buffers);
+        let mut audio_buffers = mem::take(&mut state.current_audio_buffers);
+
+        // Fill in silence until the audio starts. Without audio caps yet this uses the format
+        // NDI senders commonly use, the receivers follow the format changes per frame.
+        if audio_buffers.is_empty()
+            && !state.audio_started
+            && settings.late_stream == LateStreamMode::Pad
+            && self.audio_pad.lock().unwrap().is_some()
+        {
+            if let Some((_, running_time)) = state.current_video_buffer.as_ref() {
+                let audio_info = match state.audio_info {
+                    Some(ref audio_info) => audio_info.clone(),
+                    None => gst_audio::AudioInfo::builder(gst_audio::AUDIO_FORMAT_F32, 48_000, 2)
+                        .build()
+                        .unwrap(),
+                };
+                let duration = current_video_running_time_end
+                    .and_then(|end| end.checked_sub(*running_time))
+                    .or_else(|| current_video_buffer.duration());
+                if let Some(silence) = duration.and_then(|duration| silence(&audio_info, duration))
+                {
+                    let timecode = agg
+                        .base_time()
+                        .map(|base_time| {
+                            ((base_time.nseconds() + running_time.nseconds()) / 100) as i64
+                        })
+                        .unwrap_or(crate::ndisys::NDIlib_send_timecode_synthesize);
+
+                    gst_trace!(CAT, obj: agg, "Audio didn't start yet, including silence");
+                    audio_buffers.push((silence, audio_info, timecode));
+                }
+            }
+        }
 
         if !audio_buffers.is_empty() {
             let current_video_buffer = current_video_buffer.make_mut();
@@ -632,3 +965,61 @@ impl AggregatorImpl for NdiSinkCombiner {
         true
     }
 }
+
+// Zeroes are silence for both F32 and S16
+fn silence(info: &gst_audio::AudioInfo, duration: gst::ClockTime) -> Option<gst::Buffer> {
+    let samples = duration
+        .nseconds()
+        .mul_div_floor(info.rate() as u64, gst::ClockTime::SECOND.nseconds())?;
+    if samples == 0 {
+        return None;
+    }
+
+    gst::Buffer::from_mut_slice(vec![0u8; samples as usize * info.bpf() as usize]).into()
+}
+
+// Black in the given video format, for padding until the video starts
+fn black_frame(info: &gst_video::VideoInfo) -> gst::Buffer {
+    use gst_video::VideoFormat;
+
+    let mut data = vec![0u8; info.size()];
+    match info.format() {
+        VideoFormat::Uyvy => {
+            for pixels in data.chunks_exact_mut(4) {
+                pixels.copy_from_slice(&[128, 16, 128, 16]);
+            }
+        }
+        VideoFormat::Bgra | VideoFormat::Bgrx | VideoFormat::Rgba | VideoFormat::Rgbx => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel[3] = 255;
+            }
+        }
+        // 16 bit samples, with the alpha plane after the chroma planes fully opaque
+        VideoFormat::I42210le | VideoFormat::I42212le | VideoFormat::A42210le => {
+            let depth = info.format_info().depth()[0];
+            for plane in 0..info.n_planes() as usize {
+                let end = if plane + 1 < info.n_planes() as usize {
+                    info.offset()[plane + 1]
+                } else {
+                    data.len()
+                };
+                let value: u16 = match plane {
+                    0 => 16 << (depth - 8),
+                    3 => (1 << depth) - 1,
+                    _ => 128 << (depth - 8),
+                };
+                for sample in data[info.offset()[plane]..end].chunks_exact_mut(2) {
+                    sample.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        // Planar formats with the luma plane followed by the chroma plane(s)
+        _ => {
+            let (luma, chroma) = data.split_at_mut(info.offset()[1]);
+            luma.fill(16);
+            chroma.fill(128);
+        }
+    }
+
+    gst::Buffer::from_mut_slice(data)
+}
@@ -1,22 +1,32 @@
+use glib::translate::IntoGlib;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst::{gst_debug, gst_error};
+use gst::{gst_debug, gst_error, gst_warning};
 use gst_base::prelude::*;
 use gst_base::subclass::base_src::CreateSuccess;
 use gst_base::subclass::prelude::*;
 
-use std::sync::Mutex;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
 use std::{i32, u32};
 
 use once_cell::sync::Lazy;
 
+use crate::finder::Finder;
+use crate::ndi::RecvInstance;
 use crate::ndisys;
 
 use crate::ndisrcmeta;
+use crate::AudioSlaveMethod;
 use crate::Buffer;
+use crate::Observations;
+use crate::QueueLeaky;
 use crate::Receiver;
 use crate::ReceiverControlHandle;
 use crate::ReceiverItem;
+use crate::ReceiverSettings;
 use crate::RecvColorFormat;
 use crate::TimestampMode;
 use crate::DEFAULT_RECEIVER_NDI_NAME;
@@ -29,17 +39,50 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
+const TIMING_CONTEXT: &str = "gst.ndi.timing";
+const FINDER_CONTEXT: &str = "gst.ndi.finder";
+
+// Changes of the arrival jitter smaller than this don't cause the latency to be updated
+const JITTER_THRESHOLD: gst::ClockTime = gst::ClockTime::from_mseconds(5);
+
+// Interval over which the bitrate tag is measured, so it doesn't change with every buffer
+const BITRATE_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
 #[derive(Debug, Clone)]
 struct Settings {
     ndi_name: Option<String>,
     url_address: Option<String>,
     connect_timeout: u32,
+    connect_retries: u32,
+    retry_backoff: u32,
     timeout: u32,
+    eos_on_disconnect: bool,
+    disconnect_timeout: u32,
     max_queue_length: u32,
+    leaky: QueueLeaky,
+    skip_to_live: bool,
     receiver_ndi_name: String,
     bandwidth: ndisys::NDIlib_recv_bandwidth_e,
+    preview: bool,
+    receive_audio: bool,
     color_format: RecvColorFormat,
     timestamp_mode: TimestampMode,
+    slave_method: AudioSlaveMethod,
+    variable_framerate: bool,
+    force_framerate: gst::Fraction,
+    force_aspect_ratio: gst::Fraction,
+    pixel_aspect_ratio: gst::Fraction,
+    downmix_to_stereo: bool,
+    align_audio: bool,
+    n_threads: u32,
+    post_messages: bool,
+    level_interval: u64,
+    channel_positions: Option<Vec<gst_audio::AudioChannelPosition>>,
+    reference_level: i32,
+    shared_timing: bool,
+    show_local_sources: bool,
+    extra_ips: Option<String>,
+    library_path: Option<String>,
 }
 
 impl Default for Settings {
@@ -49,11 +92,35 @@ impl Default for Settings {
             url_address: None,
             receiver_ndi_name: DEFAULT_RECEIVER_NDI_NAME.clone(),
             connect_timeout: 10000,
+            connect_retries: 0,
+            retry_backoff: 1000,
             timeout: 5000,
+            eos_on_disconnect: false,
+            disconnect_timeout: 1000,
             max_queue_length: 10,
+            leaky: QueueLeaky::Downstream,
+            skip_to_live: false,
             bandwidth: ndisys::NDIlib_recv_bandwidth_highest,
+            preview: false,
+            receive_audio: true,
             color_format: RecvColorFormat::UyvyBgra,
             timestamp_mode: TimestampMode::ReceiveTimeTimecode,
+            slave_method: AudioSlaveMethod::Skew,
+            variable_framerate: false,
+            force_framerate: gst::Fraction::new(0, 1),
+            force_aspect_ratio: gst::Fraction::new(0, 1),
+            pixel_aspect_ratio: gst::Fraction::new(0, 1),
+            downmix_to_stereo: false,
+            align_audio: false,
+            n_threads: 1,
+            post_messages: false,
+            level_interval: 100_000_000,
+            channel_positions: None,
+            reference_level: 0,
+            shared_timing: false,
+            show_local_sources: true,
+            extra_ips: None,
+            library_path: None,
         }
     }
 }
@@ -64,7 +131,32 @@ struct State {
     audio_info: Option<crate::AudioInfo>,
     audio_caps: Option<gst::Caps>,
     current_latency: Option<gst::ClockTime>,
+    // Arrival jitter last included in the latency
+    jitter: gst::ClockTime,
+    tags: Option<gst::TagList>,
     receiver: Option<Receiver>,
+    // Color format currently received with color-format=auto
+    auto_color_format: Option<RecvColorFormat>,
+    recording: bool,
+    level: Level,
+    bitrate: Bitrate,
+}
+
+// Received bytes at the start of the current measurement and the last measured bitrate
+#[derive(Debug, Default)]
+struct Bitrate {
+    start: Option<(time::Instant, u64)>,
+    bitrate: Option<u32>,
+}
+
+// Audio levels accumulated over one level interval
+#[derive(Debug, Default)]
+struct Level {
+    timestamp: Option<gst::ClockTime>,
+    duration: gst::ClockTime,
+    sum_squares: Vec<f64>,
+    peak: Vec<f64>,
+    samples: u64,
 }
 
 impl Default for State {
@@ -75,7 +167,13 @@ impl Default for State {
             audio_info: None,
             audio_caps: None,
             current_latency: gst::ClockTime::NONE,
+            jitter: gst::ClockTime::ZERO,
+            tags: None,
             receiver: None,
+            auto_color_format: None,
+            recording: false,
+            level: Level::default(),
+            bitrate: Bitrate::default(),
         }
     }
 }
@@ -84,6 +182,10 @@ pub struct NdiSrc {
     settings: Mutex<Settings>,
     state: Mutex<State>,
     receiver_controller: Mutex<Option<ReceiverControlHandle>>,
+    observations: Mutex<Option<Observations>>,
+    finder: Mutex<Option<Finder>>,
+    // Keeps the thread watching for the source on the network running while set
+    source_watch: Mutex<Option<Arc<AtomicBool>>>,
 }
 
 #[glib::object_subclass]
@@ -97,6 +199,9 @@ impl ObjectSubclass for NdiSrc {
             settings: Mutex::new(Default::default()),
             state: Mutex::new(Default::default()),
             receiver_controller: Mutex::new(None),
+            observations: Mutex::new(None),
+            finder: Mutex::new(None),
+            source_watch: Mutex::new(None),
         }
     }
 }
@@ -115,7 +220,7 @@ impl ObjectImpl for NdiSrc {
                 glib::ParamSpecString::new(
                     "url-address",
                     "URL/Address",
-                    "URL/address and port of the sender, e.g. 127.0.0.1:5961",
+                    "URL/address and port of the sender, e.g. 127.0.0.1:5961 or [fe80::1]:5961, or a comma separated list of candidates to try in order",
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
@@ -135,6 +240,24 @@ impl ObjectImpl for NdiSrc {
                     10000,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecUInt::new(
+                    "connect-retries",
+                    "Connect Retries",
+                    "Number of times to retry connecting if no frames arrive within the connect timeout",
+                    0,
+                    u32::MAX,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt::new(
+                    "retry-backoff",
+                    "Retry Backoff",
+                    "Time in ms to wait before the first connection retry, doubled for every further retry",
+                    0,
+                    u32::MAX,
+                    1000,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecUInt::new(
                     "timeout",
                     "Timeout",
@@ -144,6 +267,22 @@ impl ObjectImpl for NdiSrc {
                     5000,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecBoolean::new(
+                    "eos-on-disconnect",
+                    "EOS On Disconnect",
+                    "Push EOS instead of posting an error when the sender disconnects or times out",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt::new(
+                    "disconnect-timeout",
+                    "Disconnect Timeout",
+                    "Time in ms the sender has to stay disconnected before EOS is pushed with eos-on-disconnect",
+                    0,
+                    u32::MAX,
+                    1000,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecUInt::new(
                     "max-queue-length",
                     "Max Queue Length",
@@ -153,6 +292,21 @@ impl ObjectImpl for NdiSrc {
                     10,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecEnum::new(
+                    "leaky",
+                    "Leaky",
+                    "Which frames to drop when the receive queue is full",
+                    QueueLeaky::static_type(),
+                    QueueLeaky::Downstream as i32,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "skip-to-live",
+                    "Skip To Live",
+                    "Keep the most recent video frame received while paused and start with it when going to PLAYING",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecInt::new(
                     "bandwidth",
                     "Bandwidth",
@@ -162,6 +316,20 @@ impl ObjectImpl for NdiSrc {
                     100,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecBoolean::new(
+                    "preview",
+                    "Preview",
+                    "Receive the low-bandwidth proxy stream of the sender instead of the full resolution, overrides bandwidth",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "receive-audio",
+                    "Receive Audio",
+                    "Receive audio frames from the sender, if disabled they are discarded by the NDI SDK",
+                    true,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecEnum::new(
                     "color-format",
                     "Color Format",
@@ -178,12 +346,405 @@ impl ObjectImpl for NdiSrc {
                     TimestampMode::ReceiveTimeTimecode as i32,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecEnum::new(
+                    "slave-method",
+                    "Slave Method",
+                    "Algorithm used to compensate drift between the sender's clock and the pipeline clock for audio",
+                    AudioSlaveMethod::static_type(),
+                    AudioSlaveMethod::Skew as i32,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "variable-framerate",
+                    "Variable Framerate",
+                    "Signal a variable framerate and take buffer durations from the timestamps, for senders emitting frames irregularly",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                gst::ParamSpecFraction::new(
+                    "force-framerate",
+                    "Force Framerate",
+                    "Output video at this framerate by dropping and repeating frames (0/1 = sender's framerate)",
+                    gst::Fraction::new(0, 1),
+                    gst::Fraction::new(i32::MAX, 1),
+                    gst::Fraction::new(0, 1),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "post-messages",
+                    "Post Messages",
+                    "Post level messages with the RMS and peak of every audio channel",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt64::new(
+                    "level-interval",
+                    "Level Interval",
+                    "Interval of time between level messages in nanoseconds",
+                    1,
+                    u64::MAX,
+                    100_000_000,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "downmix-to-stereo",
+                    "Downmix To Stereo",
+                    "Fold audio with more than two channels down to stereo",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "align-audio",
+                    "Align Audio",
+                    "Re-chunk audio so that buffer boundaries coincide with video frame boundaries",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt::new(
+                    "n-threads",
+                    "Number of Threads",
+                    "Maximum number of threads used for copying video frames (0 = number of CPUs)",
+                    0,
+                    u32::MAX,
+                    1,
+                    glib::ParamFlags::READWRITE,
+                ),
+                gst::ParamSpecFraction::new(
+                    "force-aspect-ratio",
+                    "Force Aspect Ratio",
+                    "Picture aspect ratio overriding the one signalled by the sender, e.g. 16/9 (0/1 = sender's aspect ratio)",
+                    gst::Fraction::new(0, 1),
+                    gst::Fraction::new(i32::MAX, 1),
+                    gst::Fraction::new(0, 1),
+                    glib::ParamFlags::READWRITE,
+                ),
+                gst::ParamSpecFraction::new(
+                    "pixel-aspect-ratio",
+                    "Pixel Aspect Ratio",
+                    "Pixel aspect ratio overriding the one derived from the picture aspect ratio (0/1 = automatic)",
+                    gst::Fraction::new(0, 1),
+                    gst::Fraction::new(i32::MAX, 1),
+                    gst::Fraction::new(0, 1),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt64::new(
+                    "channel-mask",
+                    "Channel Mask",
+                    "Channel mask of the audio stream, overriding the layout signalled by the sender (0 = automatic)",
+                    0,
+                    u64::MAX,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecValueArray::new(
+                    "channel-positions",
+                    "Channel Positions",
+                    "Channel positions of the audio stream in the order sent, overriding the layout signalled by the sender",
+                    &glib::ParamSpecEnum::new(
+                        "channel-position",
+                        "Channel Position",
+                        "Channel position",
+                        gst_audio::AudioChannelPosition::static_type(),
+                        gst_audio::AudioChannelPosition::None.into_glib(),
+                        glib::ParamFlags::READWRITE,
+                    ),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecInt::new(
+                    "reference-level",
+                    "Reference Level",
+                    "Audio reference level in dB, i.e. the headroom above +4 dBU that maps to full scale (e.g. 20 for SMPTE)",
+                    -60,
+                    60,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "shared-timing",
+                    "Shared Timing",
+                    "Share the mapping of sender to local times with all other NDI sources in the pipeline, \
+                     for senders locked to a common time reference",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "show-local-sources",
+                    "Show Local Sources",
+                    "Include senders running on this machine when looking for the source on the network",
+                    true,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "extra-ips",
+                    "Extra IPs",
+                    "Comma separated list of additional IPv4 or IPv6 addresses to look for the source at",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoxed::new(
+                    "source-info",
+                    "Source Info",
+                    "Name, URL/address, resolution, framerate, sample rate and channels of the current source",
+                    gst::Structure::static_type(),
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoxed::new(
+                    "stats",
+                    "Statistics",
+                    "Number of video frames and audio buffers pushed, frames dropped in total and because of queue overruns, bytes received and reconnects",
+                    gst::Structure::static_type(),
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "connected",
+                    "Connected",
+                    "Whether a sender is currently connected",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "web-control",
+                    "Web Control",
+                    "URL of the web control page the sender announces",
+                    None,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "ptz-supported",
+                    "PTZ Supported",
+                    "Whether the sender announces that it can be PTZ controlled",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "recording-supported",
+                    "Recording Supported",
+                    "Whether the sender announces that it can record",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "recording",
+                    "Recording",
+                    "Whether the NDI SDK is currently recording the received stream",
+                    false,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "recording-filename",
+                    "Recording Filename",
+                    "Filename of the current recording",
+                    None,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "recording-error",
+                    "Recording Error",
+                    "Last error reported by the recorder",
+                    None,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "video-format",
+                    "Video Format",
+                    "Raw video format or codec of the received video",
+                    None,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecInt::new(
+                    "width",
+                    "Width",
+                    "Width of the received video",
+                    0,
+                    i32::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecInt::new(
+                    "height",
+                    "Height",
+                    "Height of the received video",
+                    0,
+                    i32::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
+                gst::ParamSpecFraction::new(
+                    "framerate",
+                    "Framerate",
+                    "Framerate of the received video",
+                    gst::Fraction::new(0, 1),
+                    gst::Fraction::new(i32::MAX, 1),
+                    gst::Fraction::new(0, 1),
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecInt::new(
+                    "sample-rate",
+                    "Sample Rate",
+                    "Sample rate of the received audio",
+                    0,
+                    i32::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecInt::new(
+                    "channels",
+                    "Channels",
+                    "Number of channels of the received audio",
+                    0,
+                    i32::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "ndi-library-path",
+                    "NDI Library Path",
+                    "File or directory to load the NDI library from, tried before NDI_LIBRARY_PATH and the default locations. Only has an effect if the library wasn't loaded yet in this process",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
             ]
         });
 
         PROPERTIES.as_ref()
     }
 
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: Lazy<Vec<glib::subclass::Signal>> = Lazy::new(|| {
+            vec![
+                glib::subclass::Signal::builder(
+                    "source-found",
+                    &[String::static_type().into(), String::static_type().into()],
+                    glib::Type::UNIT.into(),
+                )
+                .build(),
+                glib::subclass::Signal::builder(
+                    "source-lost",
+                    &[String::static_type().into(), String::static_type().into()],
+                    glib::Type::UNIT.into(),
+                )
+                .build(),
+                // Emitted from the capture thread with the number of frames dropped so far
+                // because the queue was full
+                glib::subclass::Signal::builder(
+                    "overrun",
+                    &[u64::static_type().into()],
+                    glib::Type::UNIT.into(),
+                )
+                .build(),
+                glib::subclass::Signal::builder("reset-epoch", &[], glib::Type::UNIT.into())
+                    .action()
+                    .class_handler(|_, args| {
+                        let element = args[0].get::<super::NdiSrc>().expect("signal arg");
+                        let imp = NdiSrc::from_instance(&element);
+
+                        gst_debug!(CAT, obj: &element, "Resetting timestamp epoch");
+                        if let Some(ref controller) = *imp.receiver_controller.lock().unwrap() {
+                            controller.reset_epoch();
+                        }
+
+                        None
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(
+                    "start-recording",
+                    &[String::static_type().into()],
+                    bool::static_type().into(),
+                )
+                .action()
+                .class_handler(|_, args| {
+                    let element = args[0].get::<super::NdiSrc>().expect("signal arg");
+                    let filename_hint = args[1].get::<Option<String>>().expect("signal arg");
+                    let imp = NdiSrc::from_instance(&element);
+
+                    let res = imp
+                        .recv()
+                        .map(|recv| {
+                            if !ndisys::available::NDIlib_recv_recording_start() {
+                                gst_warning!(
+                                    CAT,
+                                    obj: &element,
+                                    "Recording is not supported by the loaded NDI SDK"
+                                );
+                                return false;
+                            }
+                            recv.recording_start(filename_hint.as_deref())
+                        })
+                        .unwrap_or(false);
+                    imp.update_recording(&element);
+
+                    Some(res.to_value())
+                })
+                .build(),
+                glib::subclass::Signal::builder("stop-recording", &[], bool::static_type().into())
+                    .action()
+                    .class_handler(|_, args| {
+                        let element = args[0].get::<super::NdiSrc>().expect("signal arg");
+                        let imp = NdiSrc::from_instance(&element);
+
+                        let res = imp
+                            .recv()
+                            .map(|recv| recv.recording_stop())
+                            .unwrap_or(false);
+                        imp.update_recording(&element);
+
+                        Some(res.to_value())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(
+                    "set-recording-audio-level",
+                    &[f32::static_type().into()],
+                    bool::static_type().into(),
+                )
+                .action()
+                .class_handler(|_, args| {
+                    let element = args[0].get::<super::NdiSrc>().expect("signal arg");
+                    let level = args[1].get::<f32>().expect("signal arg");
+                    let imp = NdiSrc::from_instance(&element);
+
+                    let res = imp
+                        .recv()
+                        .map(|recv| recv.recording_set_audio_level(level))
+                        .unwrap_or(false);
+
+                    Some(res.to_value())
+                })
+                .build(),
+                glib::subclass::Signal::builder(
+                    "get-recording-times",
+                    &[],
+                    gst::Structure::static_type().into(),
+                )
+                .action()
+                .class_handler(|_, args| {
+                    let element = args[0].get::<super::NdiSrc>().expect("signal arg");
+                    let imp = NdiSrc::from_instance(&element);
+
+                    // Times are NDI timecodes in 100ns units
+                    let times = imp.recv().and_then(|recv| recv.recording_times());
+                    let s = times.map(|times| {
+                        gst::Structure::builder("ndi-recording-times")
+                            .field("frames", times.no_frames())
+                            .field(
+                                "start-time",
+                                gst::ClockTime::from_nseconds(times.start_time() as u64 * 100),
+                            )
+                            .field(
+                                "last-time",
+                                gst::ClockTime::from_nseconds(times.last_time() as u64 * 100),
+                            )
+                            .build()
+                    });
+
+                    Some(s.to_value())
+                })
+                .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
     fn constructed(&self, obj: &Self::Type) {
         self.parent_constructed(obj);
 
@@ -191,6 +752,37 @@ impl ObjectImpl for NdiSrc {
         // we'd like to operate in Time format
         obj.set_live(true);
         obj.set_format(gst::Format::Time);
+
+        // Replace the random stream id of the base class with one derived from the NDI source
+        // so that it stays the same across restarts and reconnects
+        let element_weak = obj.downgrade();
+        obj.static_pad("src").unwrap().add_probe(
+            gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_pad, info| {
+                let element = match element_weak.upgrade() {
+                    Some(element) => element,
+                    None => return gst::PadProbeReturn::Ok,
+                };
+
+                if let Some(gst::PadProbeData::Event(ref mut event)) = info.data {
+                    if let gst::EventView::StreamStart(stream_start) = event.view() {
+                        let imp = NdiSrc::from_instance(&element);
+                        let stream_id = imp.stream_id();
+                        gst_debug!(CAT, obj: &element, "Using stream id {}", stream_id);
+
+                        let mut builder = gst::event::StreamStart::builder(&stream_id)
+                            .flags(stream_start.stream_flags())
+                            .seqnum(event.seqnum());
+                        if let Some(group_id) = stream_start.group_id() {
+                            builder = builder.group_id(group_id);
+                        }
+                        *event = builder.build();
+                    }
+                }
+
+                gst::PadProbeReturn::Ok
+            },
+        );
     }
 
     fn set_property(
@@ -203,7 +795,11 @@ impl ObjectImpl for NdiSrc {
         match pspec.name() {
             "ndi-name" => {
                 let mut settings = self.settings.lock().unwrap();
-                let ndi_name = value.get().unwrap();
+                let ndi_name = value.get::<Option<String>>().unwrap();
+                if let Some(Err(err)) = ndi_name.as_deref().map(crate::ndi::validate_ndi_name) {
+                    glib::g_warning!("ndisrc", "Invalid ndi-name: {}", err);
+                    return;
+                }
                 gst_debug!(
                     CAT,
                     obj: obj,
@@ -215,7 +811,14 @@ impl ObjectImpl for NdiSrc {
             }
             "url-address" => {
                 let mut settings = self.settings.lock().unwrap();
-                let url_address = value.get().unwrap();
+                let url_address = value.get::<Option<String>>().unwrap();
+                if let Some(Err(err)) = url_address
+                    .as_deref()
+                    .map(crate::ndi::validate_address_list)
+                {
+                    glib::g_warning!("ndisrc", "Invalid url-address: {}", err);
+                    return;
+                }
                 gst_debug!(
                     CAT,
                     obj: obj,
@@ -250,6 +853,30 @@ impl ObjectImpl for NdiSrc {
                 );
                 settings.connect_timeout = connect_timeout;
             }
+            "connect-retries" => {
+                let mut settings = self.settings.lock().unwrap();
+                let connect_retries = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing connect-retries from {} to {}",
+                    settings.connect_retries,
+                    connect_retries,
+                );
+                settings.connect_retries = connect_retries;
+            }
+            "retry-backoff" => {
+                let mut settings = self.settings.lock().unwrap();
+                let retry_backoff = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing retry-backoff from {} to {}",
+                    settings.retry_backoff,
+                    retry_backoff,
+                );
+                settings.retry_backoff = retry_backoff;
+            }
             "timeout" => {
                 let mut settings = self.settings.lock().unwrap();
                 let timeout = value.get().unwrap();
@@ -262,6 +889,30 @@ impl ObjectImpl for NdiSrc {
                 );
                 settings.timeout = timeout;
             }
+            "eos-on-disconnect" => {
+                let mut settings = self.settings.lock().unwrap();
+                let eos_on_disconnect = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing eos-on-disconnect from {} to {}",
+                    settings.eos_on_disconnect,
+                    eos_on_disconnect,
+                );
+                settings.eos_on_disconnect = eos_on_disconnect;
+            }
+            "disconnect-timeout" => {
+                let mut settings = self.settings.lock().unwrap();
+                let disconnect_timeout = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing disconnect-timeout from {} to {}",
+                    settings.disconnect_timeout,
+                    disconnect_timeout,
+                );
+                settings.disconnect_timeout = disconnect_timeout;
+            }
             "max-queue-length" => {
                 let mut settings = self.settings.lock().unwrap();
                 let max_queue_length = value.get().unwrap();
@@ -274,6 +925,30 @@ impl ObjectImpl for NdiSrc {
                 );
                 settings.max_queue_length = max_queue_length;
             }
+            "leaky" => {
+                let mut settings = self.settings.lock().unwrap();
+                let leaky = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing leaky from {:?} to {:?}",
+                    settings.leaky,
+                    leaky,
+                );
+                settings.leaky = leaky;
+            }
+            "skip-to-live" => {
+                let mut settings = self.settings.lock().unwrap();
+                let skip_to_live = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing skip-to-live from {} to {}",
+                    settings.skip_to_live,
+                    skip_to_live,
+                );
+                settings.skip_to_live = skip_to_live;
+            }
             "bandwidth" => {
                 let mut settings = self.settings.lock().unwrap();
                 let bandwidth = value.get().unwrap();
@@ -286,6 +961,30 @@ impl ObjectImpl for NdiSrc {
                 );
                 settings.bandwidth = bandwidth;
             }
+            "preview" => {
+                let mut settings = self.settings.lock().unwrap();
+                let preview = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing preview from {} to {}",
+                    settings.preview,
+                    preview,
+                );
+                settings.preview = preview;
+            }
+            "receive-audio" => {
+                let mut settings = self.settings.lock().unwrap();
+                let receive_audio = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing receive-audio from {} to {}",
+                    settings.receive_audio,
+                    receive_audio,
+                );
+                settings.receive_audio = receive_audio;
+            }
             "color-format" => {
                 let mut settings = self.settings.lock().unwrap();
                 let color_format = value.get().unwrap();
@@ -313,48 +1012,523 @@ impl ObjectImpl for NdiSrc {
                 }
                 settings.timestamp_mode = timestamp_mode;
             }
-            _ => unimplemented!(),
-        }
-    }
-
-    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
-        match pspec.name() {
-            "ndi-name" => {
-                let settings = self.settings.lock().unwrap();
-                settings.ndi_name.to_value()
+            "slave-method" => {
+                let mut settings = self.settings.lock().unwrap();
+                let slave_method = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing slave method from {:?} to {:?}",
+                    settings.slave_method,
+                    slave_method
+                );
+                settings.slave_method = slave_method;
             }
-            "url-address" => {
-                let settings = self.settings.lock().unwrap();
-                settings.url_address.to_value()
+            "variable-framerate" => {
+                let mut settings = self.settings.lock().unwrap();
+                let variable_framerate = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing variable-framerate from {} to {}",
+                    settings.variable_framerate,
+                    variable_framerate,
+                );
+                settings.variable_framerate = variable_framerate;
             }
-            "receiver-ndi-name" => {
-                let settings = self.settings.lock().unwrap();
-                settings.receiver_ndi_name.to_value()
+            "force-framerate" => {
+                let mut settings = self.settings.lock().unwrap();
+                let force_framerate = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing force-framerate from {} to {}",
+                    settings.force_framerate,
+                    force_framerate,
+                );
+                settings.force_framerate = force_framerate;
             }
-            "connect-timeout" => {
-                let settings = self.settings.lock().unwrap();
-                settings.connect_timeout.to_value()
+            "post-messages" => {
+                let mut settings = self.settings.lock().unwrap();
+                let post_messages = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing post-messages from {} to {}",
+                    settings.post_messages,
+                    post_messages,
+                );
+                settings.post_messages = post_messages;
             }
-            "timeout" => {
-                let settings = self.settings.lock().unwrap();
-                settings.timeout.to_value()
+            "level-interval" => {
+                let mut settings = self.settings.lock().unwrap();
+                let level_interval = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing level-interval from {} to {}",
+                    settings.level_interval,
+                    level_interval,
+                );
+                settings.level_interval = level_interval;
             }
-            "max-queue-length" => {
-                let settings = self.settings.lock().unwrap();
-                settings.max_queue_length.to_value()
+            "downmix-to-stereo" => {
+                let mut settings = self.settings.lock().unwrap();
+                let downmix_to_stereo = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing downmix-to-stereo from {} to {}",
+                    settings.downmix_to_stereo,
+                    downmix_to_stereo,
+                );
+                settings.downmix_to_stereo = downmix_to_stereo;
             }
-            "bandwidth" => {
-                let settings = self.settings.lock().unwrap();
-                settings.bandwidth.to_value()
+            "align-audio" => {
+                let mut settings = self.settings.lock().unwrap();
+                let align_audio = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing align-audio from {} to {}",
+                    settings.align_audio,
+                    align_audio,
+                );
+                settings.align_audio = align_audio;
             }
-            "color-format" => {
-                let settings = self.settings.lock().unwrap();
+            "n-threads" => {
+                let mut settings = self.settings.lock().unwrap();
+                let n_threads = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing n-threads from {} to {}",
+                    settings.n_threads,
+                    n_threads,
+                );
+                settings.n_threads = n_threads;
+            }
+            "force-aspect-ratio" => {
+                let mut settings = self.settings.lock().unwrap();
+                let force_aspect_ratio = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing force-aspect-ratio from {} to {}",
+                    settings.force_aspect_ratio,
+                    force_aspect_ratio,
+                );
+                settings.force_aspect_ratio = force_aspect_ratio;
+            }
+            "pixel-aspect-ratio" => {
+                let mut settings = self.settings.lock().unwrap();
+                let pixel_aspect_ratio = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing pixel-aspect-ratio from {} to {}",
+                    settings.pixel_aspect_ratio,
+                    pixel_aspect_ratio,
+                );
+                settings.pixel_aspect_ratio = pixel_aspect_ratio;
+            }
+            "channel-mask" => {
+                let mut settings = self.settings.lock().unwrap();
+                let channel_mask = value.get::<u64>().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing channel mask from {:?} to {:#x}",
+                    settings.channel_positions,
+                    channel_mask,
+                );
+                if channel_mask == 0 {
+                    settings.channel_positions = None;
+                } else {
+                    let mut positions = vec![
+                        gst_audio::AudioChannelPosition::Invalid;
+                        channel_mask.count_ones() as usize
+                    ];
+                    match gst_audio::AudioChannelPosition::positions_from_mask(
+                        channel_mask,
+                        &mut positions,
+                    ) {
+                        Ok(_) => settings.channel_positions = Some(positions),
+                        Err(_) => {
+                            gst_warning!(CAT, obj: obj, "Invalid channel mask {:#x}", channel_mask)
+                        }
+                    }
+                }
+            }
+            "channel-positions" => {
+                let mut settings = self.settings.lock().unwrap();
+                let positions = value
+                    .get::<Option<glib::ValueArray>>()
+                    .unwrap()
+                    .map(|array| {
+                        array
+                            .iter()
+                            .map(|v| v.get::<gst_audio::AudioChannelPosition>())
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .transpose();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing channel positions from {:?} to {:?}",
+                    settings.channel_positions,
+                    positions,
+                );
+                match positions {
+                    Ok(Some(positions)) if positions.is_empty() => {
+                        settings.channel_positions = None;
+                    }
+                    Ok(Some(positions))
+                        if gst_audio::AudioChannelPosition::check_valid_channel_positions(
+                            &positions, false,
+                        ) =>
+                    {
+                        settings.channel_positions = Some(positions);
+                    }
+                    Ok(None) => settings.channel_positions = None,
+                    _ => gst_warning!(
+                        CAT,
+                        obj: obj,
+                        "Invalid channel positions, must be unique"
+                    ),
+                }
+            }
+            "reference-level" => {
+                let mut settings = self.settings.lock().unwrap();
+                let reference_level = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing reference level from {} to {}",
+                    settings.reference_level,
+                    reference_level,
+                );
+                settings.reference_level = reference_level;
+            }
+            "shared-timing" => {
+                let mut settings = self.settings.lock().unwrap();
+                let shared_timing = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing shared timing from {} to {}",
+                    settings.shared_timing,
+                    shared_timing,
+                );
+                settings.shared_timing = shared_timing;
+            }
+            "show-local-sources" => {
+                let mut settings = self.settings.lock().unwrap();
+                let show_local_sources = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing show-local-sources from {} to {}",
+                    settings.show_local_sources,
+                    show_local_sources,
+                );
+                settings.show_local_sources = show_local_sources;
+            }
+            "extra-ips" => {
+                let mut settings = self.settings.lock().unwrap();
+                let extra_ips = value.get::<Option<String>>().unwrap();
+                if let Some(Err(err)) = extra_ips.as_deref().map(crate::ndi::validate_address_list)
+                {
+                    glib::g_warning!("ndisrc", "Invalid extra-ips: {}", err);
+                    return;
+                }
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing extra-ips from {:?} to {:?}",
+                    settings.extra_ips,
+                    extra_ips,
+                );
+                settings.extra_ips = extra_ips;
+            }
+            "ndi-library-path" => {
+                let mut settings = self.settings.lock().unwrap();
+                let library_path = value.get().unwrap();
+                gst_debug!(
+                    CAT,
+                    obj: obj,
+                    "Changing ndi-library-path from {:?} to {:?}",
+                    settings.library_path,
+                    library_path,
+                );
+                settings.library_path = library_path;
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.ndi_name.to_value()
+            }
+            "url-address" => {
+                let settings = self.settings.lock().unwrap();
+                settings.url_address.to_value()
+            }
+            "receiver-ndi-name" => {
+                let settings = self.settings.lock().unwrap();
+                settings.receiver_ndi_name.to_value()
+            }
+            "connect-timeout" => {
+                let settings = self.settings.lock().unwrap();
+                settings.connect_timeout.to_value()
+            }
+            "connect-retries" => {
+                let settings = self.settings.lock().unwrap();
+                settings.connect_retries.to_value()
+            }
+            "retry-backoff" => {
+                let settings = self.settings.lock().unwrap();
+                settings.retry_backoff.to_value()
+            }
+            "timeout" => {
+                let settings = self.settings.lock().unwrap();
+                settings.timeout.to_value()
+            }
+            "eos-on-disconnect" => {
+                let settings = self.settings.lock().unwrap();
+                settings.eos_on_disconnect.to_value()
+            }
+            "disconnect-timeout" => {
+                let settings = self.settings.lock().unwrap();
+                settings.disconnect_timeout.to_value()
+            }
+            "max-queue-length" => {
+                let settings = self.settings.lock().unwrap();
+                settings.max_queue_length.to_value()
+            }
+            "leaky" => {
+                let settings = self.settings.lock().unwrap();
+                settings.leaky.to_value()
+            }
+            "skip-to-live" => {
+                let settings = self.settings.lock().unwrap();
+                settings.skip_to_live.to_value()
+            }
+            "bandwidth" => {
+                let settings = self.settings.lock().unwrap();
+                settings.bandwidth.to_value()
+            }
+            "preview" => {
+                let settings = self.settings.lock().unwrap();
+                settings.preview.to_value()
+            }
+            "receive-audio" => {
+                let settings = self.settings.lock().unwrap();
+                settings.receive_audio.to_value()
+            }
+            "color-format" => {
+                let settings = self.settings.lock().unwrap();
                 settings.color_format.to_value()
             }
             "timestamp-mode" => {
                 let settings = self.settings.lock().unwrap();
                 settings.timestamp_mode.to_value()
             }
+            "slave-method" => {
+                let settings = self.settings.lock().unwrap();
+                settings.slave_method.to_value()
+            }
+            "variable-framerate" => {
+                let settings = self.settings.lock().unwrap();
+                settings.variable_framerate.to_value()
+            }
+            "force-framerate" => {
+                let settings = self.settings.lock().unwrap();
+                settings.force_framerate.to_value()
+            }
+            "post-messages" => {
+                let settings = self.settings.lock().unwrap();
+                settings.post_messages.to_value()
+            }
+            "level-interval" => {
+                let settings = self.settings.lock().unwrap();
+                settings.level_interval.to_value()
+            }
+            "downmix-to-stereo" => {
+                let settings = self.settings.lock().unwrap();
+                settings.downmix_to_stereo.to_value()
+            }
+            "align-audio" => {
+                let settings = self.settings.lock().unwrap();
+                settings.align_audio.to_value()
+            }
+            "n-threads" => {
+                let settings = self.settings.lock().unwrap();
+                settings.n_threads.to_value()
+            }
+            "force-aspect-ratio" => {
+                let settings = self.settings.lock().unwrap();
+                settings.force_aspect_ratio.to_value()
+            }
+            "pixel-aspect-ratio" => {
+                let settings = self.settings.lock().unwrap();
+                settings.pixel_aspect_ratio.to_value()
+            }
+            "channel-mask" => {
+                let settings = self.settings.lock().unwrap();
+                settings
+                    .channel_positions
+                    .as_ref()
+                    .and_then(|positions| {
+                        gst_audio::AudioChannelPosition::positions_to_mask(positions, true).ok()
+                    })
+                    .unwrap_or(0)
+                    .to_value()
+            }
+            "channel-positions" => {
+                let settings = self.settings.lock().unwrap();
+                let mut array = glib::ValueArray::new(0);
+                for position in settings.channel_positions.iter().flatten() {
+                    array.append(&position.to_value());
+                }
+                array.to_value()
+            }
+            "reference-level" => {
+                let settings = self.settings.lock().unwrap();
+                settings.reference_level.to_value()
+            }
+            "shared-timing" => {
+                let settings = self.settings.lock().unwrap();
+                settings.shared_timing.to_value()
+            }
+            "show-local-sources" => {
+                let settings = self.settings.lock().unwrap();
+                settings.show_local_sources.to_value()
+            }
+            "extra-ips" => {
+                let settings = self.settings.lock().unwrap();
+                settings.extra_ips.to_value()
+            }
+            "source-info" => self.source_info().to_value(),
+            "stats" => {
+                let stats = self
+                    .receiver_controller
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|controller| controller.stats())
+                    .unwrap_or_default();
+                gst::Structure::builder("application/x-ndi-src-stats")
+                    .field("video-frames", stats.video_frames)
+                    .field("audio-buffers", stats.audio_buffers)
+                    .field("dropped", stats.dropped)
+                    .field("overruns", stats.overruns)
+                    .field("bytes-received", stats.bytes_received)
+                    .field("reconnects", stats.reconnects)
+                    .build()
+                    .to_value()
+            }
+            "connected" => self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|controller| controller.is_connected())
+                .unwrap_or(false)
+                .to_value(),
+            "web-control" => self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|controller| controller.status().web_control)
+                .to_value(),
+            "ptz-supported" => self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|controller| controller.status().ptz_supported)
+                .unwrap_or(false)
+                .to_value(),
+            "recording-supported" => self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|controller| controller.status().recording_supported)
+                .unwrap_or(false)
+                .to_value(),
+            "recording" => {
+                let state = self.state.lock().unwrap();
+                state.recording.to_value()
+            }
+            "recording-filename" => self
+                .recv()
+                .and_then(|recv| recv.recording_filename())
+                .to_value(),
+            "recording-error" => self
+                .recv()
+                .and_then(|recv| recv.recording_error())
+                .to_value(),
+            "video-format" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .video_caps
+                    .as_ref()
+                    .and_then(|caps| caps.structure(0))
+                    .and_then(|s| s.get::<String>("format").ok())
+                    .or_else(|| {
+                        state
+                            .video_info
+                            .as_ref()
+                            .and_then(|info| info.codec_name())
+                            .map(String::from)
+                    })
+                    .to_value()
+            }
+            "width" | "height" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .video_caps
+                    .as_ref()
+                    .and_then(|caps| caps.structure(0))
+                    .and_then(|s| s.get::<i32>(pspec.name()).ok())
+                    .unwrap_or(0)
+                    .to_value()
+            }
+            "framerate" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .video_caps
+                    .as_ref()
+                    .and_then(|caps| caps.structure(0))
+                    .and_then(|s| s.get::<gst::Fraction>("framerate").ok())
+                    .unwrap_or_else(|| gst::Fraction::new(0, 1))
+                    .to_value()
+            }
+            "sample-rate" | "channels" => {
+                let state = self.state.lock().unwrap();
+                let field = if pspec.name() == "sample-rate" {
+                    "rate"
+                } else {
+                    "channels"
+                };
+                state
+                    .audio_caps
+                    .as_ref()
+                    .and_then(|caps| caps.structure(0))
+                    .and_then(|s| s.get::<i32>(field).ok())
+                    .unwrap_or(0)
+                    .to_value()
+            }
+            "ndi-library-path" => {
+                let settings = self.settings.lock().unwrap();
+                settings.library_path.to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -392,6 +1566,22 @@ impl ElementImpl for NdiSrc {
         PAD_TEMPLATES.as_ref()
     }
 
+    fn set_context(&self, element: &Self::Type, context: &gst::Context) {
+        if context.context_type() == TIMING_CONTEXT {
+            if let Ok(observations) = context.structure().get::<Observations>("observations") {
+                gst_debug!(CAT, obj: element, "Using shared timing from context");
+                *self.observations.lock().unwrap() = Some(observations);
+            }
+        } else if context.context_type() == FINDER_CONTEXT {
+            if let Ok(finder) = context.structure().get::<Finder>("finder") {
+                gst_debug!(CAT, obj: element, "Using shared finder from context");
+                *self.finder.lock().unwrap() = Some(finder);
+            }
+        }
+
+        self.parent_set_context(element, context)
+    }
+
     fn change_state(
         &self,
         element: &Self::Type,
@@ -400,12 +1590,12 @@ impl ElementImpl for NdiSrc {
         match transition {
             gst::StateChange::PausedToPlaying => {
                 if let Some(ref controller) = *self.receiver_controller.lock().unwrap() {
-                    controller.set_playing(true);
+                    controller.set_playing(true, element.current_running_time());
                 }
             }
             gst::StateChange::PlayingToPaused => {
                 if let Some(ref controller) = *self.receiver_controller.lock().unwrap() {
-                    controller.set_playing(false);
+                    controller.set_playing(false, None);
                 }
             }
             gst::StateChange::PausedToReady => {
@@ -420,6 +1610,434 @@ impl ElementImpl for NdiSrc {
     }
 }
 
+impl NdiSrc {
+    fn recv(&self) -> Option<RecvInstance> {
+        self.receiver_controller
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|controller| controller.recv())
+    }
+
+    fn update_recording(&self, element: &super::NdiSrc) {
+        let recording = self
+            .recv()
+            .as_ref()
+            .map(RecvInstance::recording_is_recording)
+            .unwrap_or(false);
+        let mut state = self.state.lock().unwrap();
+        if recording == state.recording {
+            return;
+        }
+        state.recording = recording;
+        drop(state);
+
+        element.notify("recording");
+        element.notify("recording-filename");
+    }
+
+    // Emits source-found and source-lost whenever the configured source appears on or disappears
+    // from the network, independent of the connection of the receiver
+    fn start_source_watch(
+        &self,
+        element: &super::NdiSrc,
+        ndi_name: Option<String>,
+        url_address: Option<String>,
+        show_local_sources: bool,
+        extra_ips: Option<String>,
+    ) {
+        let running = Arc::new(AtomicBool::new(true));
+        if let Some(old_running) = self.source_watch.lock().unwrap().replace(running.clone()) {
+            old_running.store(false, atomic::Ordering::SeqCst);
+        }
+
+        let finder = match self.shared_finder(element, show_local_sources, extra_ips.as_deref()) {
+            Some(finder) => finder,
+            None => {
+                gst_warning!(CAT, obj: element, "Failed to create Find instance");
+                return;
+            }
+        };
+
+        let element_weak = element.downgrade();
+        thread::spawn(move || {
+            let mut generation = 0;
+            let mut found = None::<(String, String)>;
+            while running.load(atomic::Ordering::SeqCst) {
+                let (new_generation, sources) =
+                    finder.wait_for_change(generation, time::Duration::from_secs(1));
+                if new_generation == generation {
+                    continue;
+                }
+                generation = new_generation;
+
+                let source = sources.into_iter().find(|(name, url)| {
+                    ndi_name.as_ref() == Some(name)
+                        || url_address
+                            .as_ref()
+                            .map(|url_address| url_address.split(',').any(|a| a == url))
+                            .unwrap_or(false)
+                });
+
+                let element = match element_weak.upgrade() {
+                    Some(element) => element,
+                    None => break,
+                };
+
+                match (&found, &source) {
+                    (None, Some((name, url))) => {
+                        gst_debug!(CAT, obj: &element, "Source {} ({}) found", name, url);
+                        element.emit_by_name::<()>("source-found", &[name, url]);
+                    }
+                    (Some((name, url)), None) => {
+                        gst_debug!(CAT, obj: &element, "Source {} ({}) lost", name, url);
+                        element.emit_by_name::<()>("source-lost", &[name, url]);
+                    }
+                    _ => (),
+                }
+                found = source;
+            }
+        });
+    }
+
+    // Looks up an object shared by all NDI sources of the pipeline the usual way: downstream
+    // context query, then a need-context message, and otherwise creates and announces a new one
+    fn shared_context<T>(
+        &self,
+        element: &super::NdiSrc,
+        context_type: &str,
+        field: &str,
+        slot: &Mutex<Option<T>>,
+        create: impl FnOnce() -> Option<T>,
+    ) -> Option<T>
+    where
+        T: Clone + glib::value::ToSendValue + Sync,
+    {
+        if let Some(ref value) = *slot.lock().unwrap() {
+            return Some(value.clone());
+        }
+
+        let mut query = gst::query::Context::new(context_type);
+        if element.static_pad("src").unwrap().peer_query(&mut query) {
+            if let Some(context) = query.context_owned() {
+                element.set_context(&context);
+            }
+        }
+
+        if slot.lock().unwrap().is_none() {
+            let _ = element.post_message(
+                gst::message::NeedContext::builder(context_type)
+                    .src(element)
+                    .build(),
+            );
+        }
+
+        if let Some(ref value) = *slot.lock().unwrap() {
+            return Some(value.clone());
+        }
+
+        gst_debug!(CAT, obj: element, "Creating new {} context", context_type);
+        let value = create()?;
+        let mut context = gst::Context::new(context_type, true);
+        context
+            .get_mut()
+            .unwrap()
+            .structure_mut()
+            .set(field, value.clone());
+        element.set_context(&context);
+        let _ = element.post_message(
+            gst::message::HaveContext::builder(context)
+                .src(element)
+                .build(),
+        );
+
+        Some(value)
+    }
+
+    fn shared_observations(&self, element: &super::NdiSrc) -> Observations {
+        self.shared_context(
+            element,
+            TIMING_CONTEXT,
+            "observations",
+            &self.observations,
+            || Some(Observations::new()),
+        )
+        .unwrap()
+    }
+
+    fn shared_finder(
+        &self,
+        element: &super::NdiSrc,
+        show_local_sources: bool,
+        extra_ips: Option<&str>,
+    ) -> Option<Finder> {
+        let finder =
+            self.shared_context(element, FINDER_CONTEXT, "finder", &self.finder, || {
+                Finder::new(show_local_sources, extra_ips)
+            })?;
+
+        // Only a finder with the same configuration can be shared
+        let extra_ips = extra_ips.map(crate::ndi::normalize_address_list);
+        if finder.show_local_sources() == show_local_sources
+            && finder.extra_ips() == extra_ips.as_deref()
+        {
+            Some(finder)
+        } else {
+            gst_debug!(CAT, obj: element, "Shared finder has a different configuration");
+            Finder::new(show_local_sources, extra_ips.as_deref())
+        }
+    }
+
+    // Accumulates the RMS and peak of every channel and returns a message like the one of the
+    // level element once a level interval is complete
+    fn update_level(
+        &self,
+        element: &super::NdiSrc,
+        state: &mut State,
+        buffer: &gst::Buffer,
+    ) -> Option<gst::Message> {
+        let (post_messages, interval) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.post_messages, settings.level_interval)
+        };
+        if !post_messages {
+            return None;
+        }
+
+        let info = match state.audio_info {
+            Some(crate::AudioInfo::AudioInfo(ref info)) => info,
+            _ => return None,
+        };
+        let channels = info.channels() as usize;
+        if channels == 0 {
+            return None;
+        }
+
+        // Samples normalized to -1.0..1.0, ndisrc outputs F32 but the other formats are cheap
+        // enough to support too
+        let sample: fn(&[u8]) -> f64 = match info.format() {
+            gst_audio::AUDIO_FORMAT_F32 => |b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            gst_audio::AUDIO_FORMAT_F64 => {
+                |b| f64::from_ne_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            }
+            gst_audio::AUDIO_FORMAT_S16 => |b| i16::from_ne_bytes([b[0], b[1]]) as f64 / 32768.0,
+            gst_audio::AUDIO_FORMAT_S32 => {
+                |b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2_147_483_648.0
+            }
+            format => {
+                gst_debug!(CAT, obj: element, "No level for audio format {:?}", format);
+                return None;
+            }
+        };
+        let bps = info.bps() as usize;
+
+        // Byte offset of the first sample of every channel and the distance between samples
+        let map = buffer.map_readable().ok()?;
+        let frames = map.len() / bps / channels;
+        let (frames, offsets, step) = if info.layout() == gst_audio::AudioLayout::NonInterleaved {
+            // The channels can be further apart than the samples, e.g. when the buffer wraps the
+            // memory of the NDI frame, as described by the audio meta
+            #[cfg(feature = "non-interleaved-audio")]
+            let meta = buffer
+                .meta::<gst_audio::AudioMeta>()
+                .map(|meta| (meta.samples(), meta.offsets().to_vec()));
+            #[cfg(not(feature = "non-interleaved-audio"))]
+            let meta: Option<(usize, Vec<usize>)> = None;
+
+            let (frames, offsets) = meta
+                .filter(|(_, offsets)| offsets.len() == channels)
+                .unwrap_or_else(|| {
+                    (
+                        frames,
+                        (0..channels)
+                            .map(|channel| channel * frames * bps)
+                            .collect(),
+                    )
+                });
+            (frames, offsets, bps)
+        } else {
+            (
+                frames,
+                (0..channels).map(|channel| channel * bps).collect(),
+                channels * bps,
+            )
+        };
+
+        let level = &mut state.level;
+        if level.sum_squares.len() != channels {
+            *level = Level {
+                sum_squares: vec![0.0; channels],
+                peak: vec![0.0; channels],
+                ..Default::default()
+            };
+        }
+        if level.timestamp.is_none() {
+            level.timestamp = buffer.pts();
+        }
+
+        for (channel, offset) in offsets.into_iter().enumerate() {
+            for i in 0..frames {
+                let data = match map.get(offset + i * step..) {
+                    Some(data) if data.len() >= bps => data,
+                    _ => break,
+                };
+                let sample = sample(data);
+                level.sum_squares[channel] += sample * sample;
+                level.peak[channel] = level.peak[channel].max(sample.abs());
+            }
+        }
+        level.samples += frames as u64;
+        level.duration += buffer.duration().unwrap_or(gst::ClockTime::ZERO);
+
+        if level.duration.nseconds() < interval || level.samples == 0 {
+            return None;
+        }
+
+        let rms = gst::Array::new(
+            level
+                .sum_squares
+                .iter()
+                .map(|sum_squares| 10.0 * (sum_squares / level.samples as f64).log10()),
+        );
+        let peak = gst::Array::new(level.peak.iter().map(|peak| 20.0 * peak.log10()));
+
+        let s = gst::Structure::builder("level")
+            .field("timestamp", level.timestamp)
+            .field("duration", level.duration)
+            .field("rms", rms)
+            .field("peak", peak)
+            .build();
+
+        *level = Level {
+            sum_squares: vec![0.0; channels],
+            peak: vec![0.0; channels],
+            ..Default::default()
+        };
+
+        Some(gst::message::Element::builder(s).src(element).build())
+    }
+
+    // Answered for the ndi-source-info query and the source-info property
+    fn source_info(&self) -> gst::Structure {
+        let state = self.state.lock().unwrap();
+        let settings = self.settings.lock().unwrap();
+
+        let mut info = gst::Structure::builder("ndi-source-info")
+            .field("ndi-name", settings.ndi_name.clone())
+            .field("url-address", settings.url_address.clone())
+            .build();
+
+        if let Some(s) = state.video_caps.as_ref().and_then(|caps| caps.structure(0)) {
+            for field in &["width", "height", "framerate"] {
+                if let Ok(value) = s.value(field) {
+                    info.set_value(field, value.clone());
+                }
+            }
+        }
+
+        if let Some(s) = state.audio_caps.as_ref().and_then(|caps| caps.structure(0)) {
+            for (field, info_field) in &[("rate", "sample-rate"), ("channels", "channels")] {
+                if let Ok(value) = s.value(field) {
+                    info.set_value(info_field, value.clone());
+                }
+            }
+        }
+
+        info
+    }
+
+    fn stream_id(&self) -> String {
+        let settings = self.settings.lock().unwrap();
+        let source = settings
+            .ndi_name
+            .as_deref()
+            .or(settings.url_address.as_deref())
+            .unwrap_or_default();
+
+        glib::compute_checksum_for_data(glib::ChecksumType::Sha256, source.as_bytes())
+            .unwrap()
+            .to_string()
+    }
+
+    // Returns the stream tags if they changed since the last call
+    fn update_tags(&self, state: &mut State) -> Option<gst::TagList> {
+        let settings = self.settings.lock().unwrap();
+
+        let mut tags = gst::TagList::new();
+        {
+            let tags = tags.get_mut().unwrap();
+
+            // NDI source names are of the form "MACHINE (STREAM)"
+            if let Some(ref ndi_name) = settings.ndi_name {
+                match ndi_name.split_once(" (") {
+                    Some((machine, stream)) if stream.ends_with(')') => {
+                        tags.add::<gst::tags::Title>(
+                            &stream.trim_end_matches(')'),
+                            gst::TagMergeMode::Replace,
+                        );
+                        tags.add::<super::NdiMachineTag>(&machine, gst::TagMergeMode::Replace);
+                    }
+                    _ => {
+                        tags.add::<gst::tags::Title>(
+                            &ndi_name.as_str(),
+                            gst::TagMergeMode::Replace,
+                        );
+                    }
+                }
+            } else if let Some(ref url_address) = settings.url_address {
+                tags.add::<gst::tags::Title>(&url_address.as_str(), gst::TagMergeMode::Replace);
+            }
+
+            if let Some(codec) = state.video_info.as_ref().and_then(|info| info.codec_name()) {
+                tags.add::<gst::tags::VideoCodec>(&codec, gst::TagMergeMode::Replace);
+            }
+            if let Some(codec) = state.audio_info.as_ref().and_then(|info| info.codec_name()) {
+                tags.add::<gst::tags::AudioCodec>(&codec, gst::TagMergeMode::Replace);
+            }
+
+            let web_control = self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|controller| controller.status().web_control);
+            if let Some(web_control) = web_control {
+                tags.add::<gst::tags::Homepage>(&web_control.as_str(), gst::TagMergeMode::Replace);
+            }
+
+            let bytes_received = self
+                .receiver_controller
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|controller| controller.stats().bytes_received);
+            if let Some(bytes_received) = bytes_received {
+                let now = time::Instant::now();
+                match state.bitrate.start {
+                    Some((start, start_bytes)) if now - start >= BITRATE_INTERVAL => {
+                        let bits = bytes_received.saturating_sub(start_bytes) * 8;
+                        let bitrate = bits as f64 / (now - start).as_secs_f64();
+                        state.bitrate.bitrate = Some(bitrate.min(u32::MAX as f64) as u32);
+                        state.bitrate.start = Some((now, bytes_received));
+                    }
+                    Some(_) => (),
+                    None => state.bitrate.start = Some((now, bytes_received)),
+                }
+            }
+            if let Some(bitrate) = state.bitrate.bitrate {
+                tags.add::<gst::tags::Bitrate>(&bitrate, gst::TagMergeMode::Replace);
+            }
+        }
+
+        if state.tags.as_ref() == Some(&tags) {
+            return None;
+        }
+
+        state.tags = Some(tags.clone());
+        Some(tags)
+    }
+}
+
 impl BaseSrcImpl for NdiSrc {
     fn negotiate(&self, element: &Self::Type) -> Result<(), gst::LoggableError> {
         element
@@ -444,6 +2062,16 @@ impl BaseSrcImpl for NdiSrc {
     }
 
     fn start(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        let library_path = self.settings.lock().unwrap().library_path.clone();
+        crate::ndi::load_from(library_path.as_deref())
+            .map_err(|err| gst::error_msg!(gst::LibraryError::Init, ["{}", err]))?;
+        gst_debug!(
+            CAT,
+            obj: element,
+            "Using NDI SDK version {}",
+            crate::ndi::version().as_deref().unwrap_or("unknown")
+        );
+
         *self.state.lock().unwrap() = Default::default();
         let settings = self.settings.lock().unwrap().clone();
 
@@ -454,17 +2082,59 @@ impl BaseSrcImpl for NdiSrc {
             ));
         }
 
+        // Also accepts IPv6 addresses, with brackets if a port is given, and a comma separated
+        // list of candidates
+        let url_address = settings
+            .url_address
+            .as_deref()
+            .map(crate::ndi::normalize_address_list);
+
+        // Only watched once connecting succeeded, as stop() isn't called otherwise
+        let watched_source = (settings.ndi_name.clone(), url_address.clone());
+
+        let settings_color_format = settings.color_format;
         let receiver = Receiver::connect(
             element.upcast_ref(),
-            settings.ndi_name.as_deref(),
-            settings.url_address.as_deref(),
-            &settings.receiver_ndi_name,
-            settings.connect_timeout,
-            settings.bandwidth,
-            settings.color_format.into(),
-            settings.timestamp_mode,
-            settings.timeout,
-            settings.max_queue_length as usize,
+            &ReceiverSettings {
+                ndi_name: settings.ndi_name,
+                url_address,
+                receiver_ndi_name: settings.receiver_ndi_name,
+                connect_timeout: settings.connect_timeout,
+                connect_retries: settings.connect_retries,
+                retry_backoff: settings.retry_backoff,
+                timeout: settings.timeout,
+                eos_on_disconnect: settings.eos_on_disconnect,
+                disconnect_timeout: settings.disconnect_timeout,
+                max_queue_length: settings.max_queue_length as usize,
+                leaky: settings.leaky,
+                skip_to_live: settings.skip_to_live,
+                bandwidth: if settings.preview {
+                    ndisys::NDIlib_recv_bandwidth_lowest
+                } else {
+                    settings.bandwidth
+                },
+                receive_audio: settings.receive_audio,
+                color_format: settings_color_format.into(),
+                timestamp_mode: settings.timestamp_mode,
+                slave_method: settings.slave_method,
+                variable_framerate: settings.variable_framerate,
+                force_framerate: Some(settings.force_framerate)
+                    .filter(|force_framerate| force_framerate.numer() > 0),
+                force_aspect_ratio: Some(settings.force_aspect_ratio)
+                    .filter(|force_aspect_ratio| force_aspect_ratio.numer() > 0),
+                pixel_aspect_ratio: Some(settings.pixel_aspect_ratio)
+                    .filter(|pixel_aspect_ratio| pixel_aspect_ratio.numer() > 0),
+                downmix_to_stereo: settings.downmix_to_stereo,
+                align_audio: settings.align_audio,
+                n_threads: settings.n_threads,
+                channel_positions: settings.channel_positions,
+                reference_level: settings.reference_level,
+                observations: if settings.shared_timing {
+                    Some(self.shared_observations(element))
+                } else {
+                    None
+                },
+            },
         );
 
         match receiver {
@@ -476,18 +2146,45 @@ impl BaseSrcImpl for NdiSrc {
                 *self.receiver_controller.lock().unwrap() =
                     Some(receiver.receiver_control_handle());
                 let mut state = self.state.lock().unwrap();
+                if settings_color_format == RecvColorFormat::Auto {
+                    state.auto_color_format = Some(RecvColorFormat::UyvyBgra);
+                }
                 state.receiver = Some(receiver);
+                drop(state);
+
+                let (ndi_name, url_address) = watched_source;
+                self.start_source_watch(
+                    element,
+                    ndi_name,
+                    url_address,
+                    settings.show_local_sources,
+                    settings.extra_ips,
+                );
 
                 Ok(())
             }
         }
     }
 
-    fn stop(&self, _element: &Self::Type) -> Result<(), gst::ErrorMessage> {
-        if let Some(ref controller) = self.receiver_controller.lock().unwrap().take() {
+    fn stop(&self, element: &Self::Type) -> Result<(), gst::ErrorMessage> {
+        if let Some(running) = self.source_watch.lock().unwrap().take() {
+            // Don't actually join because that might take a while
+            running.store(false, atomic::Ordering::SeqCst);
+        }
+
+        let controller = self.receiver_controller.lock().unwrap().take();
+        if let Some(ref controller) = controller {
             controller.shutdown();
         }
         *self.state.lock().unwrap() = State::default();
+
+        if controller
+            .map(|controller| controller.is_connected())
+            .unwrap_or(false)
+        {
+            element.notify("connected");
+        }
+
         Ok(())
     }
 
@@ -495,6 +2192,18 @@ impl BaseSrcImpl for NdiSrc {
         use gst::QueryView;
 
         match query.view_mut() {
+            QueryView::Custom(ref mut q)
+                if q.structure()
+                    .map(|s| s.name() == "ndi-source-info")
+                    .unwrap_or(false) =>
+            {
+                let info = self.source_info();
+                let s = q.query_mut().structure_mut();
+                for (field, value) in info.iter() {
+                    s.set_value(field, value.clone());
+                }
+                true
+            }
             QueryView::Scheduling(ref mut q) => {
                 q.set(gst::SchedulingFlags::SEQUENTIAL, 1, -1, 0);
                 q.add_scheduling_modes(&[gst::PadMode::Push]);
@@ -509,12 +2218,13 @@ impl BaseSrcImpl for NdiSrc {
                         settings.timestamp_mode,
                         TimestampMode::ReceiveTimeTimecode | TimestampMode::ReceiveTimeTimestamp
                     ) {
-                        latency
+                        latency + state.jitter
                     } else {
                         gst::ClockTime::ZERO
                     };
 
-                    let max = settings.max_queue_length as u64 * latency;
+                    // Every buffer can additionally wait behind a full internal queue
+                    let max = min + settings.max_queue_length as u64 * latency;
 
                     gst_debug!(
                         CAT,
@@ -533,6 +2243,83 @@ impl BaseSrcImpl for NdiSrc {
         }
     }
 
+    fn event(&self, element: &Self::Type, event: &gst::Event) -> bool {
+        use gst::EventView;
+
+        match event.view() {
+            // The streams are only split by ndisrcdemux, which names them after the stream id
+            // of this element
+            EventView::SelectStreams(select_streams) => {
+                let stream_id = self.stream_id();
+                let streams = select_streams.streams();
+                let video = streams.contains(&format!("{}/video", stream_id));
+                let audio = streams.contains(&format!("{}/audio", stream_id));
+
+                if !video && !audio {
+                    gst_warning!(CAT, obj: element, "Can't deselect all streams");
+                    return false;
+                }
+
+                gst_debug!(
+                    CAT,
+                    obj: element,
+                    "Selecting streams: video {}, audio {}",
+                    video,
+                    audio
+                );
+                if let Some(ref controller) = *self.receiver_controller.lock().unwrap() {
+                    controller.select_streams(video, audio);
+                }
+                true
+            }
+            // Formats the elements after ndisrcdemux accept, in order of preference
+            EventView::CustomUpstream(ev)
+                if ev
+                    .structure()
+                    .map(|s| s.name() == "GstNdiDownstreamFormats")
+                    .unwrap_or(false) =>
+            {
+                if self.settings.lock().unwrap().color_format != RecvColorFormat::Auto {
+                    return true;
+                }
+
+                let formats = ev
+                    .structure()
+                    .and_then(|s| s.get::<gst::Array>("formats").ok())
+                    .map(|formats| {
+                        formats
+                            .iter()
+                            .filter_map(|format| format.get::<String>().ok())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                let color_format = match RecvColorFormat::for_formats(&formats) {
+                    Some(color_format) => color_format,
+                    None => return true,
+                };
+
+                let mut state = self.state.lock().unwrap();
+                if state.auto_color_format == Some(color_format) {
+                    return true;
+                }
+
+                gst_debug!(
+                    CAT,
+                    obj: element,
+                    "Downstream accepts {:?}, receiving as {:?}",
+                    formats,
+                    color_format
+                );
+                if let Some(ref controller) = *self.receiver_controller.lock().unwrap() {
+                    state.auto_color_format = Some(color_format);
+                    controller.set_color_format(color_format.into());
+                }
+                true
+            }
+            _ => self.parent_event(element, event),
+        }
+    }
+
     fn create(
         &self,
         element: &Self::Type,
@@ -552,14 +2339,37 @@ impl BaseSrcImpl for NdiSrc {
         };
 
         let res = recv.capture();
+        let events = recv.take_events();
 
         let mut state = self.state.lock().unwrap();
+        let jitter = recv.jitter(
+            state.video_info.is_some() || matches!(res, ReceiverItem::Buffer(Buffer::Video(..))),
+        );
         state.receiver = Some(recv);
 
         match res {
             ReceiverItem::Buffer(buffer) => {
+                let jitter_changed = if jitter > state.jitter {
+                    jitter - state.jitter
+                } else {
+                    state.jitter - jitter
+                } > JITTER_THRESHOLD;
+                if jitter_changed {
+                    gst_debug!(
+                        CAT,
+                        obj: element,
+                        "Arrival jitter changed from {} to {}",
+                        state.jitter,
+                        jitter
+                    );
+                    state.jitter = jitter;
+                }
+
                 let buffer = match buffer {
                     Buffer::Audio(mut buffer, info) => {
+                        let mut latency_changed = jitter_changed;
+                        let format_changed = state.audio_info.as_ref() != Some(&info);
+
                         if state.audio_info.as_ref() != Some(&info) {
                             let caps = info.to_caps().map_err(|_| {
                                 gst::element_error!(
@@ -571,6 +2381,11 @@ impl BaseSrcImpl for NdiSrc {
                             })?;
                             state.audio_info = Some(info);
                             state.audio_caps = Some(caps);
+                            // Without video the latency is based on the audio buffers
+                            if state.video_info.is_none() {
+                                latency_changed = state.current_latency != buffer.duration();
+                                state.current_latency = buffer.duration();
+                            }
                         }
 
                         {
@@ -582,10 +2397,34 @@ impl BaseSrcImpl for NdiSrc {
                             );
                         }
 
+                        let level_message = self.update_level(element, &mut state, &buffer);
+
+                        let tags = self.update_tags(&mut state);
+                        drop(state);
+                        if format_changed {
+                            for name in ["sample-rate", "channels"] {
+                                element.notify(name);
+                            }
+                        }
+                        if let Some(tags) = tags {
+                            element.send_event(gst::event::Tag::new(tags));
+                        }
+
+                        if latency_changed {
+                            let _ = element.post_message(
+                                gst::message::Latency::builder().src(element).build(),
+                            );
+                        }
+
+                        if let Some(message) = level_message {
+                            let _ = element.post_message(message);
+                        }
+
                         buffer
                     }
                     Buffer::Video(mut buffer, info) => {
-                        let mut latency_changed = false;
+                        let mut latency_changed = jitter_changed;
+                        let format_changed = state.video_info.as_ref() != Some(&info);
 
                         if state.video_info.as_ref() != Some(&info) {
                             let caps = info.to_caps().map_err(|_| {
@@ -611,7 +2450,17 @@ impl BaseSrcImpl for NdiSrc {
                             );
                         }
 
+                        let tags = self.update_tags(&mut state);
                         drop(state);
+                        if format_changed {
+                            for name in ["video-format", "width", "height", "framerate"] {
+                                element.notify(name);
+                            }
+                        }
+                        if let Some(tags) = tags {
+                            element.send_event(gst::event::Tag::new(tags));
+                        }
+
                         if latency_changed {
                             let _ = element.post_message(
                                 gst::message::Latency::builder().src(element).build(),
@@ -622,6 +2471,12 @@ impl BaseSrcImpl for NdiSrc {
                     }
                 };
 
+                for event in events {
+                    element.send_event(event);
+                }
+
+                self.update_recording(element);
+
                 Ok(CreateSuccess::NewBuffer(buffer))
             }
             ReceiverItem::Timeout => Err(gst::FlowError::Eos),
@@ -9,7 +9,30 @@ glib::wrapper! {
 unsafe impl Send for NdiSrc {}
 unsafe impl Sync for NdiSrc {}
 
+// Machine that an NDI source runs on, i.e. "MACHINE" of a source named "MACHINE (STREAM)"
+pub enum NdiMachineTag {}
+
+impl<'a> gst::tags::Tag<'a> for NdiMachineTag {
+    type TagType = &'a str;
+
+    fn tag_name<'b>() -> &'b str {
+        "ndi-machine"
+    }
+}
+
+impl<'a> gst::tags::CustomTag<'a> for NdiMachineTag {
+    const FLAG: gst::TagFlag = gst::TagFlag::Meta;
+    const NICK: &'static str = "NDI machine";
+    const DESCRIPTION: &'static str = "Machine that the NDI source runs on";
+}
+
 pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    use gst::tags::Tag;
+
+    if !gst::tags::tag_exists(NdiMachineTag::tag_name()) {
+        gst::tags::register::<NdiMachineTag>();
+    }
+
     gst::Element::register(
         Some(plugin),
         "ndisrc",
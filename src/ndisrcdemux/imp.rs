@@ -24,6 +24,13 @@ struct State {
 
     audio_pad: Option<gst::Pad>,
     audio_caps: Option<gst::Caps>,
+    // Input and output audio info if the received F32 audio has to be converted to another
+    // format or layout for downstream
+    audio_conversion: Option<(gst_audio::AudioInfo, gst_audio::AudioInfo)>,
+
+    // Streams of the source pads, announced together in a stream collection
+    video_stream: Option<gst::Stream>,
+    audio_stream: Option<gst::Stream>,
 }
 
 pub struct NdiSrcDemux {
@@ -166,10 +173,12 @@ impl NdiSrcDemux {
         let mut events = vec![];
         let srcpad;
         let mut add_pad = false;
+        let mut renegotiate_audio = None;
 
         let mut state = self.state.lock().unwrap();
         let caps = meta.caps();
-        match meta.stream_type() {
+        let stream_type = meta.stream_type();
+        match stream_type {
             ndisrcmeta::StreamType::Audio => {
                 if let Some(ref pad) = state.audio_pad {
                     srcpad = pad.clone();
@@ -180,6 +189,13 @@ impl NdiSrcDemux {
                     let templ = klass.pad_template("audio").unwrap();
                     let pad = gst::Pad::builder_with_template(&templ, Some("audio"))
                         .flags(gst::PadFlags::FIXED_CAPS)
+                        .event_function(|pad, parent, event| {
+                            NdiSrcDemux::catch_panic_pad_function(
+                                parent,
+                                || false,
+                                |self_, element| self_.src_event(pad, element, event),
+                            )
+                        })
                         .build();
 
                     let mut caps_event = Some(gst::event::Caps::new(&caps));
@@ -205,6 +221,11 @@ impl NdiSrcDemux {
 
                     let _ = pad.set_active(true);
                     for ev in events.drain(..) {
+                        let (ev, stream) =
+                            stream_start_for_pad(ev, "audio", gst::StreamType::AUDIO, Some(&caps));
+                        if stream.is_some() {
+                            state.audio_stream = stream;
+                        }
                         let _ = pad.store_sticky_event(&ev);
                     }
 
@@ -216,8 +237,13 @@ impl NdiSrcDemux {
 
                 if state.audio_caps.as_ref() != Some(&caps) {
                     gst_debug!(CAT, obj: element, "Audio caps changed to {}", caps);
-                    events.push(gst::event::Caps::new(&caps));
-                    state.audio_caps = Some(caps);
+                    state.audio_caps = Some(caps.clone());
+                    if let Some(ref stream) = state.audio_stream {
+                        stream.set_caps(Some(&caps));
+                    }
+                    renegotiate_audio = Some(caps);
+                } else if add_pad {
+                    renegotiate_audio = Some(caps);
                 }
             }
             ndisrcmeta::StreamType::Video => {
@@ -230,6 +256,13 @@ impl NdiSrcDemux {
                     let templ = klass.pad_template("video").unwrap();
                     let pad = gst::Pad::builder_with_template(&templ, Some("video"))
                         .flags(gst::PadFlags::FIXED_CAPS)
+                        .event_function(|pad, parent, event| {
+                            NdiSrcDemux::catch_panic_pad_function(
+                                parent,
+                                || false,
+                                |self_, element| self_.src_event(pad, element, event),
+                            )
+                        })
                         .build();
 
                     let mut caps_event = Some(gst::event::Caps::new(&caps));
@@ -255,6 +288,11 @@ impl NdiSrcDemux {
 
                     let _ = pad.set_active(true);
                     for ev in events.drain(..) {
+                        let (ev, stream) =
+                            stream_start_for_pad(ev, "video", gst::StreamType::VIDEO, Some(&caps));
+                        if stream.is_some() {
+                            state.video_stream = stream;
+                        }
                         let _ = pad.store_sticky_event(&ev);
                     }
 
@@ -267,6 +305,9 @@ impl NdiSrcDemux {
                 if state.video_caps.as_ref() != Some(&caps) {
                     gst_debug!(CAT, obj: element, "Video caps changed to {}", caps);
                     events.push(gst::event::Caps::new(&caps));
+                    if let Some(ref stream) = state.video_stream {
+                        stream.set_caps(Some(&caps));
+                    }
                     state.video_caps = Some(caps);
                 }
             }
@@ -282,12 +323,97 @@ impl NdiSrcDemux {
             srcpad.push_event(ev);
         }
 
+        if add_pad {
+            self.update_stream_collection(element, self.sinkpad.stream_id().as_deref());
+        }
+
+        // Lets ndisrc with color-format=auto pick the color format, e.g. after linking
+        if stream_type == ndisrcmeta::StreamType::Video && srcpad.check_reconfigure() {
+            self.send_downstream_formats(element, &srcpad);
+        }
+
+        if stream_type == ndisrcmeta::StreamType::Audio {
+            let renegotiate_audio = renegotiate_audio.or_else(|| {
+                if srcpad.check_reconfigure() {
+                    self.state.lock().unwrap().audio_caps.clone()
+                } else {
+                    None
+                }
+            });
+
+            if let Some(in_caps) = renegotiate_audio {
+                let caps = self.negotiate_audio_caps(&srcpad, &in_caps);
+                if srcpad.current_caps().as_ref() != Some(&caps) {
+                    gst_debug!(CAT, obj: element, "Negotiated audio caps {}", caps);
+                    srcpad.push_event(gst::event::Caps::new(&caps));
+                }
+
+                let conversion = match (
+                    gst_audio::AudioInfo::from_caps(&in_caps),
+                    gst_audio::AudioInfo::from_caps(&caps),
+                ) {
+                    (Ok(in_info), Ok(out_info)) if in_info != out_info => Some((in_info, out_info)),
+                    _ => None,
+                };
+                self.state.lock().unwrap().audio_conversion = conversion;
+            }
+
+            let audio_conversion = self.state.lock().unwrap().audio_conversion.clone();
+            if let Some((in_info, out_info)) = audio_conversion {
+                buffer = convert_audio_buffer(&buffer, &in_info, &out_info).ok_or_else(|| {
+                    gst_error!(CAT, obj: element, "Failed to convert audio buffer");
+                    gst::FlowError::Error
+                })?;
+            }
+        }
+
         let res = srcpad.push(buffer);
 
         let mut state = self.state.lock().unwrap();
         state.combiner.update_pad_flow(&srcpad, res)
     }
 
+    fn negotiate_audio_caps(&self, pad: &gst::Pad, caps: &gst::Caps) -> gst::Caps {
+        match gst_audio::AudioInfo::from_caps(caps) {
+            Ok(info) if info.format() == gst_audio::AUDIO_FORMAT_F32 => (),
+            _ => return caps.clone(),
+        }
+
+        // Offer the formats and layouts we can convert to, in order of preference, and let
+        // downstream pick
+        let mut filter = caps.clone();
+        {
+            let s = filter.get_mut().unwrap().structure_mut(0).unwrap();
+            s.set(
+                "format",
+                gst::List::new([
+                    gst_audio::AUDIO_FORMAT_F32.to_str(),
+                    gst_audio::AudioFormat::F64le.to_str(),
+                    gst_audio::AudioFormat::S32le.to_str(),
+                ]),
+            );
+            #[cfg(feature = "non-interleaved-audio")]
+            s.set("layout", gst::List::new(["non-interleaved", "interleaved"]));
+        }
+
+        let mut peer_caps = pad.peer_query_caps(Some(&filter));
+        if peer_caps.is_empty() {
+            return caps.clone();
+        }
+        peer_caps.fixate();
+
+        let mut caps = caps.clone();
+        if let Some(peer_s) = peer_caps.structure(0) {
+            let s = caps.get_mut().unwrap().structure_mut(0).unwrap();
+            for field in ["format", "layout"] {
+                if let Ok(value) = peer_s.get::<&str>(field) {
+                    s.set(field, value);
+                }
+            }
+        }
+        caps
+    }
+
     fn sink_event(&self,
         pad: &gst::Pad,
         element: &super::NdiSrcDemux,
@@ -305,8 +431,236 @@ impl NdiSrcDemux {
                     ["EOS without available srcpad(s)"]
                 );
             }
+        } else if let EventView::StreamStart(stream_start) = event.view() {
+            let mut ret = true;
+            for srcpad in element.src_pads() {
+                let stream_type = if srcpad.name() == "video" {
+                    gst::StreamType::VIDEO
+                } else {
+                    gst::StreamType::AUDIO
+                };
+                let (ev, stream) = stream_start_for_pad(
+                    event.clone(),
+                    &srcpad.name(),
+                    stream_type,
+                    srcpad.current_caps().as_ref(),
+                );
+
+                let mut state = self.state.lock().unwrap();
+                if stream_type == gst::StreamType::VIDEO {
+                    state.video_stream = stream;
+                } else {
+                    state.audio_stream = stream;
+                }
+                drop(state);
+
+                ret &= srcpad.push_event(ev);
+            }
+            self.update_stream_collection(element, Some(stream_start.stream_id()));
+            return ret;
         }
         pad.event_default(Some(element), event)
     }
 
+    fn src_event(&self, pad: &gst::Pad, element: &super::NdiSrcDemux, event: gst::Event) -> bool {
+        gst_log!(CAT, obj: pad, "Handling event {:?}", event);
+
+        if let gst::EventView::SelectStreams(select_streams) = event.view() {
+            let mut streams = select_streams.streams();
+
+            // Streams that didn't show up yet stay enabled so that they can still be selected
+            // once they do
+            if let Some(upstream_id) = self.sinkpad.stream_id() {
+                let state = self.state.lock().unwrap();
+                for (name, stream) in [
+                    ("video", &state.video_stream),
+                    ("audio", &state.audio_stream),
+                ] {
+                    if stream.is_none() {
+                        streams.push(format!("{}/{}", upstream_id, name));
+                    }
+                }
+            }
+
+            let streams = streams.iter().map(String::as_str).collect::<Vec<_>>();
+            let event = gst::event::SelectStreams::builder(&streams)
+                .seqnum(event.seqnum())
+                .build();
+            return self.sinkpad.push_event(event);
+        }
+
+        pad.event_default(Some(element), event)
+    }
+
+    // Sends the raw video formats accepted downstream upstream, in order of preference
+    fn send_downstream_formats(&self, element: &super::NdiSrcDemux, srcpad: &gst::Pad) {
+        let caps = srcpad.peer_query_caps(None);
+        let mut formats = Vec::<String>::new();
+        for s in caps.iter().filter(|s| s.name() == "video/x-raw") {
+            let values = if let Ok(format) = s.get::<String>("format") {
+                vec![format]
+            } else if let Ok(list) = s.get::<gst::List>("format") {
+                list.iter().filter_map(|v| v.get::<String>().ok()).collect()
+            } else {
+                Vec::new()
+            };
+
+            for format in values {
+                if !formats.contains(&format) {
+                    formats.push(format);
+                }
+            }
+        }
+
+        if formats.is_empty() {
+            return;
+        }
+
+        gst_debug!(CAT, obj: element, "Downstream accepts video formats {:?}", formats);
+        let s = gst::Structure::builder("GstNdiDownstreamFormats")
+            .field("formats", gst::Array::new(formats))
+            .build();
+        self.sinkpad.push_event(gst::event::CustomUpstream::new(s));
+    }
+
+    // Posts the streams of all source pads as a stream collection and sends it downstream, so
+    // that e.g. decodebin3 and playbin3 can select between them
+    fn update_stream_collection(&self, element: &super::NdiSrcDemux, upstream_id: Option<&str>) {
+        let collection = {
+            let state = self.state.lock().unwrap();
+            let streams = [&state.video_stream, &state.audio_stream]
+                .iter()
+                .filter_map(|stream| stream.as_ref())
+                .cloned()
+                .collect::<Vec<_>>();
+            if streams.is_empty() {
+                return;
+            }
+
+            gst::StreamCollection::builder(upstream_id)
+                .streams(&streams)
+                .build()
+        };
+
+        gst_debug!(CAT, obj: element, "Posting stream collection {:?}", collection);
+        let _ = element.post_message(
+            gst::message::StreamCollection::builder(&collection)
+                .src(element)
+                .build(),
+        );
+
+        for srcpad in element.src_pads() {
+            srcpad.push_event(gst::event::StreamCollection::new(&collection));
+        }
+    }
+}
+
+fn convert_audio_buffer(
+    buffer: &gst::Buffer,
+    in_info: &gst_audio::AudioInfo,
+    out_info: &gst_audio::AudioInfo,
+) -> Option<gst::Buffer> {
+    use byte_slice_cast::*;
+
+    let map = buffer.map_readable().ok()?;
+    let src = map.as_slice_of::<f32>().ok()?;
+
+    let channels = in_info.channels() as usize;
+    let samples = src.len() / channels;
+
+    // Input and output are either interleaved or fully planar without any padding
+    let index = |info: &gst_audio::AudioInfo, channel: usize, sample: usize| {
+        if info.layout() == gst_audio::AudioLayout::NonInterleaved {
+            channel * samples + sample
+        } else {
+            sample * channels + channel
+        }
+    };
+
+    let reordered;
+    let src = if in_info.layout() != out_info.layout() {
+        let mut dest = vec![0.0f32; src.len()];
+        for channel in 0..channels {
+            for sample in 0..samples {
+                dest[index(out_info, channel, sample)] = src[index(in_info, channel, sample)];
+            }
+        }
+        reordered = dest;
+        &reordered[..]
+    } else {
+        src
+    };
+
+    let mut outbuf = match out_info.format() {
+        gst_audio::AudioFormat::F32le => gst::Buffer::from_mut_slice(src.as_byte_slice().to_vec()),
+        gst_audio::AudioFormat::F64le => {
+            let dest = src.iter().map(|s| *s as f64).collect::<Vec<_>>();
+            gst::Buffer::from_mut_slice(dest.as_byte_slice().to_vec())
+        }
+        gst_audio::AudioFormat::S32le => {
+            let dest = src
+                .iter()
+                .map(|s| (s.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32)
+                .collect::<Vec<_>>();
+            gst::Buffer::from_mut_slice(dest.as_byte_slice().to_vec())
+        }
+        _ => return None,
+    };
+
+    {
+        let outbuf = outbuf.get_mut().unwrap();
+        buffer
+            .copy_into(
+                outbuf,
+                gst::BufferCopyFlags::FLAGS
+                    | gst::BufferCopyFlags::TIMESTAMPS
+                    | gst::BufferCopyFlags::META,
+                0,
+                None,
+            )
+            .ok()?;
+
+        // The audio meta describes the input buffer and has to be replaced
+        #[cfg(feature = "non-interleaved-audio")]
+        {
+            if let Some(meta) = outbuf.meta_mut::<gst_audio::AudioMeta>() {
+                meta.remove().ok()?;
+            }
+            if out_info.layout() == gst_audio::AudioLayout::NonInterleaved {
+                gst_audio::AudioMeta::add(outbuf, out_info, samples, &[]).ok()?;
+            }
+        }
+    }
+
+    Some(outbuf)
+}
+
+// Give each source pad its own stream id derived from the upstream one, together with the
+// stream object for it
+fn stream_start_for_pad(
+    event: gst::Event,
+    name: &str,
+    stream_type: gst::StreamType,
+    caps: Option<&gst::Caps>,
+) -> (gst::Event, Option<gst::Stream>) {
+    let stream_start = match event.view() {
+        gst::EventView::StreamStart(stream_start) => stream_start,
+        _ => return (event, None),
+    };
+
+    let stream_id = format!("{}/{}", stream_start.stream_id(), name);
+    let stream = gst::Stream::new(
+        Some(&stream_id),
+        caps,
+        stream_type,
+        stream_start.stream_flags(),
+    );
+    let mut builder = gst::event::StreamStart::builder(&stream_id)
+        .flags(stream_start.stream_flags())
+        .stream(stream.clone())
+        .seqnum(event.seqnum());
+    if let Some(group_id) = stream_start.group_id() {
+        builder = builder.group_id(group_id);
+    }
+    (builder.build(), Some(stream))
 }
@@ -1,81 +1,423 @@
 #![allow(non_camel_case_types, non_upper_case_globals, non_snake_case)]
 
-#[cfg_attr(
-    all(target_arch = "x86_64", target_os = "windows"),
-    link(name = "Processing.NDI.Lib.x64")
-)]
-#[cfg_attr(
-    all(target_arch = "x86", target_os = "windows"),
-    link(name = "Processing.NDI.Lib.x86")
-)]
-#[cfg_attr(
-    not(any(target_os = "windows", target_os = "macos")),
-    link(name = "ndi")
-)]
-extern "C" {
-    pub fn NDIlib_initialize() -> bool;
-    pub fn NDIlib_destroy();
-    pub fn NDIlib_find_create_v2(
-        p_create_settings: *const NDIlib_find_create_t,
-    ) -> NDIlib_find_instance_t;
-    pub fn NDIlib_find_destroy(p_instance: NDIlib_find_instance_t);
-    pub fn NDIlib_find_wait_for_sources(
-        p_instance: NDIlib_find_instance_t,
-        timeout_in_ms: u32,
-    ) -> bool;
-    pub fn NDIlib_find_get_current_sources(
-        p_instance: NDIlib_find_instance_t,
-        p_no_sources: *mut u32,
-    ) -> *const NDIlib_source_t;
-    pub fn NDIlib_recv_create_v3(
-        p_create_settings: *const NDIlib_recv_create_v3_t,
-    ) -> NDIlib_recv_instance_t;
-    pub fn NDIlib_recv_destroy(p_instance: NDIlib_recv_instance_t);
-    pub fn NDIlib_recv_set_tally(
-        p_instance: NDIlib_recv_instance_t,
-        p_tally: *const NDIlib_tally_t,
-    ) -> bool;
-    pub fn NDIlib_recv_send_metadata(
-        p_instance: NDIlib_recv_instance_t,
-        p_metadata: *const NDIlib_metadata_frame_t,
-    ) -> bool;
-    pub fn NDIlib_recv_capture_v3(
-        p_instance: NDIlib_recv_instance_t,
-        p_video_data: *mut NDIlib_video_frame_v2_t,
-        p_audio_data: *mut NDIlib_audio_frame_v3_t,
-        p_metadata: *mut NDIlib_metadata_frame_t,
-        timeout_in_ms: u32,
-    ) -> NDIlib_frame_type_e;
-    pub fn NDIlib_recv_free_video_v2(
-        p_instance: NDIlib_recv_instance_t,
-        p_video_data: *mut NDIlib_video_frame_v2_t,
-    );
-    pub fn NDIlib_recv_free_audio_v3(
-        p_instance: NDIlib_recv_instance_t,
-        p_audio_data: *mut NDIlib_audio_frame_v3_t,
-    );
-    pub fn NDIlib_recv_free_metadata(
-        p_instance: NDIlib_recv_instance_t,
-        p_metadata: *mut NDIlib_metadata_frame_t,
-    );
-    pub fn NDIlib_recv_get_queue(
-        p_instance: NDIlib_recv_instance_t,
-        p_total: *mut NDIlib_recv_queue_t,
-    );
-    pub fn NDIlib_send_create(
-        p_create_settings: *const NDIlib_send_create_t,
-    ) -> NDIlib_send_instance_t;
-    pub fn NDIlib_send_destroy(p_instance: NDIlib_send_instance_t);
-    pub fn NDIlib_send_send_video_v2(
-        p_instance: NDIlib_send_instance_t,
-        p_video_data: *const NDIlib_video_frame_v2_t,
-    );
-    pub fn NDIlib_send_send_audio_v3(
-        p_instance: NDIlib_send_instance_t,
-        p_audio_data: *const NDIlib_audio_frame_v3_t,
-    );
+use std::env;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+// The NDI library is loaded at runtime so that the plugin can be installed without the SDK
+// being present, and only fails once one of the elements is actually used.
+//
+// This declares the functions like an extern "C" block, but calls go through function
+// pointers that are resolved in `load()`. Functions that don't exist in all supported SDK
+// versions are optional: calling them returns `None` if they're missing, and whether they
+// exist can be checked with the function of the same name in the `available` module.
+macro_rules! ndi_functions {
+    (
+        required {
+            $($(#[$attr:meta])* pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*
+        }
+        optional {
+            $($(#[$opt_attr:meta])* pub fn $opt_name:ident($($opt_arg:ident: $opt_ty:ty),* $(,)?) $(-> $opt_ret:ty)?;)*
+        }
+    ) => {
+        struct Functions {
+            $($(#[$attr])* $name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+            $($(#[$opt_attr])* $opt_name: Option<unsafe extern "C" fn($($opt_ty),*) $(-> $opt_ret)?>,)*
+        }
+
+        impl Functions {
+            unsafe fn load(library: &Library) -> Result<Self, String> {
+                Ok(Functions {
+                    $($(#[$attr])* $name: {
+                        let ptr = library.symbol(stringify!($name))?;
+                        std::mem::transmute::<*mut ::std::os::raw::c_void, unsafe extern "C" fn($($ty),*) $(-> $ret)?>(ptr)
+                    },)*
+                    $($(#[$opt_attr])* $opt_name: library.symbol(stringify!($opt_name)).ok().map(|ptr| {
+                        std::mem::transmute::<*mut ::std::os::raw::c_void, unsafe extern "C" fn($($opt_ty),*) $(-> $opt_ret)?>(ptr)
+                    }),)*
+                })
+            }
+        }
+
+        $(
+            $(#[$attr])*
+            #[allow(clippy::missing_safety_doc)]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                (functions().$name)($($arg),*)
+            }
+        )*
+
+        $(
+            $(#[$opt_attr])*
+            #[allow(clippy::missing_safety_doc)]
+            pub unsafe fn $opt_name($($opt_arg: $opt_ty),*) -> Option<ndi_functions!(@ret $($opt_ret)?)> {
+                functions().$opt_name.map(|f| f($($opt_arg),*))
+            }
+        )*
+
+        pub mod available {
+            $(
+                $(#[$opt_attr])*
+                pub fn $opt_name() -> bool {
+                    super::functions().$opt_name.is_some()
+                }
+            )*
+        }
+    };
+    (@ret) => { () };
+    (@ret $ret:ty) => { $ret };
+}
+
+// The library is kept loaded together with the functions resolved from it until the process
+// exits
+static FUNCTIONS: OnceCell<(Library, Functions)> = OnceCell::new();
+
+fn functions() -> &'static Functions {
+    &FUNCTIONS.get().expect("NDI library not loaded").1
+}
+
+// Loads the NDI library and resolves all functions. The given path is tried before the
+// default locations, but once the library is loaded successfully later calls don't do
+// anything anymore.
+pub fn load(library_path: Option<&Path>) -> Result<(), String> {
+    FUNCTIONS
+        .get_or_try_init(|| unsafe {
+            #[cfg(feature = "test-backend")]
+            let library = {
+                let _ = library_path;
+                Library::Fake
+            };
+            #[cfg(not(feature = "test-backend"))]
+            let library = Library::open(library_path)?;
+            let functions = Functions::load(&library)?;
+            // Audio needs either the NDI SDK 4 functions or the older ones
+            if (functions.NDIlib_recv_capture_v3.is_none()
+                && functions.NDIlib_recv_capture_v2.is_none())
+                || (functions.NDIlib_recv_free_audio_v3.is_none()
+                    && functions.NDIlib_recv_free_audio_v2.is_none())
+                || (functions.NDIlib_send_send_audio_v3.is_none()
+                    && functions.NDIlib_send_send_audio_v2.is_none())
+            {
+                return Err(String::from(
+                    "NDI library has no audio capture functions, at least NDI SDK 3.5 is required",
+                ));
+            }
+            Ok((library, functions))
+        })
+        .map(|_| ())
+}
+
+#[cfg(all(target_os = "windows", target_arch = "x86"))]
+const LIBRARY_NAMES: &[&str] = &["Processing.NDI.Lib.x86.dll"];
+#[cfg(all(target_os = "windows", not(target_arch = "x86")))]
+const LIBRARY_NAMES: &[&str] = &["Processing.NDI.Lib.x64.dll"];
+#[cfg(target_os = "macos")]
+const LIBRARY_NAMES: &[&str] = &["libndi.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LIBRARY_NAMES: &[&str] = &["libndi.so.6", "libndi.so.5", "libndi.so.4", "libndi.so"];
+
+// Directories set by the NDI runtime installers
+const RUNTIME_DIR_VARIABLES: &[&str] = &[
+    "NDI_RUNTIME_DIR_V6",
+    "NDI_RUNTIME_DIR_V5",
+    "NDI_RUNTIME_DIR_V4",
+    "NDI_RUNTIME_DIR_V3",
+];
+
+// Either the library itself or the directory containing it
+fn path_candidates(path: &Path) -> Vec<PathBuf> {
+    if path.is_dir() {
+        LIBRARY_NAMES.iter().map(|name| path.join(name)).collect()
+    } else {
+        vec![path.to_path_buf()]
+    }
+}
+
+#[cfg_attr(feature = "test-backend", allow(dead_code))]
+fn library_candidates(library_path: Option<&Path>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    if let Some(path) = library_path {
+        candidates.extend(path_candidates(path));
+    }
+
+    if let Some(path) = env::var_os("NDI_LIBRARY_PATH") {
+        candidates.extend(path_candidates(Path::new(&path)));
+    }
+
+    for variable in RUNTIME_DIR_VARIABLES {
+        if let Some(dir) = env::var_os(variable) {
+            let dir = PathBuf::from(dir);
+            candidates.extend(LIBRARY_NAMES.iter().map(|name| dir.join(name)));
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        candidates.push(PathBuf::from(
+            "/Library/NDI SDK for Apple/lib/macOS/libndi.dylib",
+        ));
+        candidates.push(PathBuf::from("/usr/local/lib/libndi.dylib"));
+    }
+
+    // Plain names are looked up in the default library search path
+    candidates.extend(LIBRARY_NAMES.iter().map(PathBuf::from));
+
+    candidates
+}
+
+enum Library {
+    Loaded(libloading::Library),
+    // Functions come from the in-process fake in ndifake.rs instead
+    #[cfg(feature = "test-backend")]
+    Fake,
 }
 
+impl Library {
+    #[cfg_attr(feature = "test-backend", allow(dead_code))]
+    unsafe fn open(library_path: Option<&Path>) -> Result<Self, String> {
+        let candidates = library_candidates(library_path);
+
+        for candidate in &candidates {
+            if let Ok(library) = libloading::Library::new(candidate) {
+                return Ok(Library::Loaded(library));
+            }
+        }
+
+        Err(format!(
+            "Could not load the NDI library, tried {}. Install the NDI runtime or point \
+             NDI_LIBRARY_PATH or the ndi-library-path property to the library",
+            candidates
+                .iter()
+                .map(|candidate| candidate.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+
+    unsafe fn symbol(&self, name: &str) -> Result<*mut ::std::os::raw::c_void, String> {
+        let ptr = match self {
+            Library::Loaded(library) => library
+                .get::<*mut ::std::os::raw::c_void>(name.as_bytes())
+                .map(|symbol| *symbol)
+                .unwrap_or(std::ptr::null_mut()),
+            #[cfg(feature = "test-backend")]
+            Library::Fake => crate::ndifake::symbol(name),
+        };
+        if ptr.is_null() {
+            Err(format!(
+                "NDI library has no function {}, at least NDI SDK 3.5 is required",
+                name
+            ))
+        } else {
+            Ok(ptr)
+        }
+    }
+}
+
+ndi_functions! {
+    required {
+        pub fn NDIlib_initialize() -> bool;
+        pub fn NDIlib_destroy();
+        pub fn NDIlib_find_create_v2(
+            p_create_settings: *const NDIlib_find_create_t,
+        ) -> NDIlib_find_instance_t;
+        pub fn NDIlib_find_destroy(p_instance: NDIlib_find_instance_t);
+        pub fn NDIlib_find_wait_for_sources(
+            p_instance: NDIlib_find_instance_t,
+            timeout_in_ms: u32,
+        ) -> bool;
+        pub fn NDIlib_find_get_current_sources(
+            p_instance: NDIlib_find_instance_t,
+            p_no_sources: *mut u32,
+        ) -> *const NDIlib_source_t;
+        pub fn NDIlib_recv_create_v3(
+            p_create_settings: *const NDIlib_recv_create_v3_t,
+        ) -> NDIlib_recv_instance_t;
+        pub fn NDIlib_recv_destroy(p_instance: NDIlib_recv_instance_t);
+        pub fn NDIlib_recv_connect(p_instance: NDIlib_recv_instance_t, p_src: *const NDIlib_source_t);
+        pub fn NDIlib_recv_set_tally(
+            p_instance: NDIlib_recv_instance_t,
+            p_tally: *const NDIlib_tally_t,
+        ) -> bool;
+        pub fn NDIlib_recv_send_metadata(
+            p_instance: NDIlib_recv_instance_t,
+            p_metadata: *const NDIlib_metadata_frame_t,
+        ) -> bool;
+        pub fn NDIlib_recv_get_no_connections(
+            p_instance: NDIlib_recv_instance_t,
+        ) -> ::std::os::raw::c_int;
+        pub fn NDIlib_recv_free_video_v2(
+            p_instance: NDIlib_recv_instance_t,
+            p_video_data: *mut NDIlib_video_frame_v2_t,
+        );
+        pub fn NDIlib_recv_free_metadata(
+            p_instance: NDIlib_recv_instance_t,
+            p_metadata: *mut NDIlib_metadata_frame_t,
+        );
+        pub fn NDIlib_recv_get_queue(
+            p_instance: NDIlib_recv_instance_t,
+            p_total: *mut NDIlib_recv_queue_t,
+        );
+        pub fn NDIlib_send_create(
+            p_create_settings: *const NDIlib_send_create_t,
+        ) -> NDIlib_send_instance_t;
+        pub fn NDIlib_send_destroy(p_instance: NDIlib_send_instance_t);
+        pub fn NDIlib_routing_create(
+            p_create_settings: *const NDIlib_routing_create_t,
+        ) -> NDIlib_routing_instance_t;
+        pub fn NDIlib_routing_destroy(p_instance: NDIlib_routing_instance_t);
+        pub fn NDIlib_routing_change(
+            p_instance: NDIlib_routing_instance_t,
+            p_source: *const NDIlib_source_t,
+        ) -> bool;
+        pub fn NDIlib_routing_clear(p_instance: NDIlib_routing_instance_t) -> bool;
+        pub fn NDIlib_send_send_video_v2(
+            p_instance: NDIlib_send_instance_t,
+            p_video_data: *const NDIlib_video_frame_v2_t,
+        );
+        pub fn NDIlib_send_send_video_async_v2(
+            p_instance: NDIlib_send_instance_t,
+            p_video_data: *const NDIlib_video_frame_v2_t,
+        );
+        pub fn NDIlib_send_send_metadata(
+            p_instance: NDIlib_send_instance_t,
+            p_metadata: *const NDIlib_metadata_frame_t,
+        );
+        pub fn NDIlib_send_capture(
+            p_instance: NDIlib_send_instance_t,
+            p_metadata: *mut NDIlib_metadata_frame_t,
+            timeout_in_ms: u32,
+        ) -> NDIlib_frame_type_e;
+        pub fn NDIlib_send_free_metadata(
+            p_instance: NDIlib_send_instance_t,
+            p_metadata: *const NDIlib_metadata_frame_t,
+        );
+        pub fn NDIlib_send_get_no_connections(
+            p_instance: NDIlib_send_instance_t,
+            timeout_in_ms: u32,
+        ) -> ::std::os::raw::c_int;
+        pub fn NDIlib_send_clear_connection_metadata(p_instance: NDIlib_send_instance_t);
+        pub fn NDIlib_send_add_connection_metadata(
+            p_instance: NDIlib_send_instance_t,
+            p_metadata: *const NDIlib_metadata_frame_t,
+        );
+        pub fn NDIlib_send_set_failover(
+            p_instance: NDIlib_send_instance_t,
+            p_failover_source: *const NDIlib_source_t,
+        );
+        pub fn NDIlib_send_get_tally(
+            p_instance: NDIlib_send_instance_t,
+            p_tally: *mut NDIlib_tally_t,
+            timeout_in_ms: u32,
+        ) -> bool;
+    }
+    optional {
+        pub fn NDIlib_version() -> *const ::std::os::raw::c_char;
+        // Receiver-side recording, only part of some SDK versions
+        pub fn NDIlib_recv_free_string(
+            p_instance: NDIlib_recv_instance_t,
+            p_string: *const ::std::os::raw::c_char,
+        );
+        pub fn NDIlib_recv_recording_start(
+            p_instance: NDIlib_recv_instance_t,
+            p_filename_hint: *const ::std::os::raw::c_char,
+        ) -> bool;
+        pub fn NDIlib_recv_recording_stop(p_instance: NDIlib_recv_instance_t) -> bool;
+        pub fn NDIlib_recv_recording_set_audio_level(
+            p_instance: NDIlib_recv_instance_t,
+            level_dB: f32,
+        ) -> bool;
+        pub fn NDIlib_recv_recording_is_supported(p_instance: NDIlib_recv_instance_t) -> bool;
+        pub fn NDIlib_recv_recording_is_recording(p_instance: NDIlib_recv_instance_t) -> bool;
+        pub fn NDIlib_recv_recording_get_filename(
+            p_instance: NDIlib_recv_instance_t,
+        ) -> *const ::std::os::raw::c_char;
+        pub fn NDIlib_recv_recording_get_error(
+            p_instance: NDIlib_recv_instance_t,
+        ) -> *const ::std::os::raw::c_char;
+        pub fn NDIlib_recv_recording_get_times(
+            p_instance: NDIlib_recv_instance_t,
+            p_times: *mut NDIlib_recv_recording_time_t,
+        ) -> bool;
+        pub fn NDIlib_recv_get_web_control(
+            p_instance: NDIlib_recv_instance_t,
+        ) -> *const ::std::os::raw::c_char;
+        pub fn NDIlib_recv_ptz_is_supported(p_instance: NDIlib_recv_instance_t) -> bool;
+        pub fn NDIlib_send_get_source_name(
+            p_instance: NDIlib_send_instance_t,
+        ) -> *const NDIlib_source_t;
+        pub fn NDIlib_framesync_create(
+            p_receiver: NDIlib_recv_instance_t,
+        ) -> NDIlib_framesync_instance_t;
+        pub fn NDIlib_framesync_destroy(p_instance: NDIlib_framesync_instance_t);
+        pub fn NDIlib_framesync_capture_video(
+            p_instance: NDIlib_framesync_instance_t,
+            p_video_data: *mut NDIlib_video_frame_v2_t,
+            field_type: NDIlib_frame_format_type_e,
+        );
+        pub fn NDIlib_framesync_free_video(
+            p_instance: NDIlib_framesync_instance_t,
+            p_video_data: *mut NDIlib_video_frame_v2_t,
+        );
+        pub fn NDIlib_framesync_audio_queue_depth(
+            p_instance: NDIlib_framesync_instance_t,
+        ) -> ::std::os::raw::c_int;
+        // Since NDI SDK 4, NDI SDK 3.5 only has the v2 functions for planar float audio
+        pub fn NDIlib_recv_capture_v3(
+            p_instance: NDIlib_recv_instance_t,
+            p_video_data: *mut NDIlib_video_frame_v2_t,
+            p_audio_data: *mut NDIlib_audio_frame_v3_t,
+            p_metadata: *mut NDIlib_metadata_frame_t,
+            timeout_in_ms: u32,
+        ) -> NDIlib_frame_type_e;
+        pub fn NDIlib_recv_free_audio_v3(
+            p_instance: NDIlib_recv_instance_t,
+            p_audio_data: *mut NDIlib_audio_frame_v3_t,
+        );
+        pub fn NDIlib_send_send_audio_v3(
+            p_instance: NDIlib_send_instance_t,
+            p_audio_data: *const NDIlib_audio_frame_v3_t,
+        );
+        // Replaced by the v3 functions in NDI SDK 4
+        pub fn NDIlib_recv_capture_v2(
+            p_instance: NDIlib_recv_instance_t,
+            p_video_data: *mut NDIlib_video_frame_v2_t,
+            p_audio_data: *mut NDIlib_audio_frame_v2_t,
+            p_metadata: *mut NDIlib_metadata_frame_t,
+            timeout_in_ms: u32,
+        ) -> NDIlib_frame_type_e;
+        pub fn NDIlib_recv_free_audio_v2(
+            p_instance: NDIlib_recv_instance_t,
+            p_audio_data: *const NDIlib_audio_frame_v2_t,
+        );
+        pub fn NDIlib_send_send_audio_v2(
+            p_instance: NDIlib_send_instance_t,
+            p_audio_data: *const NDIlib_audio_frame_v2_t,
+        );
+        // Since NDI SDK 5
+        pub fn NDIlib_framesync_capture_audio_v2(
+            p_instance: NDIlib_framesync_instance_t,
+            p_audio_data: *mut NDIlib_audio_frame_v3_t,
+            sample_rate: ::std::os::raw::c_int,
+            no_channels: ::std::os::raw::c_int,
+            no_samples: ::std::os::raw::c_int,
+        );
+        pub fn NDIlib_framesync_free_audio_v2(
+            p_instance: NDIlib_framesync_instance_t,
+            p_audio_data: *mut NDIlib_audio_frame_v3_t,
+        );
+        // Since NDI SDK 5.5
+        pub fn NDIlib_send_create_v2(
+            p_create_settings: *const NDIlib_send_create_t,
+            p_config_data: *const ::std::os::raw::c_char,
+        ) -> NDIlib_send_instance_t;
+    }
+}
+
+pub type NDIlib_framesync_instance_t = *mut ::std::os::raw::c_void;
+
 pub type NDIlib_find_instance_t = *mut ::std::os::raw::c_void;
 
 #[repr(C)]
@@ -251,6 +593,15 @@ pub struct NDIlib_send_create_t {
 
 pub type NDIlib_send_instance_t = *mut ::std::os::raw::c_void;
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct NDIlib_routing_create_t {
+    pub p_ndi_name: *const ::std::os::raw::c_char,
+    pub p_groups: *const ::std::os::raw::c_char,
+}
+
+pub type NDIlib_routing_instance_t = *mut ::std::os::raw::c_void;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct NDIlib_tally_t {
@@ -266,6 +617,14 @@ pub struct NDIlib_recv_queue_t {
     pub metadata_frames: i32,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct NDIlib_recv_recording_time_t {
+    pub no_frames: i64,
+    pub start_time: i64,
+    pub last_time: i64,
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct NDIlib_metadata_frame_t {
@@ -291,6 +650,19 @@ pub struct NDIlib_video_frame_v2_t {
     pub timestamp: i64,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct NDIlib_audio_frame_v2_t {
+    pub sample_rate: ::std::os::raw::c_int,
+    pub no_channels: ::std::os::raw::c_int,
+    pub no_samples: ::std::os::raw::c_int,
+    pub timecode: i64,
+    pub p_data: *const ::std::os::raw::c_float,
+    pub channel_stride_in_bytes: ::std::os::raw::c_int,
+    pub p_metadata: *const ::std::os::raw::c_char,
+    pub timestamp: i64,
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct NDIlib_audio_frame_v3_t {
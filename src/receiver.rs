@@ -7,7 +7,8 @@ use byte_slice_cast::*;
 
 use std::cmp;
 use std::collections::VecDeque;
-use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::mem;
+use std::sync::{mpsc, Arc, Condvar, Mutex, Weak};
 use std::thread;
 
 use super::*;
@@ -20,6 +21,19 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
+// Per-frame receive latency and queue residency, logged as structures like the output of
+// tracers so that it can be analyzed with the usual tooling, e.g. GST_DEBUG=ndiframetiming:7
+static TIMING_CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
+    gst::DebugCategory::new(
+        "ndiframetiming",
+        gst::DebugColorFlags::empty(),
+        Some("NewTek NDI frame timing"),
+    )
+});
+
+// Returned for frames that are dropped instead of failing the whole stream
+const FLOW_DROPPED: gst::FlowError = gst::FlowError::CustomError;
+
 #[derive(Clone)]
 pub struct Receiver(Arc<ReceiverInner>);
 
@@ -66,9 +80,19 @@ impl AudioInfo {
                 .build()),
         }
     }
+
+    pub fn codec_name(&self) -> Option<&'static str> {
+        match self {
+            AudioInfo::AudioInfo(..) => None,
+            #[cfg(feature = "advanced-sdk")]
+            AudioInfo::OpusInfo { .. } => Some("Opus"),
+            #[cfg(feature = "advanced-sdk")]
+            AudioInfo::AacInfo { .. } => Some("MPEG-4 AAC"),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VideoInfo {
     VideoInfo(gst_video::VideoInfo),
     #[cfg(feature = "advanced-sdk")]
@@ -166,6 +190,18 @@ impl VideoInfo {
                 .build()),
         }
     }
+
+    pub fn codec_name(&self) -> Option<&'static str> {
+        match self {
+            VideoInfo::VideoInfo(..) => None,
+            #[cfg(feature = "advanced-sdk")]
+            VideoInfo::SpeedHQInfo { .. } => Some("SpeedHQ"),
+            #[cfg(feature = "advanced-sdk")]
+            VideoInfo::H264Info { .. } => Some("H.264"),
+            #[cfg(feature = "advanced-sdk")]
+            VideoInfo::H265Info { .. } => Some("H.265"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -174,6 +210,17 @@ pub enum Buffer {
     Video(gst::Buffer, VideoInfo),
 }
 
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub video_frames: u64,
+    pub audio_buffers: u64,
+    pub dropped: u64,
+    // Frames dropped because the queue was full, also counted in dropped
+    pub overruns: u64,
+    pub bytes_received: u64,
+    pub reconnects: u64,
+}
+
 #[derive(Debug)]
 pub enum ReceiverItem {
     Buffer(Buffer),
@@ -182,17 +229,100 @@ pub enum ReceiverItem {
     Error(gst::FlowError),
 }
 
+#[derive(Debug, Clone)]
+pub struct ReceiverSettings {
+    pub ndi_name: Option<String>,
+    // Comma separated list of candidate addresses, tried in order until one delivers frames
+    pub url_address: Option<String>,
+    pub receiver_ndi_name: String,
+    pub connect_timeout: u32,
+    // Number of times the connection is retried if no frame arrives within the connect timeout
+    pub connect_retries: u32,
+    // Initial wait in ms before retrying, doubled for every further retry
+    pub retry_backoff: u32,
+    pub timeout: u32,
+    // Signal EOS instead of an error when the sender disconnects or times out
+    pub eos_on_disconnect: bool,
+    // Time in ms the sender has to stay disconnected before that counts as a disconnect
+    pub disconnect_timeout: u32,
+    pub max_queue_length: usize,
+    // Whether the oldest queued or the new frames are dropped when the queue is full
+    pub leaky: QueueLeaky,
+    // Keep the most recent video frame received while paused and output it when playing again
+    pub skip_to_live: bool,
+    pub bandwidth: NDIlib_recv_bandwidth_e,
+    pub receive_audio: bool,
+    pub color_format: NDIlib_recv_color_format_e,
+    pub timestamp_mode: TimestampMode,
+    pub slave_method: AudioSlaveMethod,
+    // Signal framerate 0/1 and take video buffer durations from the timestamps
+    pub variable_framerate: bool,
+    // Output video at exactly this framerate by dropping and repeating frames if set
+    pub force_framerate: Option<gst::Fraction>,
+    // Override the picture aspect ratio signalled by the sender or directly the pixel aspect
+    // ratio, which takes precedence, if set
+    pub force_aspect_ratio: Option<gst::Fraction>,
+    pub pixel_aspect_ratio: Option<gst::Fraction>,
+    // Fold audio with more than two channels down to stereo
+    pub downmix_to_stereo: bool,
+    // Re-chunk audio so that buffer boundaries coincide with video frame boundaries
+    pub align_audio: bool,
+    // Number of threads copying each video frame, 0 for the number of CPUs
+    pub n_threads: u32,
+    // Overrides the channel layout signalled by the sender if set
+    pub channel_positions: Option<Vec<gst_audio::AudioChannelPosition>>,
+    // dB above the NDI +4 dBU reference level that map to digital full scale
+    pub reference_level: i32,
+    // Shared with other receivers instead of using a per-receiver mapping if set
+    pub observations: Option<Observations>,
+}
+
+impl ReceiverSettings {
+    fn url_addresses(&self) -> Vec<Option<&str>> {
+        let url_addresses = self
+            .url_address
+            .iter()
+            .flat_map(|url_address| url_address.split(','))
+            .map(str::trim)
+            .filter(|url_address| !url_address.is_empty())
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        if url_addresses.is_empty() {
+            vec![None]
+        } else {
+            url_addresses
+        }
+    }
+}
+
 pub struct ReceiverInner {
     queue: ReceiverQueue,
-    max_queue_length: usize,
 
     observations: Observations,
 
-    element: glib::WeakRef<gst_base::BaseSrc>,
-    timestamp_mode: TimestampMode,
+    rebase: Arc<Mutex<TimestampRebase>>,
+
+    audio_slave: Arc<Mutex<AudioSlave>>,
+
+    // PTS of the previous video buffer for the variable framerate mode
+    last_video_pts: Mutex<Option<gst::ClockTime>>,
 
-    timeout: u32,
-    connect_timeout: u32,
+    retimer: Mutex<Retimer>,
+
+    audio_chunker: Mutex<AudioChunker>,
+
+    video_pool: Mutex<SizedBufferPool>,
+    audio_pool: Mutex<SizedBufferPool>,
+
+    // Threads helping to copy each video frame
+    copy_pool: CopyPool,
+
+    // Replaced by the capture thread if the color format is changed
+    recv: Arc<Mutex<RecvInstance>>,
+
+    element: glib::WeakRef<gst_base::BaseSrc>,
+    settings: ReceiverSettings,
 
     thread: Mutex<Option<std::thread::JoinHandle<()>>>,
 }
@@ -213,21 +343,381 @@ struct ReceiverQueueInner {
     // Queue containing our buffers. This holds at most 5 buffers at a time.
     //
     // On timeout/error will contain a single item and then never be filled again
-    buffer_queue: VecDeque<Buffer>,
+    // Queued buffers together with the time they were queued at
+    buffer_queue: VecDeque<(Buffer, time::Instant)>,
+
+    // Most recent video frame received while not playing if skipping to live
+    live_frame: Option<Buffer>,
 
     error: Option<gst::FlowError>,
     timeout: bool,
+
+    // If a sender is currently connected
+    connected: bool,
+
+    stats: Stats,
+
+    video_jitter: ArrivalJitter,
+    audio_jitter: ArrivalJitter,
+
+    // Serialized events for splice points that have to be sent before the next buffer
+    pending_events: Vec<gst::Event>,
+
+    status: SourceStatus,
+
+    // Frame types to capture as selected by a select-streams event
+    video_selected: bool,
+    audio_selected: bool,
+
+    // Color format the receiver should be recreated with
+    pending_color_format: Option<ndisys::NDIlib_recv_color_format_e>,
+}
+
+// Capabilities the sender announces, updated on status change frames
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceStatus {
+    pub web_control: Option<String>,
+    pub ptz_supported: bool,
+    pub recording_supported: bool,
 }
 
+// Smoothed deviation of the intervals between arriving buffers from their durations, like the
+// RTP interarrival jitter
+#[derive(Debug, Default, Clone, Copy)]
+struct ArrivalJitter {
+    last_arrival: Option<time::Instant>,
+    jitter: f64,
+}
+
+impl ArrivalJitter {
+    fn update(&mut self, arrival: time::Instant, duration: Option<gst::ClockTime>) {
+        if let (Some(last_arrival), Some(duration)) = (self.last_arrival, duration) {
+            let interval = arrival.saturating_duration_since(last_arrival).as_nanos() as f64;
+            let deviation = (interval - duration.nseconds() as f64).abs();
+            self.jitter += (deviation - self.jitter) / 16.0;
+        }
+        self.last_arrival = Some(arrival);
+    }
+
+    fn jitter(&self) -> gst::ClockTime {
+        gst::ClockTime::from_nseconds(self.jitter as u64)
+    }
+}
+
+const CONNECTION_CHECK_INTERVAL: time::Duration = time::Duration::from_millis(200);
+
+// Interval at which warnings are posted while no frames arrive, until the timeout is reached
+const MISSING_FRAMES_WARNING_INTERVAL: u128 = 1000;
+
 const WINDOW_LENGTH: u64 = 512;
 const WINDOW_DURATION: u64 = 2_000_000_000;
 
-#[derive(Clone)]
-struct Observations(Arc<Mutex<ObservationsInner>>);
+// Audio and video of the same sender can be slightly apart, only larger backwards steps are
+// considered a jump of the sender's timeline
+const BACKWARDS_JUMP_THRESHOLD: gst::ClockTime = gst::ClockTime::from_seconds(1);
+
+// Offset added to timestamps taken from the sender after its timeline jumped backwards, e.g.
+// because it was restarted or loops its content, so that the output stays monotonic
+#[derive(Debug, Default)]
+struct TimestampRebase {
+    offset: gst::ClockTime,
+    last_pts: Option<gst::ClockTime>,
+    // Set when the epoch was reset so the next buffer is marked as discontinuous
+    discont: bool,
+}
+
+// Retimes video to a fixed framerate by dropping frames or repeating the previous one
+#[derive(Debug, Default)]
+struct Retimer {
+    base: Option<gst::ClockTime>,
+    next_slot: u64,
+    last: Option<gst::Buffer>,
+}
+
+impl Retimer {
+    fn slot_time(&self, fps: gst::Fraction, slot: u64) -> Option<gst::ClockTime> {
+        let offset = gst::ClockTime::SECOND
+            .nseconds()
+            .mul_div_floor(slot * fps.denom() as u64, fps.numer() as u64)?;
+        self.base?
+            .checked_add(gst::ClockTime::from_nseconds(offset))
+    }
+
+    fn retimed_buffer(&self, fps: gst::Fraction, buffer: &gst::Buffer, slot: u64) -> gst::Buffer {
+        let mut buffer = buffer.clone();
+        {
+            let buffer = buffer.make_mut();
+            let pts = self.slot_time(fps, slot);
+            buffer.set_pts(pts);
+            buffer.set_duration(
+                self.slot_time(fps, slot + 1)
+                    .zip(pts)
+                    .map(|(end, pts)| end - pts),
+            );
+        }
+        buffer
+    }
+
+    fn retime(&mut self, fps: gst::Fraction, buffer: gst::Buffer) -> Vec<gst::Buffer> {
+        let pts = match buffer.pts() {
+            Some(pts) => pts,
+            None => return vec![buffer],
+        };
+
+        if buffer.flags().contains(gst::BufferFlags::DISCONT)
+            || self.base.map(|base| pts < base).unwrap_or(true)
+        {
+            *self = Retimer {
+                base: Some(pts),
+                ..Default::default()
+            };
+        }
+
+        let slot = match (pts - self.base.unwrap()).nseconds().mul_div_round(
+            fps.numer() as u64,
+            gst::ClockTime::SECOND.nseconds() * fps.denom() as u64,
+        ) {
+            Some(slot) => slot,
+            None => return vec![buffer],
+        };
+
+        // There already was a frame for this slot
+        if slot < self.next_slot {
+            return vec![];
+        }
+
+        // Fill the gap with the previous frame, unless the sender stalled for more than a
+        // second and it would be a burst of stale frames
+        let mut buffers = Vec::new();
+        if let Some(ref last) = self.last {
+            if (slot - self.next_slot) * (fps.denom() as u64) <= fps.numer() as u64 {
+                for gap_slot in self.next_slot..slot {
+                    let mut buffer = self.retimed_buffer(fps, last, gap_slot);
+                    buffer.make_mut().unset_flags(gst::BufferFlags::DISCONT);
+                    buffers.push(buffer);
+                }
+            }
+        }
+
+        buffers.push(self.retimed_buffer(fps, &buffer, slot));
+        self.last = Some(buffer);
+        self.next_slot = slot + 1;
+
+        buffers
+    }
+}
+
+// Pool for buffers of a single size that is replaced whenever a different size is needed, so
+// that no buffers are allocated while receiving a stream with a constant format
+#[derive(Debug, Default)]
+struct SizedBufferPool(Option<(usize, gst::BufferPool)>);
+
+impl SizedBufferPool {
+    fn acquire(&mut self, size: usize) -> Option<gst::Buffer> {
+        if size == 0 {
+            return Some(gst::Buffer::new());
+        }
+
+        if self.0.as_ref().map(|(pool_size, _)| *pool_size) != Some(size) {
+            if let Some((_, pool)) = self.0.take() {
+                let _ = pool.set_active(false);
+            }
+
+            let pool = gst::BufferPool::new();
+            let mut config = pool.config();
+            config.set_params(None, size as u32, 2, 0);
+            pool.set_config(config).ok()?;
+            pool.set_active(true).ok()?;
+            self.0 = Some((size, pool));
+        }
+
+        self.0.as_ref()?.1.acquire_buffer(None).ok()
+    }
+}
+
+// Re-chunks raw audio so that buffer boundaries coincide with video frame boundaries
+#[derive(Debug, Default)]
+struct AudioChunker {
+    // PTS and duration of the last video frame
+    video: Option<(gst::ClockTime, gst::ClockTime)>,
+    // Samples of every channel that were not output yet and the PTS of the first one
+    pending: Vec<Vec<f32>>,
+    pending_pts: Option<gst::ClockTime>,
+    discont: bool,
+}
+
+impl AudioChunker {
+    fn video_frame(&mut self, buffer: &gst::Buffer) {
+        if let Some((pts, duration)) = buffer.pts().zip(buffer.duration()) {
+            if duration > gst::ClockTime::ZERO {
+                self.video = Some((pts, duration));
+            }
+        }
+    }
+
+    fn push(&mut self, buffer: gst::Buffer, info: &gst_audio::AudioInfo) -> Vec<gst::Buffer> {
+        let ((video_pts, video_duration), pts) = match self.video.zip(buffer.pts()) {
+            Some(v) => v,
+            None => return vec![buffer],
+        };
+        let channels = info.channels() as usize;
+        let rate = info.rate() as u64;
+        if channels == 0 || rate == 0 {
+            return vec![buffer];
+        }
+
+        let pending_samples = self.pending.first().map(Vec::len).unwrap_or(0) as u64;
+        let expected_pts = self.pending_pts.map(|pending_pts| {
+            pending_pts
+                + gst::ClockTime::SECOND
+                    .mul_div_floor(pending_samples, rate)
+                    .unwrap()
+        });
+        let drifted = expected_pts
+            .map(|expected_pts| {
+                let diff = if expected_pts > pts {
+                    expected_pts - pts
+                } else {
+                    pts - expected_pts
+                };
+                diff > video_duration
+            })
+            .unwrap_or(true);
+        if buffer.flags().contains(gst::BufferFlags::DISCONT)
+            || self.pending.len() != channels
+            || drifted
+        {
+            *self = AudioChunker {
+                video: self.video,
+                pending: vec![Vec::new(); channels],
+                pending_pts: Some(pts),
+                discont: true,
+            };
+        }
+
+        {
+            let map = match buffer.map_readable() {
+                Ok(map) => map,
+                Err(_) => return vec![],
+            };
+            let samples = match map.as_slice_of::<f32>() {
+                Ok(samples) => samples,
+                Err(_) => return vec![],
+            };
+            let frames = samples.len() / channels;
+            for (channel, pending) in self.pending.iter_mut().enumerate() {
+                if info.layout() == gst_audio::AudioLayout::NonInterleaved {
+                    pending.extend_from_slice(&samples[channel * frames..][..frames]);
+                } else {
+                    pending.extend(samples.iter().skip(channel).step_by(channels).take(frames));
+                }
+            }
+        }
+
+        let mut buffers = Vec::new();
+        loop {
+            let start = self.pending_pts.unwrap();
+
+            // Up to the next video frame boundary after the start
+            let offset = (start.nseconds() as i128 - video_pts.nseconds() as i128)
+                .div_euclid(video_duration.nseconds() as i128);
+            let end =
+                video_pts.nseconds() as i128 + (offset + 1) * video_duration.nseconds() as i128;
+            let chunk = cmp::max(
+                ((end - start.nseconds() as i128) as u64)
+                    .mul_div_round(rate, gst::ClockTime::SECOND.nseconds())
+                    .unwrap_or(0),
+                1,
+            ) as usize;
+
+            if self.pending[0].len() < chunk {
+                break;
+            }
+
+            let mut data = vec![0.0f32; chunk * channels];
+            for (channel, pending) in self.pending.iter_mut().enumerate() {
+                for (i, sample) in pending.drain(..chunk).enumerate() {
+                    if info.layout() == gst_audio::AudioLayout::NonInterleaved {
+                        data[channel * chunk + i] = sample;
+                    } else {
+                        data[i * channels + channel] = sample;
+                    }
+                }
+            }
+
+            let duration = gst::ClockTime::SECOND
+                .mul_div_floor(chunk as u64, rate)
+                .unwrap();
+            let mut buffer = gst::Buffer::from_mut_slice(data.as_byte_slice().to_vec());
+            {
+                let buffer = buffer.get_mut().unwrap();
+                buffer.set_pts(start);
+                buffer.set_duration(duration);
+                if self.discont {
+                    buffer.set_flags(gst::BufferFlags::DISCONT);
+                    self.discont = false;
+                }
+
+                #[cfg(feature = "non-interleaved-audio")]
+                {
+                    if gst_audio::AudioMeta::add(buffer, info, chunk, &[]).is_err() {
+                        return vec![];
+                    }
+                }
+            }
+
+            buffers.push(buffer);
+            self.pending_pts = Some(start + duration);
+        }
+
+        buffers
+    }
+}
+
+// Audio is resynced to the calculated timestamps if it drifted further away than this
+const AUDIO_RESYNC_THRESHOLD: gst::ClockTime = gst::ClockTime::from_seconds(1);
 
-struct ObservationsInner {
+// Contiguous audio timeline for the resample and none slave methods
+#[derive(Debug, Default)]
+struct AudioSlave {
+    base_pts: Option<gst::ClockTime>,
+    rate: u32,
+    samples: u64,
+}
+
+// NDI times are in 100ns units, senders with broken clocks can send anything in there
+fn from_ndi_time(time: i64) -> gst::ClockTime {
+    gst::ClockTime::from_nseconds((cmp::max(time, 0) as u64).saturating_mul(100))
+}
+
+// Mapping between remote and local times. The base of the mapping is shareable between
+// receivers via a GstContext so that sources locked to a common time reference end up on a
+// common timeline, while the skew is always estimated per receiver as every source has its
+// own network delay.
+#[derive(Debug, Clone, Default, glib::Boxed)]
+#[boxed_type(name = "GstNdiObservations")]
+pub struct Observations {
+    base: Arc<Mutex<TimeBase>>,
+    skew: Arc<Mutex<SkewEstimate>>,
+}
+
+#[derive(Debug, Default)]
+struct TimeBase {
     base_remote_time: Option<u64>,
     base_local_time: Option<u64>,
+    // Incremented whenever the base changes, so that skew estimates relative to the previous
+    // base are discarded
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct SkewEstimate {
+    generation: u64,
+    // Remote and local time at which this receiver last synchronized against the base
+    anchor: Option<(u64, u64)>,
+    // Delta of this receiver against the base at the time of the last synchronization. Per
+    // source anomalies only re-synchronize this instead of changing the shared base.
+    offset: i64,
     deltas: VecDeque<i64>,
     min_delta: i64,
     skew: i64,
@@ -235,11 +725,12 @@ struct ObservationsInner {
     window_size: usize,
 }
 
-impl Default for ObservationsInner {
-    fn default() -> ObservationsInner {
-        ObservationsInner {
-            base_local_time: None,
-            base_remote_time: None,
+impl Default for SkewEstimate {
+    fn default() -> SkewEstimate {
+        SkewEstimate {
+            generation: 0,
+            anchor: None,
+            offset: 0,
             deltas: VecDeque::new(),
             min_delta: 0,
             skew: 0,
@@ -250,8 +741,47 @@ impl Default for ObservationsInner {
 }
 
 impl Observations {
-    fn new() -> Self {
-        Self(Arc::new(Mutex::new(ObservationsInner::default())))
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Shares the base of the mapping with this one but estimates the skew separately
+    fn with_own_skew(&self) -> Self {
+        Observations {
+            base: self.base.clone(),
+            skew: Default::default(),
+        }
+    }
+
+    // Only forgets the skew estimate of this receiver
+    fn reset(&self) {
+        let base = self.base.lock().unwrap();
+        *self.skew.lock().unwrap() = SkewEstimate {
+            generation: base.generation,
+            ..Default::default()
+        };
+    }
+
+    // Also forgets the base, which affects all receivers sharing it
+    fn reset_shared(&self) {
+        let mut base = self.base.lock().unwrap();
+        *base = TimeBase {
+            generation: base.generation + 1,
+            ..Default::default()
+        };
+        *self.skew.lock().unwrap() = SkewEstimate {
+            generation: base.generation,
+            ..Default::default()
+        };
+    }
+
+    fn resync(inner: &mut SkewEstimate, remote_time: u64, local_time: u64, offset: i64) {
+        *inner = SkewEstimate {
+            generation: inner.generation,
+            anchor: Some((remote_time, local_time)),
+            offset,
+            ..Default::default()
+        };
     }
 
     // Based on the algorithm used in GStreamer's rtpjitterbuffer, which comes from
@@ -279,10 +809,19 @@ impl Observations {
             gst::ClockTime::from_nseconds(remote_time),
         );
 
-        let mut inner = self.0.lock().unwrap();
+        let mut base = self.base.lock().unwrap();
+        let mut inner = self.skew.lock().unwrap();
+
+        // Another receiver sharing the base changed it
+        if inner.generation != base.generation {
+            *inner = SkewEstimate {
+                generation: base.generation,
+                ..Default::default()
+            };
+        }
 
         let (base_remote_time, base_local_time) =
-            match (inner.base_remote_time, inner.base_local_time) {
+            match (base.base_remote_time, base.base_local_time) {
                 (Some(remote), Some(local)) => (remote, local),
                 _ => {
                     gst_debug!(
@@ -292,76 +831,91 @@ impl Observations {
                         gst::ClockTime::from_nseconds(local_time),
                         gst::ClockTime::from_nseconds(remote_time),
                     );
-                    inner.base_remote_time = Some(remote_time);
-                    inner.base_local_time = Some(local_time);
+                    base.base_remote_time = Some(remote_time);
+                    base.base_local_time = Some(local_time);
 
-                    return (gst::ClockTime::from_nseconds(local_time), duration, true);
+                    (remote_time, local_time)
                 }
             };
 
-        let remote_diff = remote_time.saturating_sub(base_remote_time);
-        let local_diff = local_time.saturating_sub(base_local_time);
-        let delta = (local_diff as i64) - (remote_diff as i64);
+        let remote_diff =
+            (cmp::min(remote_time, i64::MAX as u64) as i64)
+                .saturating_sub(cmp::min(base_remote_time, i64::MAX as u64) as i64);
+        let local_diff =
+            (cmp::min(local_time, i64::MAX as u64) as i64)
+                .saturating_sub(cmp::min(base_local_time, i64::MAX as u64) as i64);
+        let offset = local_diff.saturating_sub(remote_diff);
+
+        let (anchor_remote_time, anchor_local_time) = match inner.anchor {
+            Some(anchor) => anchor,
+            None => {
+                gst_debug!(
+                    CAT,
+                    obj: element,
+                    "Synchronizing to base with offset {}",
+                    offset
+                );
+                Self::resync(&mut inner, remote_time, local_time, offset);
+
+                return (gst::ClockTime::from_nseconds(local_time), duration, true);
+            }
+        };
+
+        if remote_time < anchor_remote_time {
+            gst_warning!(
+                CAT,
+                obj: element,
+                "Remote time {} went backwards from {}, resynchronizing",
+                gst::ClockTime::from_nseconds(remote_time),
+                gst::ClockTime::from_nseconds(anchor_remote_time),
+            );
+
+            Self::resync(&mut inner, remote_time, local_time, offset);
+
+            return (gst::ClockTime::from_nseconds(local_time), duration, true);
+        }
+
+        let remote_elapsed = remote_time - anchor_remote_time;
+        let local_elapsed = local_time.saturating_sub(anchor_local_time);
+        let delta = offset.saturating_sub(inner.offset);
 
         gst_trace!(
             CAT,
             obj: element,
             "Local diff {}, remote diff {}, delta {}",
-            gst::ClockTime::from_nseconds(local_diff),
-            gst::ClockTime::from_nseconds(remote_diff),
+            gst::ClockTime::from_nseconds(local_elapsed),
+            gst::ClockTime::from_nseconds(remote_elapsed),
             delta,
         );
 
-        if remote_diff > 0 && local_diff > 0 {
-            let slope = (local_diff as f64) / (remote_diff as f64);
+        if remote_elapsed > 0 && local_elapsed > 0 {
+            let slope = (local_elapsed as f64) / (remote_elapsed as f64);
             if !(0.8..1.2).contains(&slope) {
                 gst_warning!(
                     CAT,
                     obj: element,
-                    "Too small/big slope {}, resetting",
+                    "Too small/big slope {}, resynchronizing",
                     slope
                 );
 
                 let discont = !inner.deltas.is_empty();
-                *inner = ObservationsInner::default();
-
-                gst_debug!(
-                    CAT,
-                    obj: element,
-                    "Initializing base time: local {}, remote {}",
-                    gst::ClockTime::from_nseconds(local_time),
-                    gst::ClockTime::from_nseconds(remote_time),
-                );
-                inner.base_remote_time = Some(remote_time);
-                inner.base_local_time = Some(local_time);
+                Self::resync(&mut inner, remote_time, local_time, offset);
 
                 return (gst::ClockTime::from_nseconds(local_time), duration, discont);
             }
         }
 
-        if (delta > inner.skew && delta - inner.skew > 1_000_000_000)
-            || (delta < inner.skew && inner.skew - delta > 1_000_000_000)
-        {
+        if delta.saturating_sub(inner.skew).saturating_abs() > 1_000_000_000 {
             gst_warning!(
                 CAT,
                 obj: element,
-                "Delta {} too far from skew {}, resetting",
+                "Delta {} too far from skew {}, resynchronizing",
                 delta,
                 inner.skew
             );
 
             let discont = !inner.deltas.is_empty();
-            *inner = ObservationsInner::default();
-
-            gst_debug!(
-                CAT,
-                obj: element,
-                "Initializing base time: local {}, remote {}",
-                gst::ClockTime::from_nseconds(local_time),
-                gst::ClockTime::from_nseconds(remote_time),
-            );
-            inner.base_remote_time = Some(remote_time);
-            inner.base_local_time = Some(local_time);
+            Self::resync(&mut inner, remote_time, local_time, offset);
 
             return (gst::ClockTime::from_nseconds(local_time), duration, discont);
         }
@@ -372,12 +926,12 @@ impl Observations {
             }
             inner.deltas.push_back(delta);
 
-            if remote_diff > WINDOW_DURATION || inner.deltas.len() as u64 == WINDOW_LENGTH {
+            if remote_elapsed > WINDOW_DURATION || inner.deltas.len() as u64 == WINDOW_LENGTH {
                 inner.window_size = inner.deltas.len();
                 inner.skew = inner.min_delta;
                 inner.filling = false;
             } else {
-                let perc_time = remote_diff.mul_div_floor(100, WINDOW_DURATION).unwrap() as i64;
+                let perc_time = remote_elapsed.mul_div_floor(100, WINDOW_DURATION).unwrap() as i64;
                 let perc_window = (inner.deltas.len() as u64)
                     .mul_div_floor(100, WINDOW_LENGTH)
                     .unwrap() as i64;
@@ -398,11 +952,13 @@ impl Observations {
             inner.skew = (inner.min_delta + (124 * inner.skew)) / 125;
         }
 
-        let out_time = base_local_time + remote_diff;
-        let out_time = if inner.skew < 0 {
-            out_time.saturating_sub((-inner.skew) as u64)
+        let diff = remote_diff
+            .saturating_add(inner.offset)
+            .saturating_add(inner.skew);
+        let out_time = if diff < 0 {
+            base_local_time.saturating_sub(diff.unsigned_abs())
         } else {
-            out_time + (inner.skew as u64)
+            base_local_time.saturating_add(diff as u64)
         };
 
         gst_trace!(
@@ -426,25 +982,106 @@ impl Observations {
 #[derive(Clone)]
 pub struct ReceiverControlHandle {
     queue: ReceiverQueue,
+    observations: Observations,
+    rebase: Arc<Mutex<TimestampRebase>>,
+    audio_slave: Arc<Mutex<AudioSlave>>,
+    recv: Arc<Mutex<RecvInstance>>,
 }
 
 impl ReceiverControlHandle {
     pub fn set_flushing(&self, flushing: bool) {
         let mut queue = (self.queue.0).0.lock().unwrap();
+        // Nothing captured before or during a flush must come out afterwards, and timestamps
+        // start over as after a seek the running time doesn't continue from before
+        queue.buffer_queue.clear();
+        queue.pending_events.clear();
+        queue.live_frame = None;
+        if !flushing && queue.flushing {
+            self.resync();
+        }
         queue.flushing = flushing;
         (self.queue.0).1.notify_all();
     }
 
-    pub fn set_playing(&self, playing: bool) {
+    // The running time is that of the transition to PLAYING, at which a video frame kept
+    // for skipping to live is output
+    pub fn set_playing(&self, playing: bool, running_time: Option<gst::ClockTime>) {
         let mut queue = (self.queue.0).0.lock().unwrap();
+        // Frames queued before pausing are in the past now and the running time continues from
+        // where it was paused, so start with a fresh mapping to be live again immediately
+        if playing && !queue.playing {
+            queue.buffer_queue.clear();
+            self.resync();
+
+            if let Some(Buffer::Video(mut buffer, info)) = queue.live_frame.take() {
+                {
+                    let buffer = buffer.make_mut();
+                    buffer.set_pts(running_time);
+                    buffer.set_dts(running_time);
+                    buffer.set_flags(gst::BufferFlags::DISCONT);
+                }
+                queue
+                    .buffer_queue
+                    .push_back((Buffer::Video(buffer, info), time::Instant::now()));
+                (self.queue.0).1.notify_one();
+            }
+        }
+        queue.live_frame = None;
         queue.playing = playing;
     }
 
+    // Stops or resumes capturing video and audio from the NDI receiver
+    pub fn select_streams(&self, video: bool, audio: bool) {
+        let mut queue = (self.queue.0).0.lock().unwrap();
+        queue.video_selected = video;
+        queue.audio_selected = audio;
+    }
+
+    // Recreates the receiver with another color format, the source stays connected
+    pub fn set_color_format(&self, color_format: ndisys::NDIlib_recv_color_format_e) {
+        let mut queue = (self.queue.0).0.lock().unwrap();
+        queue.pending_color_format = Some(color_format);
+    }
+
+    pub fn recv(&self) -> RecvInstance {
+        self.recv.lock().unwrap().clone()
+    }
+
     pub fn shutdown(&self) {
         let mut queue = (self.queue.0).0.lock().unwrap();
         queue.shutdown = true;
         (self.queue.0).1.notify_all();
     }
+
+    pub fn is_connected(&self) -> bool {
+        (self.queue.0).0.lock().unwrap().connected
+    }
+
+    pub fn status(&self) -> SourceStatus {
+        (self.queue.0).0.lock().unwrap().status.clone()
+    }
+
+    pub fn stats(&self) -> Stats {
+        (self.queue.0).0.lock().unwrap().stats
+    }
+
+    // Forgets the mapping of remote to local times, the next frame starts a new epoch. With a
+    // shared base this affects all receivers sharing it.
+    pub fn reset_epoch(&self) {
+        self.observations.reset_shared();
+        self.resync();
+    }
+
+    // Forgets only this receiver's skew estimate, the next frame re-synchronizes it against the
+    // current base
+    fn resync(&self) {
+        self.observations.reset();
+        *self.rebase.lock().unwrap() = TimestampRebase {
+            discont: true,
+            ..Default::default()
+        };
+        *self.audio_slave.lock().unwrap() = AudioSlave::default();
+    }
 }
 
 impl Drop for ReceiverInner {
@@ -463,32 +1100,48 @@ impl Drop for ReceiverInner {
 }
 
 impl Receiver {
-    fn new(
-        recv: RecvInstance,
-        timestamp_mode: TimestampMode,
-        timeout: u32,
-        connect_timeout: u32,
-        max_queue_length: usize,
-        element: &gst_base::BaseSrc,
-    ) -> Self {
+    fn new(recv: RecvInstance, settings: &ReceiverSettings, element: &gst_base::BaseSrc) -> Self {
         let receiver = Receiver(Arc::new(ReceiverInner {
             queue: ReceiverQueue(Arc::new((
                 Mutex::new(ReceiverQueueInner {
                     shutdown: false,
                     playing: false,
                     flushing: false,
-                    buffer_queue: VecDeque::with_capacity(max_queue_length),
+                    buffer_queue: VecDeque::with_capacity(settings.max_queue_length),
+                    live_frame: None,
                     error: None,
                     timeout: false,
+                    connected: false,
+                    stats: Stats::default(),
+                    video_jitter: ArrivalJitter::default(),
+                    audio_jitter: ArrivalJitter::default(),
+                    pending_events: Vec::new(),
+                    status: SourceStatus::default(),
+                    video_selected: true,
+                    audio_selected: true,
+                    pending_color_format: None,
                 }),
                 Condvar::new(),
             ))),
-            max_queue_length,
-            observations: Observations::new(),
+            observations: settings
+                .observations
+                .as_ref()
+                .map(Observations::with_own_skew)
+                .unwrap_or_default(),
+            rebase: Arc::new(Mutex::new(TimestampRebase::default())),
+            audio_slave: Arc::new(Mutex::new(AudioSlave::default())),
+            last_video_pts: Mutex::new(None),
+            retimer: Mutex::new(Retimer::default()),
+            audio_chunker: Mutex::new(AudioChunker::default()),
+            video_pool: Mutex::new(SizedBufferPool::default()),
+            audio_pool: Mutex::new(SizedBufferPool::default()),
+            copy_pool: CopyPool::new(match settings.n_threads {
+                0 => thread::available_parallelism().map_or(1, |n| n.get()),
+                n_threads => n_threads as usize,
+            }),
+            recv: Arc::new(Mutex::new(recv.clone())),
             element: element.downgrade(),
-            timestamp_mode,
-            timeout,
-            connect_timeout,
+            settings: settings.clone(),
             thread: Mutex::new(None),
         }));
 
@@ -524,21 +1177,27 @@ impl Receiver {
         receiver
     }
 
+    pub fn recv(&self) -> RecvInstance {
+        self.0.recv.lock().unwrap().clone()
+    }
+
     pub fn receiver_control_handle(&self) -> ReceiverControlHandle {
         ReceiverControlHandle {
             queue: self.0.queue.clone(),
+            observations: self.0.observations.clone(),
+            rebase: self.0.rebase.clone(),
+            audio_slave: self.0.audio_slave.clone(),
+            recv: self.0.recv.clone(),
         }
     }
 
     pub fn set_flushing(&self, flushing: bool) {
-        let mut queue = (self.0.queue.0).0.lock().unwrap();
-        queue.flushing = flushing;
-        (self.0.queue.0).1.notify_all();
+        self.receiver_control_handle().set_flushing(flushing);
     }
 
-    pub fn set_playing(&self, playing: bool) {
-        let mut queue = (self.0.queue.0).0.lock().unwrap();
-        queue.playing = playing;
+    pub fn set_playing(&self, playing: bool, running_time: Option<gst::ClockTime>) {
+        self.receiver_control_handle()
+            .set_playing(playing, running_time);
     }
 
     pub fn shutdown(&self) {
@@ -547,54 +1206,198 @@ impl Receiver {
         (self.0.queue.0).1.notify_all();
     }
 
-    pub fn capture(&self) -> ReceiverItem {
+    fn update_status(&self, element: &gst_base::BaseSrc, recv: &RecvInstance) {
+        let status = SourceStatus {
+            web_control: recv.web_control(),
+            ptz_supported: recv.ptz_is_supported(),
+            recording_supported: recv.recording_is_supported(),
+        };
+
         let mut queue = (self.0.queue.0).0.lock().unwrap();
-        loop {
-            if let Some(err) = queue.error {
-                return ReceiverItem::Error(err);
-            } else if queue.buffer_queue.is_empty() && queue.timeout {
-                return ReceiverItem::Timeout;
-            } else if queue.flushing || queue.shutdown {
-                return ReceiverItem::Flushing;
-            } else if let Some(buffer) = queue.buffer_queue.pop_front() {
-                return ReceiverItem::Buffer(buffer);
-            }
+        let old_status = mem::replace(&mut queue.status, status.clone());
+        drop(queue);
 
-            queue = (self.0.queue.0).1.wait(queue).unwrap();
+        gst_debug!(CAT, obj: element, "Source status changed: {:?}", status);
+        if old_status.web_control != status.web_control {
+            element.notify("web-control");
+        }
+        if old_status.ptz_supported != status.ptz_supported {
+            element.notify("ptz-supported");
+        }
+        if old_status.recording_supported != status.recording_supported {
+            element.notify("recording-supported");
         }
     }
 
-    pub fn connect(
-        element: &gst_base::BaseSrc,
-        ndi_name: Option<&str>,
-        url_address: Option<&str>,
-        receiver_ndi_name: &str,
-        connect_timeout: u32,
-        bandwidth: NDIlib_recv_bandwidth_e,
-        color_format: NDIlib_recv_color_format_e,
-        timestamp_mode: TimestampMode,
-        timeout: u32,
-        max_queue_length: usize,
-    ) -> Option<Self> {
-        gst_debug!(CAT, obj: element, "Starting NDI connection...");
+    fn update_connected(&self, element: &gst_base::BaseSrc, recv: &RecvInstance) {
+        let connected = recv.no_connections() > 0;
+        let mut queue = (self.0.queue.0).0.lock().unwrap();
+        if queue.connected == connected {
+            return;
+        }
+        queue.connected = connected;
+        drop(queue);
 
-        assert!(ndi_name.is_some() || url_address.is_some());
+        gst_debug!(CAT, obj: element, "Connected: {}", connected);
+        // Don't rely on the sender announcing its capabilities right after connecting
+        if connected {
+            self.update_status(element, recv);
+        }
+        element.notify("connected");
+        self.post_connection_message(
+            element,
+            if connected {
+                "ndi-connected"
+            } else {
+                "ndi-disconnected"
+            },
+        );
+    }
+
+    // Connects to the source again, which only fails if the NDI name or URL/address contain a
+    // NUL byte
+    fn reconnect(
+        &self,
+        element: &gst_base::BaseSrc,
+        recv: &RecvInstance,
+        url_address: Option<&str>,
+    ) -> Result<(), gst::FlowError> {
+        recv.connect(None, None);
+        recv.connect(self.0.settings.ndi_name.as_deref(), url_address)
+            .ok_or_else(|| {
+                gst::element_error!(
+                    element,
+                    gst::ResourceError::Settings,
+                    ["Invalid NDI name or URL/address"]
+                );
+                gst::FlowError::Error
+            })
+    }
+
+    fn post_connection_message(&self, element: &gst_base::BaseSrc, name: &str) {
+        let s = gst::Structure::builder(name)
+            .field("ndi-name", &self.0.settings.ndi_name)
+            .field("url-address", &self.0.settings.url_address)
+            .field("running-time", element.current_running_time())
+            .field(
+                "real-time",
+                gst::ClockTime::from_nseconds(glib::real_time() as u64 * 1000),
+            )
+            .build();
+        let _ = element.post_message(gst::message::Element::builder(s).src(element).build());
+    }
+
+    fn error_frame_details(
+        &self,
+        recv: &RecvInstance,
+        url_address_index: usize,
+        timer: &time::Instant,
+    ) -> gst::Structure {
+        gst::Structure::builder("ndi-error-frame")
+            .field("ndi-name", &self.0.settings.ndi_name)
+            .field(
+                "url-address",
+                self.0
+                    .settings
+                    .url_addresses()
+                    .get(url_address_index)
+                    .copied()
+                    .flatten(),
+            )
+            .field("connections", recv.no_connections())
+            .field("ms-since-last-frame", timer.elapsed().as_millis() as u64)
+            .build()
+    }
+
+    // Returns true if shut down while waiting
+    fn wait_for_shutdown(&self, timeout: time::Duration) -> bool {
+        let deadline = time::Instant::now() + timeout;
+        let mut queue = (self.0.queue.0).0.lock().unwrap();
+        loop {
+            let now = time::Instant::now();
+            if queue.shutdown {
+                return true;
+            } else if now >= deadline {
+                return false;
+            }
+
+            queue = (self.0.queue.0)
+                .1
+                .wait_timeout(queue, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    // Current arrival jitter of the video or audio buffers
+    pub fn jitter(&self, video: bool) -> gst::ClockTime {
+        let queue = (self.0.queue.0).0.lock().unwrap();
+        if video {
+            queue.video_jitter.jitter()
+        } else {
+            queue.audio_jitter.jitter()
+        }
+    }
+
+    pub fn capture(&self) -> ReceiverItem {
+        let mut queue = (self.0.queue.0).0.lock().unwrap();
+        loop {
+            if let Some(err) = queue.error {
+                return ReceiverItem::Error(err);
+            } else if queue.buffer_queue.is_empty() && queue.timeout {
+                return ReceiverItem::Timeout;
+            } else if queue.flushing || queue.shutdown {
+                return ReceiverItem::Flushing;
+            } else if let Some((buffer, queued)) = queue.buffer_queue.pop_front() {
+                if TIMING_CAT.threshold() >= gst::DebugLevel::Trace {
+                    if let Some(element) = self.0.element.upgrade() {
+                        let s = gst::Structure::builder("ndi-frame-queue")
+                            .field(
+                                "type",
+                                match buffer {
+                                    Buffer::Video(..) => "video",
+                                    Buffer::Audio(..) => "audio",
+                                },
+                            )
+                            .field("residency", queued.elapsed().as_nanos() as u64)
+                            .field("queue-length", queue.buffer_queue.len() as u32)
+                            .build();
+                        gst_trace!(TIMING_CAT, obj: &element, "{}", s);
+                    }
+                }
+                match buffer {
+                    Buffer::Video(..) => queue.stats.video_frames += 1,
+                    Buffer::Audio(..) => queue.stats.audio_buffers += 1,
+                }
+                return ReceiverItem::Buffer(buffer);
+            }
+
+            queue = (self.0.queue.0).1.wait(queue).unwrap();
+        }
+    }
+
+    pub fn take_events(&self) -> Vec<gst::Event> {
+        let mut queue = (self.0.queue.0).0.lock().unwrap();
+        mem::take(&mut queue.pending_events)
+    }
+
+    pub fn connect(element: &gst_base::BaseSrc, settings: &ReceiverSettings) -> Option<Self> {
+        gst_debug!(CAT, obj: element, "Starting NDI connection...");
+
+        if settings.ndi_name.is_none() && settings.url_address.is_none() {
+            gst_error!(CAT, obj: element, "No NDI name or URL/address given");
+            return None;
+        }
 
         gst_debug!(
             CAT,
             obj: element,
             "Connecting to NDI source with NDI name '{:?}' and URL/Address {:?}",
-            ndi_name,
-            url_address,
+            settings.ndi_name,
+            settings.url_address,
         );
 
-        // FIXME: Ideally we would use NDIlib_recv_color_format_fastest here but that seems to be
-        // broken with interlaced content currently
-        let recv = RecvInstance::builder(ndi_name, url_address, receiver_ndi_name)
-            .bandwidth(bandwidth)
-            .color_format(color_format)
-            .allow_video_fields(true)
-            .build();
+        let recv = Self::create_recv(settings, settings.url_addresses()[0], settings.color_format);
         let recv = match recv {
             None => {
                 gst::element_error!(
@@ -607,29 +1410,55 @@ impl Receiver {
             Some(recv) => recv,
         };
 
+        // This will set info.audio/video accordingly
+        let receiver = Receiver::new(recv, settings, element);
+
+        Some(receiver)
+    }
+
+    fn create_recv(
+        settings: &ReceiverSettings,
+        url_address: Option<&str>,
+        color_format: ndisys::NDIlib_recv_color_format_e,
+    ) -> Option<RecvInstance> {
+        // FIXME: Ideally we would use NDIlib_recv_color_format_fastest here but that seems to be
+        // broken with interlaced content currently
+        let recv = RecvInstance::builder(
+            settings.ndi_name.as_deref(),
+            url_address,
+            &settings.receiver_ndi_name,
+        )
+        .bandwidth(settings.bandwidth)
+        .color_format(color_format)
+        .allow_video_fields(true)
+        .build()?;
+
         recv.set_tally(&Tally::default());
 
         let enable_hw_accel = MetadataFrame::new(0, Some("<ndi_hwaccel enabled=\"true\"/>"));
         recv.send_metadata(&enable_hw_accel);
 
-        // This will set info.audio/video accordingly
-        let receiver = Receiver::new(
-            recv,
-            timestamp_mode,
-            timeout,
-            connect_timeout,
-            max_queue_length,
-            element,
-        );
-
-        Some(receiver)
+        Some(recv)
     }
 
-    fn receive_thread(receiver: &Weak<ReceiverInner>, recv: RecvInstance) {
+    fn receive_thread(receiver: &Weak<ReceiverInner>, mut recv: RecvInstance) {
         let mut first_video_frame = true;
         let mut first_audio_frame = true;
         let mut first_frame = true;
         let mut timer = time::Instant::now();
+        let mut missing_frames_warnings = 0u32;
+        let mut connect_attempts = 0u32;
+        let mut url_address_index = 0;
+        let mut last_connection_check = None::<time::Instant>;
+        let mut disconnected_since = None::<time::Instant>;
+        // Set after reconnecting because of an SDK error frame, cleared by the next frame
+        let mut error_reconnected = false;
+
+        if let Some(receiver) = receiver.upgrade().map(Receiver) {
+            if let Some(element) = receiver.0.element.upgrade() {
+                receiver.post_connection_message(&element, "ndi-connecting");
+            }
+        }
 
         // Capture until error or shutdown
         loop {
@@ -643,8 +1472,8 @@ impl Receiver {
                 Some(element) => element,
             };
 
-            let flushing = {
-                let queue = (receiver.0.queue.0).0.lock().unwrap();
+            let (flushing, video_selected, audio_selected, pending_color_format) = {
+                let mut queue = (receiver.0.queue.0).0.lock().unwrap();
                 if queue.shutdown {
                     gst_debug!(CAT, obj: &element, "Shutting down");
                     break;
@@ -656,45 +1485,233 @@ impl Receiver {
                     return;
                 }
 
-                queue.flushing
+                (
+                    queue.flushing,
+                    queue.video_selected,
+                    queue.audio_selected && receiver.0.settings.receive_audio,
+                    queue.pending_color_format.take(),
+                )
             };
 
+            if let Some(color_format) = pending_color_format {
+                match Self::create_recv(
+                    &receiver.0.settings,
+                    receiver.0.settings.url_addresses()[url_address_index],
+                    color_format,
+                ) {
+                    Some(new_recv) => {
+                        gst_debug!(
+                            CAT,
+                            obj: &element,
+                            "Recreated receiver with color format {}",
+                            color_format
+                        );
+                        recv = new_recv;
+                        *receiver.0.recv.lock().unwrap() = recv.clone();
+                    }
+                    None => {
+                        gst_warning!(
+                            CAT,
+                            obj: &element,
+                            "Failed to recreate receiver with color format {}",
+                            color_format
+                        );
+                    }
+                }
+            }
+
+            // Frames of a stream that is selected again don't continue from before
+            if !video_selected {
+                first_video_frame = true;
+            }
+            if !audio_selected {
+                first_audio_frame = true;
+            }
+
             let timeout = if first_frame {
-                receiver.0.connect_timeout
+                receiver.0.settings.connect_timeout
             } else {
-                receiver.0.timeout
+                receiver.0.settings.timeout
             };
 
-            let res = match recv.capture(50) {
+            if last_connection_check
+                .map(|check| check.elapsed() >= CONNECTION_CHECK_INTERVAL)
+                .unwrap_or(true)
+            {
+                last_connection_check = Some(time::Instant::now());
+                receiver.update_connected(&element, &recv);
+            }
+
+            // The number of connections can drop to zero for a moment, e.g. while the sender
+            // changes its format, so only count the sender as gone after the disconnect timeout
+            if recv.no_connections() == 0 {
+                disconnected_since.get_or_insert_with(time::Instant::now);
+            } else {
+                disconnected_since = None;
+            }
+
+            let res = match recv.capture(50, video_selected, audio_selected) {
                 _ if flushing => {
                     gst_debug!(CAT, obj: &element, "Flushing");
                     Err(gst::FlowError::Flushing)
                 }
+                Err(_) if !error_reconnected => {
+                    error_reconnected = true;
+                    gst::element_warning!(
+                        element,
+                        gst::ResourceError::Read,
+                        ("NDI SDK reported an error while receiving, reconnecting"),
+                        details: receiver.error_frame_details(&recv, url_address_index, &timer)
+                    );
+
+                    receiver.post_connection_message(&element, "ndi-reconnecting");
+                    (receiver.0.queue.0).0.lock().unwrap().stats.reconnects += 1;
+                    match receiver.reconnect(
+                        &element,
+                        &recv,
+                        receiver.0.settings.url_addresses()[url_address_index],
+                    ) {
+                        Ok(_) => {
+                            timer = time::Instant::now();
+                            continue;
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
                 Err(_) => {
                     gst::element_error!(
                         element,
                         gst::ResourceError::Read,
-                        ["Error receiving frame"]
+                        ("NDI SDK reported an error while receiving, also after reconnecting"),
+                        details: receiver.error_frame_details(&recv, url_address_index, &timer)
                     );
                     Err(gst::FlowError::Error)
                 }
-                Ok(None) if timeout > 0 && timer.elapsed().as_millis() >= timeout as u128 => {
-                    gst_debug!(CAT, obj: &element, "Timed out -- assuming EOS",);
+                Ok(None)
+                    if receiver.0.settings.eos_on_disconnect
+                        && !first_frame
+                        && disconnected_since
+                            .map(|since| {
+                                since.elapsed().as_millis()
+                                    >= receiver.0.settings.disconnect_timeout as u128
+                            })
+                            .unwrap_or(false) =>
+                {
+                    gst_debug!(CAT, obj: &element, "Sender disconnected -- signalling EOS");
                     Err(gst::FlowError::Eos)
                 }
+                Ok(None)
+                    if first_frame
+                        && url_address_index + 1 < receiver.0.settings.url_addresses().len()
+                        && timeout > 0
+                        && timer.elapsed().as_millis() >= timeout as u128 =>
+                {
+                    url_address_index += 1;
+                    let url_address = receiver.0.settings.url_addresses()[url_address_index];
+                    gst_debug!(
+                        CAT,
+                        obj: &element,
+                        "No frames received within {}ms, trying {:?} next",
+                        timeout,
+                        url_address,
+                    );
+
+                    match receiver.reconnect(&element, &recv, url_address) {
+                        Ok(_) => {
+                            timer = time::Instant::now();
+                            continue;
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                Ok(None)
+                    if first_frame
+                        && connect_attempts < receiver.0.settings.connect_retries
+                        && timeout > 0
+                        && timer.elapsed().as_millis() >= timeout as u128 =>
+                {
+                    connect_attempts += 1;
+                    let backoff = receiver
+                        .0
+                        .settings
+                        .retry_backoff
+                        .saturating_mul(1 << cmp::min(connect_attempts - 1, 16));
+
+                    gst::element_warning!(
+                        element,
+                        gst::ResourceError::NotFound,
+                        (
+                            "No frames received within {}ms, retrying in {}ms ({}/{})",
+                            timeout,
+                            backoff,
+                            connect_attempts,
+                            receiver.0.settings.connect_retries
+                        )
+                    );
+
+                    if receiver.wait_for_shutdown(time::Duration::from_millis(backoff as u64)) {
+                        gst_debug!(CAT, obj: &element, "Shutting down");
+                        break;
+                    }
+
+                    receiver.post_connection_message(&element, "ndi-reconnecting");
+                    (receiver.0.queue.0).0.lock().unwrap().stats.reconnects += 1;
+                    url_address_index = 0;
+                    match receiver.reconnect(
+                        &element,
+                        &recv,
+                        receiver.0.settings.url_addresses()[0],
+                    ) {
+                        Ok(_) => {
+                            timer = time::Instant::now();
+                            continue;
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                Ok(None) if timeout > 0 && timer.elapsed().as_millis() >= timeout as u128 => {
+                    if receiver.0.settings.eos_on_disconnect {
+                        gst_debug!(CAT, obj: &element, "Timed out -- assuming EOS",);
+                        Err(gst::FlowError::Eos)
+                    } else {
+                        gst::element_error!(
+                            element,
+                            gst::ResourceError::Read,
+                            ["Timed out waiting for frames after {}ms", timeout]
+                        );
+                        Err(gst::FlowError::Error)
+                    }
+                }
                 Ok(None) => {
                     gst_debug!(CAT, obj: &element, "No frame received yet, retry");
+
+                    let elapsed = timer.elapsed().as_millis();
+                    if !first_frame
+                        && elapsed
+                            >= (missing_frames_warnings as u128 + 1)
+                                * MISSING_FRAMES_WARNING_INTERVAL
+                    {
+                        missing_frames_warnings += 1;
+                        gst::element_warning!(
+                            element,
+                            gst::ResourceError::Read,
+                            ("No frames received for {}ms", elapsed),
+                            details: gst::Structure::builder("ndi-missing-frames")
+                                .field("consecutive-timeouts", missing_frames_warnings)
+                                .field("elapsed-ms", elapsed as u64)
+                                .build()
+                        );
+                    }
+
                     continue;
                 }
                 Ok(Some(Frame::Video(frame))) => {
                     first_frame = false;
+                    error_reconnected = false;
                     let mut buffer = receiver.create_video_buffer_and_info(&element, frame);
                     if first_video_frame {
                         if let Ok(Buffer::Video(ref mut buffer, _)) = buffer {
-                            buffer
-                                .get_mut()
-                                .unwrap()
-                                .set_flags(gst::BufferFlags::DISCONT);
+                            buffer.make_mut().set_flags(gst::BufferFlags::DISCONT);
                             first_video_frame = false;
                         }
                     }
@@ -702,27 +1719,49 @@ impl Receiver {
                 }
                 Ok(Some(Frame::Audio(frame))) => {
                     first_frame = false;
+                    error_reconnected = false;
                     let mut buffer = receiver.create_audio_buffer_and_info(&element, frame);
                     if first_audio_frame {
                         if let Ok(Buffer::Audio(ref mut buffer, _)) = buffer {
-                            buffer
-                                .get_mut()
-                                .unwrap()
-                                .set_flags(gst::BufferFlags::DISCONT);
+                            buffer.make_mut().set_flags(gst::BufferFlags::DISCONT);
                             first_audio_frame = false;
                         }
                     }
                     buffer
                 }
+                Ok(Some(Frame::StatusChange)) => {
+                    receiver.update_status(&element, &recv);
+                    continue;
+                }
                 Ok(Some(Frame::Metadata(frame))) => {
                     if let Some(metadata) = frame.metadata() {
                         gst_debug!(
                             CAT,
                             obj: &element,
                             "Received metadata at timecode {}: {}",
-                            gst::ClockTime::from_nseconds(frame.timecode() as u64 * 100),
+                            from_ndi_time(frame.timecode()),
                             metadata,
                         );
+
+                        if let Some(s) = splice_from_metadata(
+                            metadata,
+                            from_ndi_time(frame.timecode()),
+                            element.current_running_time(),
+                        ) {
+                            gst_debug!(CAT, obj: &element, "Received splice point {}", s);
+                            let _ = element.post_message(
+                                gst::message::Element::builder(s.clone())
+                                    .src(&element)
+                                    .build(),
+                            );
+
+                            let mut queue = (receiver.0.queue.0).0.lock().unwrap();
+                            if queue.playing {
+                                queue
+                                    .pending_events
+                                    .push(gst::event::CustomDownstream::new(s));
+                            }
+                        }
                     }
 
                     continue;
@@ -731,19 +1770,126 @@ impl Receiver {
 
             match res {
                 Ok(item) => {
-                    let mut queue = (receiver.0.queue.0).0.lock().unwrap();
-                    while queue.buffer_queue.len() > receiver.0.max_queue_length {
-                        gst_warning!(
-                            CAT,
-                            obj: &element,
-                            "Dropping old buffer -- queue has {} items",
-                            queue.buffer_queue.len()
+                    if missing_frames_warnings > 0 {
+                        gst::element_info!(
+                            element,
+                            gst::ResourceError::Read,
+                            ("Receiving frames again"),
+                            details: gst::Structure::builder("ndi-missing-frames")
+                                .field("consecutive-timeouts", missing_frames_warnings)
+                                .build()
                         );
-                        queue.buffer_queue.pop_front();
+                        missing_frames_warnings = 0;
+                    }
+
+                    let mut queue = (receiver.0.queue.0).0.lock().unwrap();
+                    let now = time::Instant::now();
+                    match item {
+                        Buffer::Video(ref buffer, _) => {
+                            queue.stats.bytes_received += buffer.size() as u64;
+                            queue.video_jitter.update(now, buffer.duration());
+                        }
+                        Buffer::Audio(ref buffer, _) => {
+                            queue.stats.bytes_received += buffer.size() as u64;
+                            queue.audio_jitter.update(now, buffer.duration());
+                        }
+                    }
+                    if !queue.playing
+                        && !queue.flushing
+                        && receiver.0.settings.skip_to_live
+                        && matches!(item, Buffer::Video(..))
+                    {
+                        gst_trace!(CAT, obj: &element, "Not playing, keeping {:?}", item);
+                        if queue.live_frame.replace(item).is_some() {
+                            queue.stats.dropped += 1;
+                        }
+                        drop(queue);
+                        timer = time::Instant::now();
+                        continue;
+                    }
+                    if !queue.playing || queue.flushing {
+                        gst_trace!(CAT, obj: &element, "Not playing or flushing, dropping {:?}", item);
+                        queue.stats.dropped += 1;
+                        drop(queue);
+                        timer = time::Instant::now();
+                        continue;
+                    }
+                    let items = match (item, receiver.0.settings.force_framerate) {
+                        (Buffer::Video(buffer, info), Some(fps)) => {
+                            let buffers = receiver.0.retimer.lock().unwrap().retime(fps, buffer);
+                            if buffers.is_empty() {
+                                gst_trace!(CAT, obj: &element, "Dropping frame for framerate");
+                                queue.stats.dropped += 1;
+                            }
+                            buffers
+                                .into_iter()
+                                .map(|buffer| Buffer::Video(buffer, info.clone()))
+                                .collect::<Vec<_>>()
+                        }
+                        (item, _) => vec![item],
+                    };
+                    let items = if receiver.0.settings.align_audio {
+                        let mut chunker = receiver.0.audio_chunker.lock().unwrap();
+                        items
+                            .into_iter()
+                            .flat_map(|item| match item {
+                                Buffer::Video(ref buffer, _) => {
+                                    chunker.video_frame(buffer);
+                                    vec![item]
+                                }
+                                Buffer::Audio(buffer, AudioInfo::AudioInfo(info)) => chunker
+                                    .push(buffer, &info)
+                                    .into_iter()
+                                    .map(|buffer| {
+                                        Buffer::Audio(buffer, AudioInfo::AudioInfo(info.clone()))
+                                    })
+                                    .collect(),
+                                #[allow(unreachable_patterns)]
+                                item => vec![item],
+                            })
+                            .collect::<Vec<_>>()
+                    } else {
+                        items
+                    };
+                    let mut overrun = false;
+                    for item in items {
+                        if receiver.0.settings.leaky == QueueLeaky::Upstream
+                            && queue.buffer_queue.len() > receiver.0.settings.max_queue_length
+                        {
+                            gst_warning!(
+                                CAT,
+                                obj: &element,
+                                "Dropping new buffer -- queue has {} items",
+                                queue.buffer_queue.len()
+                            );
+                            queue.stats.dropped += 1;
+                            queue.stats.overruns += 1;
+                            overrun = true;
+                            continue;
+                        }
+
+                        while queue.buffer_queue.len() > receiver.0.settings.max_queue_length {
+                            gst_warning!(
+                                CAT,
+                                obj: &element,
+                                "Dropping old buffer -- queue has {} items",
+                                queue.buffer_queue.len()
+                            );
+                            queue.buffer_queue.pop_front();
+                            queue.stats.dropped += 1;
+                            queue.stats.overruns += 1;
+                            overrun = true;
+                        }
+                        queue.buffer_queue.push_back((item, time::Instant::now()));
                     }
-                    queue.buffer_queue.push_back(item);
+                    let overruns = queue.stats.overruns;
                     (receiver.0.queue.0).1.notify_one();
+                    drop(queue);
                     timer = time::Instant::now();
+
+                    if overrun {
+                        element.emit_by_name::<()>("overrun", &[&overruns]);
+                    }
                 }
                 Err(gst::FlowError::Eos) => {
                     gst_debug!(CAT, obj: &element, "Signalling EOS");
@@ -752,6 +1898,11 @@ impl Receiver {
                     (receiver.0.queue.0).1.notify_one();
                     break;
                 }
+                Err(FLOW_DROPPED) => {
+                    gst_debug!(CAT, obj: &element, "Dropped invalid frame");
+                    (receiver.0.queue.0).0.lock().unwrap().stats.dropped += 1;
+                    timer = time::Instant::now();
+                }
                 Err(gst::FlowError::Flushing) => {
                     // Flushing, nothing to be done here except for emptying our queue
                     let mut queue = (receiver.0.queue.0).0.lock().unwrap();
@@ -785,9 +1936,9 @@ impl Receiver {
         let timestamp = if timestamp == ndisys::NDIlib_recv_timestamp_undefined {
             gst::ClockTime::NONE
         } else {
-            Some(gst::ClockTime::from_nseconds(timestamp as u64 * 100))
+            Some(from_ndi_time(timestamp))
         };
-        let timecode = gst::ClockTime::from_nseconds(timecode as u64 * 100);
+        let timecode = from_ndi_time(timecode);
 
         gst_log!(
             CAT,
@@ -800,7 +1951,24 @@ impl Receiver {
             real_time_now,
         );
 
-        let (pts, duration, discont) = match self.0.timestamp_mode {
+        if TIMING_CAT.threshold() >= gst::DebugLevel::Trace {
+            // NDI timestamps are UTC like the local real time, so their difference is the time
+            // it took from the sender to here if both clocks are synchronized
+            let mut s = gst::Structure::builder("ndi-frame-receive")
+                .field("timecode", timecode.nseconds())
+                .field("arrival", receive_time.nseconds())
+                .build();
+            if let Some(timestamp) = timestamp {
+                s.set("timestamp", timestamp.nseconds());
+                s.set(
+                    "latency",
+                    real_time_now.nseconds() as i64 - timestamp.nseconds() as i64,
+                );
+            }
+            gst_trace!(TIMING_CAT, obj: element, "{}", s);
+        }
+
+        let (pts, duration, discont) = match self.0.settings.timestamp_mode {
             TimestampMode::ReceiveTimeTimecode => {
                 self.0
                     .observations
@@ -811,22 +1979,27 @@ impl Receiver {
                     .observations
                     .process(element, (timestamp, receive_time), duration)
             }
-            TimestampMode::Timecode => (timecode, duration, false),
+            TimestampMode::Timecode => {
+                let (pts, discont) = self.rebase_timestamp(element, timecode);
+                (pts, duration, discont)
+            }
             TimestampMode::Timestamp if timestamp.is_none() => (receive_time, duration, false),
             TimestampMode::Timestamp => {
                 // Timestamps are relative to the UNIX epoch
                 let timestamp = timestamp?;
-                if real_time_now > timestamp {
+                let pts = if real_time_now > timestamp {
                     let diff = real_time_now - timestamp;
                     if diff > receive_time {
-                        (gst::ClockTime::ZERO, duration, false)
+                        gst::ClockTime::ZERO
                     } else {
-                        (receive_time - diff, duration, false)
+                        receive_time - diff
                     }
                 } else {
                     let diff = timestamp - real_time_now;
-                    (receive_time + diff, duration, false)
-                }
+                    receive_time.saturating_add(diff)
+                };
+                let (pts, discont) = self.rebase_timestamp(element, pts);
+                (pts, duration, discont)
             }
             TimestampMode::ReceiveTime => (receive_time, duration, false),
         };
@@ -842,6 +2015,42 @@ impl Receiver {
         Some((pts, duration, discont))
     }
 
+    fn rebase_timestamp(
+        &self,
+        element: &gst_base::BaseSrc,
+        pts: gst::ClockTime,
+    ) -> (gst::ClockTime, bool) {
+        let mut rebase = self.0.rebase.lock().unwrap();
+
+        let mut pts = pts.saturating_add(rebase.offset);
+        let mut discont = mem::take(&mut rebase.discont);
+        if let Some(last_pts) = rebase.last_pts {
+            if last_pts > pts && last_pts - pts > BACKWARDS_JUMP_THRESHOLD {
+                gst_warning!(
+                    CAT,
+                    obj: element,
+                    "Timestamp {} jumped backwards from {}, rebasing",
+                    pts,
+                    last_pts,
+                );
+
+                rebase.offset += last_pts - pts;
+                pts = last_pts;
+                discont = true;
+            }
+        }
+
+        if rebase
+            .last_pts
+            .map(|last_pts| pts > last_pts)
+            .unwrap_or(true)
+        {
+            rebase.last_pts = Some(pts);
+        }
+
+        (pts, discont)
+    }
+
     fn create_video_buffer_and_info(
         &self,
         element: &gst_base::BaseSrc,
@@ -856,14 +2065,28 @@ impl Receiver {
                 gst::FlowError::Flushing
             })?;
 
+        // Irregularly sent frames last until the next one, which is only known afterwards, so
+        // use the interval since the previous frame as an estimate and the nominal frame
+        // duration for the first one
+        let duration = if self.0.settings.variable_framerate {
+            let mut last_video_pts = self.0.last_video_pts.lock().unwrap();
+            let duration = last_video_pts
+                .filter(|_| !discont)
+                .and_then(|last_video_pts| pts.checked_sub(last_video_pts))
+                .or(duration);
+            *last_video_pts = Some(pts);
+            duration
+        } else {
+            duration
+        };
+
         let info = self.create_video_info(element, &video_frame)?;
 
         let mut buffer = self.create_video_buffer(element, pts, duration, &info, &video_frame)?;
         if discont {
             buffer
-                .get_mut()
-                .unwrap()
-                .set_flags(gst::BufferFlags::RESYNC);
+                .make_mut()
+                .set_flags(gst::BufferFlags::DISCONT | gst::BufferFlags::RESYNC);
         }
 
         gst_log!(CAT, obj: element, "Produced video buffer {:?}", buffer);
@@ -876,10 +2099,24 @@ impl Receiver {
         element: &gst_base::BaseSrc,
         video_frame: &VideoFrame,
     ) -> Option<(gst::ClockTime, Option<gst::ClockTime>, bool)> {
-        let duration = gst::ClockTime::SECOND.mul_div_floor(
-            video_frame.frame_rate().1 as u64,
-            video_frame.frame_rate().0 as u64,
-        );
+        let fps = frame_rate(video_frame);
+        let duration = if fps.numer() > 0 {
+            // Separate fields only last half a frame
+            let fields = if [
+                ndisys::NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_0,
+                ndisys::NDIlib_frame_format_type_e::NDIlib_frame_format_type_field_1,
+            ]
+            .contains(&video_frame.frame_format_type())
+            {
+                2
+            } else {
+                1
+            };
+
+            gst::ClockTime::SECOND.mul_div_floor(fps.denom() as u64, fps.numer() as u64 * fields)
+        } else {
+            gst::ClockTime::NONE
+        };
 
         self.calculate_timestamp(
             element,
@@ -889,6 +2126,16 @@ impl Receiver {
         )
     }
 
+    fn output_frame_rate(&self, video_frame: &VideoFrame) -> gst::Fraction {
+        if let Some(fps) = self.0.settings.force_framerate {
+            fps
+        } else if self.0.settings.variable_framerate {
+            gst::Fraction::new(0, 1)
+        } else {
+            frame_rate(video_frame)
+        }
+    }
+
     fn create_video_info(
         &self,
         element: &gst_base::BaseSrc,
@@ -896,9 +2143,20 @@ impl Receiver {
     ) -> Result<VideoInfo, gst::FlowError> {
         let fourcc = video_frame.fourcc();
 
-        let par = gst::Fraction::approximate_f32(video_frame.picture_aspect_ratio())
-            .unwrap_or_else(|| gst::Fraction::new(1, 1))
-            * gst::Fraction::new(video_frame.yres(), video_frame.xres());
+        // A picture aspect ratio of 0 means square pixels
+        let par = match (
+            self.0.settings.pixel_aspect_ratio,
+            self.0.settings.force_aspect_ratio.or_else(|| {
+                gst::Fraction::approximate_f32(video_frame.picture_aspect_ratio())
+                    .filter(|dar| dar.numer() > 0)
+            }),
+        ) {
+            (Some(par), _) => par,
+            (None, Some(dar)) if video_frame.xres() > 0 => {
+                dar * gst::Fraction::new(video_frame.yres(), video_frame.xres())
+            }
+            _ => gst::Fraction::new(1, 1),
+        };
         let interlace_mode = match video_frame.frame_format_type() {
             ndisys::NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive => {
                 gst_video::VideoInterlaceMode::Progressive
@@ -928,7 +2186,9 @@ impl Receiver {
             ndisys::NDIlib_FourCC_video_type_BGRA,
             ndisys::NDIlib_FourCC_video_type_BGRX,
             ndisys::NDIlib_FourCC_video_type_RGBA,
-            ndisys::NDIlib_FourCC_video_type_BGRX,
+            ndisys::NDIlib_FourCC_video_type_RGBX,
+            ndisys::NDIlib_FourCC_video_type_P216,
+            ndisys::NDIlib_FourCC_video_type_PA16,
         ]
         .contains(&fourcc)
         {
@@ -944,6 +2204,11 @@ impl Receiver {
                 ndisys::NDIlib_FourCC_video_type_BGRX => gst_video::VideoFormat::Bgrx,
                 ndisys::NDIlib_FourCC_video_type_RGBA => gst_video::VideoFormat::Rgba,
                 ndisys::NDIlib_FourCC_video_type_RGBX => gst_video::VideoFormat::Rgbx,
+                // GStreamer has no 16 bit 4:2:2 formats, these are converted to 16 bit 4:4:4
+                // when copying to keep the full precision
+                ndisys::NDIlib_FourCC_video_type_P216 | ndisys::NDIlib_FourCC_video_type_PA16 => {
+                    gst_video::VideoFormat::Ayuv64
+                }
                 _ => {
                     gst::element_error!(
                         element,
@@ -952,8 +2217,7 @@ impl Receiver {
                     );
 
                     return Err(gst::FlowError::NotNegotiated);
-                } // TODO: NDIlib_FourCC_video_type_P216 and NDIlib_FourCC_video_type_PA16 not
-                  // supported by GStreamer
+                }
             };
 
             #[cfg(feature = "interlaced-fields")]
@@ -963,7 +2227,7 @@ impl Receiver {
                     video_frame.xres() as u32,
                     video_frame.yres() as u32,
                 )
-                .fps(gst::Fraction::from(video_frame.frame_rate()))
+                .fps(self.output_frame_rate(video_frame))
                 .par(par)
                 .interlace_mode(interlace_mode);
 
@@ -991,7 +2255,7 @@ impl Receiver {
                     video_frame.xres() as u32,
                     video_frame.yres() as u32,
                 )
-                .fps(gst::Fraction::from(video_frame.frame_rate()))
+                .fps(self.output_frame_rate(video_frame))
                 .par(par)
                 .interlace_mode(interlace_mode);
 
@@ -1049,8 +2313,8 @@ impl Receiver {
                 variant,
                 xres: video_frame.xres(),
                 yres: video_frame.yres(),
-                fps_n: video_frame.frame_rate().0,
-                fps_d: video_frame.frame_rate().1,
+                fps_n: self.output_frame_rate(video_frame).numer(),
+                fps_d: self.output_frame_rate(video_frame).denom(),
                 par_n: par.numer(),
                 par_d: par.denom(),
                 interlace_mode,
@@ -1087,8 +2351,8 @@ impl Receiver {
             return Ok(VideoInfo::H264Info {
                 xres: video_frame.xres(),
                 yres: video_frame.yres(),
-                fps_n: video_frame.frame_rate().0,
-                fps_d: video_frame.frame_rate().1,
+                fps_n: self.output_frame_rate(video_frame).numer(),
+                fps_d: self.output_frame_rate(video_frame).denom(),
                 par_n: par.numer(),
                 par_d: par.denom(),
                 interlace_mode,
@@ -1125,8 +2389,8 @@ impl Receiver {
             return Ok(VideoInfo::H265Info {
                 xres: video_frame.xres(),
                 yres: video_frame.yres(),
-                fps_n: video_frame.frame_rate().0,
-                fps_d: video_frame.frame_rate().1,
+                fps_n: self.output_frame_rate(video_frame).numer(),
+                fps_d: self.output_frame_rate(video_frame).denom(),
                 par_n: par.numer(),
                 par_d: par.denom(),
                 interlace_mode,
@@ -1151,28 +2415,47 @@ impl Receiver {
     ) -> Result<gst::Buffer, gst::FlowError> {
         let mut buffer = self.copy_video_frame(element, info, video_frame)?;
         {
-            let buffer = buffer.get_mut().unwrap();
+            let buffer = buffer.make_mut();
             buffer.set_pts(pts);
             buffer.set_duration(duration);
 
+            // Only intra-coded video can be decoded in presentation order
+            let intra_only = match info {
+                VideoInfo::VideoInfo(_) => true,
+                #[cfg(feature = "advanced-sdk")]
+                VideoInfo::SpeedHQInfo { .. } => true,
+                #[cfg(feature = "advanced-sdk")]
+                _ => false,
+            };
+            if intra_only {
+                buffer.set_dts(pts);
+            }
+
             #[cfg(feature = "reference-timestamps")]
             {
                 gst::ReferenceTimestampMeta::add(
                     buffer,
                     &*TIMECODE_CAPS,
-                    gst::ClockTime::from_nseconds(video_frame.timecode() as u64 * 100),
+                    from_ndi_time(video_frame.timecode()),
                     gst::ClockTime::NONE,
                 );
                 if video_frame.timestamp() != ndisys::NDIlib_recv_timestamp_undefined {
                     gst::ReferenceTimestampMeta::add(
                         buffer,
                         &*TIMESTAMP_CAPS,
-                        gst::ClockTime::from_nseconds(video_frame.timestamp() as u64 * 100),
+                        from_ndi_time(video_frame.timestamp()),
                         gst::ClockTime::NONE,
                     );
                 }
             }
 
+            #[cfg(feature = "afd-bar-meta")]
+            {
+                if let Some(metadata) = video_frame.metadata() {
+                    add_afd_bar_meta(buffer, metadata);
+                }
+            }
+
             #[cfg(feature = "interlaced-fields")]
             {
                 match video_frame.frame_format_type() {
@@ -1215,7 +2498,7 @@ impl Receiver {
 
     fn copy_video_frame(
         &self,
-        #[allow(unused_variables)] element: &gst_base::BaseSrc,
+        element: &gst_base::BaseSrc,
         info: &VideoInfo,
         video_frame: &VideoFrame,
     ) -> Result<gst::Buffer, gst::FlowError> {
@@ -1223,126 +2506,114 @@ impl Receiver {
             VideoInfo::VideoInfo(ref info) => {
                 let src = video_frame.data().ok_or(gst::FlowError::Error)?;
 
-                let buffer = gst::Buffer::with_size(info.size()).unwrap();
-                let mut vframe = gst_video::VideoFrame::from_buffer_writable(buffer, info).unwrap();
+                let width = video_frame.xres() as usize;
+                let height = video_frame.yres() as usize;
+                let chroma_height = (height + 1) / 2;
+                let src_stride = video_frame.line_stride_or_data_size_in_bytes() as usize;
 
-                match info.format() {
-                    gst_video::VideoFormat::Uyvy
-                    | gst_video::VideoFormat::Bgra
+                // Offset, stride, bytes per line and number of lines of each plane in the
+                // NDI frame
+                let src_planes = match info.format() {
+                    gst_video::VideoFormat::Uyvy => vec![(0, src_stride, 2 * width, height)],
+                    gst_video::VideoFormat::Bgra
                     | gst_video::VideoFormat::Bgrx
                     | gst_video::VideoFormat::Rgba
-                    | gst_video::VideoFormat::Rgbx => {
-                        let line_bytes = if info.format() == gst_video::VideoFormat::Uyvy {
-                            2 * vframe.width() as usize
-                        } else {
-                            4 * vframe.width() as usize
-                        };
-                        let dest_stride = vframe.plane_stride()[0] as usize;
-                        let dest = vframe.plane_data_mut(0).unwrap();
-                        let src_stride = video_frame.line_stride_or_data_size_in_bytes() as usize;
+                    | gst_video::VideoFormat::Rgbx => vec![(0, src_stride, 4 * width, height)],
+                    gst_video::VideoFormat::Nv12 => vec![
+                        (0, src_stride, width, height),
+                        (height * src_stride, src_stride, width, chroma_height),
+                    ],
+                    gst_video::VideoFormat::Yv12 | gst_video::VideoFormat::I420 => {
+                        let chroma_stride = src_stride / 2;
+                        vec![
+                            (0, src_stride, width, height),
+                            (
+                                height * src_stride,
+                                chroma_stride,
+                                (width + 1) / 2,
+                                chroma_height,
+                            ),
+                            (
+                                height * src_stride + chroma_height * chroma_stride,
+                                chroma_stride,
+                                (width + 1) / 2,
+                                chroma_height,
+                            ),
+                        ]
+                    }
+                    // Converted from P216/PA16 instead of copied
+                    gst_video::VideoFormat::Ayuv64 => vec![],
+                    format => {
+                        gst::element_error!(
+                            element,
+                            gst::StreamError::Format,
+                            ["Unsupported video format {:?}", format]
+                        );
+                        return Err(gst::FlowError::NotNegotiated);
+                    }
+                };
+
+                let buffer = self.0.video_pool.lock().unwrap().acquire(info.size());
+                let mut buffer = buffer.ok_or_else(|| {
+                    gst::element_error!(
+                        element,
+                        gst::ResourceError::NoSpaceLeft,
+                        ["Failed to allocate video buffer of size {}", info.size()]
+                    );
+                    gst::FlowError::Error
+                })?;
+
+                {
+                    // The buffer is mapped only once and the planes are copied at the offsets
+                    // and with the strides of the video info
+                    let mut map = buffer.get_mut().unwrap().map_writable().map_err(|_| {
+                        gst::element_error!(
+                            element,
+                            gst::ResourceError::Write,
+                            ["Failed to map video buffer writable"]
+                        );
+                        gst::FlowError::Error
+                    })?;
+                    let dest = map.as_mut_slice();
+
+                    let same_layout = src_planes.iter().enumerate().all(
+                        |(plane, &(src_offset, src_stride, _, _))| {
+                            info.offset()[plane] == src_offset
+                                && info.stride()[plane] as usize == src_stride
+                        },
+                    );
 
-                        for (dest, src) in dest
-                            .chunks_exact_mut(dest_stride)
-                            .zip(src.chunks_exact(src_stride))
+                    if src_planes.is_empty() {
+                        let alpha = video_frame.fourcc() == ndisys::NDIlib_FourCC_video_type_PA16;
+                        convert_p216(dest, info, src, src_stride, width, height, alpha);
+                    } else if same_layout {
+                        // Everything in a single copy, split by lines of the first plane if
+                        // copied by multiple threads
+                        let (_, stride, line_bytes, _) = src_planes[0];
+                        self.0
+                            .copy_pool
+                            .copy_plane(dest, stride, src, stride, line_bytes);
+                    } else {
+                        for (plane, &(src_offset, src_stride, line_bytes, lines)) in
+                            src_planes.iter().enumerate()
                         {
-                            dest.copy_from_slice(src);
-                            dest.copy_from_slice(&src[..line_bytes]);
+                            let dest_offset = cmp::min(info.offset()[plane], dest.len());
+                            let dest_stride = info.stride()[plane] as usize;
+                            let dest_end = cmp::min(dest_offset + dest_stride * lines, dest.len());
+                            let src = src.get(src_offset..).unwrap_or_default();
+
+                            self.0.copy_pool.copy_plane(
+                                &mut dest[dest_offset..dest_end],
+                                dest_stride,
+                                src,
+                                src_stride,
+                                line_bytes,
+                            );
                         }
                     }
-                    gst_video::VideoFormat::Nv12 => {
-                        // First plane
-                        {
-                            let line_bytes = vframe.width() as usize;
-                            let dest_stride = vframe.plane_stride()[0] as usize;
-                            let dest = vframe.plane_data_mut(0).unwrap();
-                            let src_stride =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize;
-
-                            for (dest, src) in dest
-                                .chunks_exact_mut(dest_stride)
-                                .zip(src.chunks_exact(src_stride))
-                            {
-                                dest.copy_from_slice(&src[..line_bytes]);
-                            }
-                        }
+                }
 
-                        // Second plane
-                        {
-                            let line_bytes = vframe.width() as usize;
-                            let dest_stride = vframe.plane_stride()[1] as usize;
-                            let dest = vframe.plane_data_mut(1).unwrap();
-                            let src_stride =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize;
-                            let src = &src[(video_frame.yres() as usize * src_stride)..];
-
-                            for (dest, src) in dest
-                                .chunks_exact_mut(dest_stride)
-                                .zip(src.chunks_exact(src_stride))
-                            {
-                                dest.copy_from_slice(&src[..line_bytes]);
-                            }
-                        }
-                    }
-                    gst_video::VideoFormat::Yv12 | gst_video::VideoFormat::I420 => {
-                        // First plane
-                        {
-                            let line_bytes = vframe.width() as usize;
-                            let dest_stride = vframe.plane_stride()[0] as usize;
-                            let dest = vframe.plane_data_mut(0).unwrap();
-                            let src_stride =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize;
-
-                            for (dest, src) in dest
-                                .chunks_exact_mut(dest_stride)
-                                .zip(src.chunks_exact(src_stride))
-                            {
-                                dest.copy_from_slice(&src[..line_bytes]);
-                            }
-                        }
-
-                        // Second plane
-                        {
-                            let line_bytes = (vframe.width() as usize + 1) / 2;
-                            let dest_stride = vframe.plane_stride()[1] as usize;
-                            let dest = vframe.plane_data_mut(1).unwrap();
-                            let src_stride =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize;
-                            let src_stride1 =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize / 2;
-                            let src = &src[(video_frame.yres() as usize * src_stride)..];
-
-                            for (dest, src) in dest
-                                .chunks_exact_mut(dest_stride)
-                                .zip(src.chunks_exact(src_stride1))
-                            {
-                                dest.copy_from_slice(&src[..line_bytes]);
-                            }
-                        }
-
-                        // Third plane
-                        {
-                            let line_bytes = (vframe.width() as usize + 1) / 2;
-                            let dest_stride = vframe.plane_stride()[2] as usize;
-                            let dest = vframe.plane_data_mut(2).unwrap();
-                            let src_stride =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize;
-                            let src_stride1 =
-                                video_frame.line_stride_or_data_size_in_bytes() as usize / 2;
-                            let src = &src[(video_frame.yres() as usize * src_stride
-                                + (video_frame.yres() as usize + 1) / 2 * src_stride1)..];
-
-                            for (dest, src) in dest
-                                .chunks_exact_mut(dest_stride)
-                                .zip(src.chunks_exact(src_stride1))
-                            {
-                                dest.copy_from_slice(&src[..line_bytes]);
-                            }
-                        }
-                    }
-                    _ => unreachable!(),
-                }
-
-                Ok(vframe.into_buffer())
+                Ok(buffer)
             }
             #[cfg(feature = "advanced-sdk")]
             VideoInfo::SpeedHQInfo { .. } => {
@@ -1408,12 +2679,40 @@ impl Receiver {
 
         let info = self.create_audio_info(element, &audio_frame)?;
 
-        let mut buffer = self.create_audio_buffer(element, pts, duration, &info, &audio_frame)?;
+        let no_samples = audio_frame.no_samples() as usize;
+        let (pts, duration, out_samples, discont) = match info {
+            AudioInfo::AudioInfo(ref audio_info)
+                if self.0.settings.slave_method != AudioSlaveMethod::Skew =>
+            {
+                self.slave_audio(element, pts, discont, audio_info.rate(), no_samples)
+            }
+            _ => (pts, duration, no_samples, discont),
+        };
+
+        #[cfg(feature = "non-interleaved-audio")]
+        let audio_frame = match info {
+            AudioInfo::AudioInfo(ref audio_info)
+                if self.can_wrap_audio_frame(element, audio_info, &audio_frame, out_samples) =>
+            {
+                match audio_frame.into_owned_recv() {
+                    Ok(frame) => {
+                        let buffer =
+                            self.wrap_audio_frame(pts, duration, discont, audio_info, frame)?;
+                        gst_log!(CAT, obj: element, "Wrapped audio frame in buffer {:?}", buffer);
+                        return Ok(Buffer::Audio(buffer, info));
+                    }
+                    Err(audio_frame) => audio_frame,
+                }
+            }
+            _ => audio_frame,
+        };
+
+        let mut buffer =
+            self.create_audio_buffer(element, pts, duration, &info, &audio_frame, out_samples)?;
         if discont {
             buffer
-                .get_mut()
-                .unwrap()
-                .set_flags(gst::BufferFlags::RESYNC);
+                .make_mut()
+                .set_flags(gst::BufferFlags::DISCONT | gst::BufferFlags::RESYNC);
         }
 
         gst_log!(CAT, obj: element, "Produced audio buffer {:?}", buffer);
@@ -1421,6 +2720,86 @@ impl Receiver {
         Ok(Buffer::Audio(buffer, info))
     }
 
+    // Timestamps audio contiguously and, for the resample method, stretches or squeezes each
+    // buffer by a few samples so that the output follows the calculated timestamps without gaps
+    fn slave_audio(
+        &self,
+        element: &gst_base::BaseSrc,
+        pts: gst::ClockTime,
+        discont: bool,
+        rate: u32,
+        no_samples: usize,
+    ) -> (gst::ClockTime, Option<gst::ClockTime>, usize, bool) {
+        let mut slave = self.0.audio_slave.lock().unwrap();
+        let mut discont = discont;
+
+        let next_pts = slave
+            .base_pts
+            .filter(|_| !discont && slave.rate == rate)
+            .and_then(|base_pts| {
+                gst::ClockTime::SECOND
+                    .mul_div_floor(slave.samples, rate as u64)
+                    .map(|offset| base_pts + offset)
+            })
+            .filter(|next_pts| {
+                let diff = if *next_pts > pts {
+                    *next_pts - pts
+                } else {
+                    pts - *next_pts
+                };
+                diff <= AUDIO_RESYNC_THRESHOLD
+            });
+
+        let next_pts = match next_pts {
+            Some(next_pts) => next_pts,
+            None => {
+                if slave.base_pts.is_some() {
+                    gst_debug!(CAT, obj: element, "Resyncing audio to {}", pts);
+                    discont = true;
+                }
+                *slave = AudioSlave {
+                    base_pts: Some(pts),
+                    rate,
+                    samples: 0,
+                };
+                pts
+            }
+        };
+
+        let out_samples =
+            if self.0.settings.slave_method == AudioSlaveMethod::Resample && no_samples > 1 {
+                // Positive if the output is behind and more samples are needed to catch up
+                let error = (pts.nseconds() as i128 - next_pts.nseconds() as i128) * rate as i128
+                    / 1_000_000_000;
+                // Correct slowly and by at most 0.5% to keep the pitch change inaudible
+                let max_correction = cmp::max(1, no_samples as i128 / 200);
+                let correction = (error / 8).clamp(-max_correction, max_correction);
+                (no_samples as i128 + correction) as usize
+            } else {
+                no_samples
+            };
+
+        gst_trace!(
+            CAT,
+            obj: element,
+            "Calculated PTS {}, slaved PTS {}, {} samples to {} samples",
+            pts,
+            next_pts,
+            no_samples,
+            out_samples,
+        );
+
+        slave.samples += out_samples as u64;
+        let end_pts = slave.base_pts.and_then(|base_pts| {
+            gst::ClockTime::SECOND
+                .mul_div_floor(slave.samples, rate as u64)
+                .map(|offset| base_pts + offset)
+        });
+        let duration = end_pts.map(|end_pts| end_pts.saturating_sub(next_pts));
+
+        (next_pts, duration, out_samples, discont)
+    }
+
     fn calculate_audio_timestamp(
         &self,
         element: &gst_base::BaseSrc,
@@ -1447,11 +2826,29 @@ impl Receiver {
         let fourcc = audio_frame.fourcc();
 
         if [NDIlib_FourCC_audio_type_FLTp].contains(&fourcc) {
+            let positions = if self.downmixes(audio_frame) {
+                vec![
+                    gst_audio::AudioChannelPosition::FrontLeft,
+                    gst_audio::AudioChannelPosition::FrontRight,
+                ]
+            } else {
+                // Channels are reordered to GStreamer's canonical order if necessary
+                let mut positions = self.audio_channel_positions(element, audio_frame);
+                let _ = gst_audio::AudioChannelPosition::positions_to_valid_order(&mut positions);
+                positions
+            };
+
             let builder = gst_audio::AudioInfo::builder(
                 gst_audio::AUDIO_FORMAT_F32,
                 audio_frame.sample_rate() as u32,
-                audio_frame.no_channels() as u32,
-            );
+                positions.len() as u32,
+            )
+            .positions(&positions);
+
+            // NDI audio is planar, so pass it on like that and let ndisrcdemux interleave it
+            // only if downstream can't handle planar audio
+            #[cfg(feature = "non-interleaved-audio")]
+            let builder = builder.layout(gst_audio::AudioLayout::NonInterleaved);
 
             let info = builder.build().map_err(|_| {
                 gst::element_error!(
@@ -1510,22 +2907,177 @@ impl Receiver {
         Err(gst::FlowError::NotNegotiated)
     }
 
+    fn audio_channel_positions(
+        &self,
+        element: &gst_base::BaseSrc,
+        audio_frame: &AudioFrame,
+    ) -> Vec<gst_audio::AudioChannelPosition> {
+        let channels = audio_frame.no_channels() as usize;
+
+        if let Some(ref positions) = self.0.settings.channel_positions {
+            if positions.len() == channels {
+                return positions.clone();
+            }
+
+            gst_debug!(
+                CAT,
+                obj: element,
+                "Configured channel positions {:?} don't match {} channels, ignoring",
+                positions,
+                channels
+            );
+        }
+
+        let mut positions = vec![gst_audio::AudioChannelPosition::Invalid; channels];
+
+        if let Some(mask) = audio_frame.metadata().and_then(channel_mask_from_metadata) {
+            if mask.count_ones() as usize == channels
+                && gst_audio::AudioChannelPosition::positions_from_mask(mask, &mut positions)
+                    .is_ok()
+            {
+                return positions;
+            }
+
+            gst_debug!(
+                CAT,
+                obj: element,
+                "Channel mask {:#x} from metadata doesn't match {} channels, ignoring",
+                mask,
+                channels
+            );
+        }
+
+        // There is no default layout for more than 8 channels, in which case the fallback mask
+        // is 0 and all channels are negotiated as unpositioned
+        let mask = gst_audio::AudioChannelPosition::fallback_mask(channels as u32);
+        if gst_audio::AudioChannelPosition::positions_from_mask(mask, &mut positions).is_err() {
+            positions.fill(gst_audio::AudioChannelPosition::None);
+        }
+
+        positions
+    }
+
+    // Non-interleaved audio that needs no resampling, gain, downmixing or reordering can be
+    // output directly in the memory of the NDI frame. Only tightly packed planes are wrapped,
+    // the level measurement and the audio chunker don't look at the AudioMeta offsets
+    #[cfg(feature = "non-interleaved-audio")]
+    fn can_wrap_audio_frame(
+        &self,
+        element: &gst_base::BaseSrc,
+        info: &gst_audio::AudioInfo,
+        audio_frame: &AudioFrame,
+        out_samples: usize,
+    ) -> bool {
+        let no_samples = audio_frame.no_samples() as usize;
+        let channel_stride = audio_frame.channel_stride_or_data_size_in_bytes() as usize;
+
+        info.layout() == gst_audio::AudioLayout::NonInterleaved
+            && info.format() == gst_audio::AUDIO_FORMAT_F32
+            && info.channels() as i32 == audio_frame.no_channels()
+            && out_samples == no_samples
+            && no_samples > 0
+            && channel_stride == no_samples * mem::size_of::<f32>()
+            && self.0.settings.reference_level == 0
+            && !self.downmixes(audio_frame)
+            && canonical_channel_order(&self.audio_channel_positions(element, audio_frame))
+                .is_none()
+    }
+
+    #[cfg(feature = "non-interleaved-audio")]
+    fn wrap_audio_frame(
+        &self,
+        pts: gst::ClockTime,
+        duration: Option<gst::ClockTime>,
+        discont: bool,
+        info: &gst_audio::AudioInfo,
+        audio_frame: ndi::OwnedRecvAudioFrame,
+    ) -> Result<gst::Buffer, gst::FlowError> {
+        let no_samples = audio_frame.no_samples() as usize;
+        let offsets = (0..audio_frame.no_channels() as usize)
+            .map(|channel| channel * audio_frame.channel_stride() as usize)
+            .collect::<Vec<_>>();
+        #[cfg(feature = "reference-timestamps")]
+        let (timecode, timestamp) = (audio_frame.timecode(), audio_frame.timestamp());
+
+        let mut buffer = gst::Buffer::from_slice(audio_frame);
+        {
+            let buffer = buffer.get_mut().unwrap();
+
+            buffer.set_pts(pts);
+            buffer.set_duration(duration);
+            if discont {
+                buffer.set_flags(gst::BufferFlags::DISCONT | gst::BufferFlags::RESYNC);
+            }
+
+            #[cfg(feature = "reference-timestamps")]
+            {
+                gst::ReferenceTimestampMeta::add(
+                    buffer,
+                    &TIMECODE_CAPS,
+                    from_ndi_time(timecode),
+                    gst::ClockTime::NONE,
+                );
+                if timestamp != ndisys::NDIlib_recv_timestamp_undefined {
+                    gst::ReferenceTimestampMeta::add(
+                        buffer,
+                        &TIMESTAMP_CAPS,
+                        from_ndi_time(timestamp),
+                        gst::ClockTime::NONE,
+                    );
+                }
+            }
+
+            gst_audio::AudioMeta::add(buffer, info, no_samples, &offsets)
+                .map_err(|_| gst::FlowError::NotNegotiated)?;
+        }
+
+        Ok(buffer)
+    }
+
+    fn downmixes(&self, audio_frame: &AudioFrame) -> bool {
+        self.0.settings.downmix_to_stereo && audio_frame.no_channels() > 2
+    }
+
     fn create_audio_buffer(
         &self,
-        #[allow(unused_variables)] element: &gst_base::BaseSrc,
+        element: &gst_base::BaseSrc,
         pts: gst::ClockTime,
         duration: Option<gst::ClockTime>,
         info: &AudioInfo,
         audio_frame: &AudioFrame,
+        out_samples: usize,
     ) -> Result<gst::Buffer, gst::FlowError> {
         match info {
             AudioInfo::AudioInfo(ref info) => {
                 let src = audio_frame.data().ok_or(gst::FlowError::Error)?;
-                let buff_size = (audio_frame.no_samples() as u32 * info.bpf()) as usize;
+                let buff_size = out_samples * info.bpf() as usize;
+
+                let no_samples = audio_frame.no_samples() as usize;
+                let channel_stride = audio_frame.channel_stride_or_data_size_in_bytes() as usize;
+                if no_samples > 0 && channel_stride < no_samples * mem::size_of::<f32>() {
+                    gst::element_warning!(
+                        element,
+                        gst::StreamError::Decode,
+                        [
+                            "Dropping audio frame with channel stride {} for {} samples",
+                            channel_stride,
+                            no_samples
+                        ]
+                    );
+                    return Err(FLOW_DROPPED);
+                }
 
-                let mut buffer = gst::Buffer::with_size(buff_size).unwrap();
+                let buffer = self.0.audio_pool.lock().unwrap().acquire(buff_size);
+                let mut buffer = buffer.ok_or_else(|| {
+                    gst::element_error!(
+                        element,
+                        gst::ResourceError::NoSpaceLeft,
+                        ["Failed to allocate audio buffer of size {}", buff_size]
+                    );
+                    gst::FlowError::Error
+                })?;
                 {
-                    let buffer = buffer.get_mut().unwrap();
+                    let buffer = buffer.make_mut();
 
                     buffer.set_pts(pts);
                     buffer.set_duration(duration);
@@ -1535,42 +3087,141 @@ impl Receiver {
                         gst::ReferenceTimestampMeta::add(
                             buffer,
                             &*TIMECODE_CAPS,
-                            gst::ClockTime::from_nseconds(audio_frame.timecode() as u64 * 100),
+                            from_ndi_time(audio_frame.timecode()),
                             gst::ClockTime::NONE,
                         );
                         if audio_frame.timestamp() != ndisys::NDIlib_recv_timestamp_undefined {
                             gst::ReferenceTimestampMeta::add(
                                 buffer,
                                 &*TIMESTAMP_CAPS,
-                                gst::ClockTime::from_nseconds(audio_frame.timestamp() as u64 * 100),
+                                from_ndi_time(audio_frame.timestamp()),
                                 gst::ClockTime::NONE,
                             );
                         }
                     }
 
-                    let mut dest = buffer.map_writable().unwrap();
-                    let dest = dest
+                    #[cfg(feature = "non-interleaved-audio")]
+                    {
+                        gst_audio::AudioMeta::add(buffer, info, out_samples, &[])
+                            .map_err(|_| gst::FlowError::NotNegotiated)?;
+                    }
+
+                    let mut dest_map = buffer.map_writable().map_err(|_| {
+                        gst::element_error!(
+                            element,
+                            gst::ResourceError::Write,
+                            ["Failed to map audio buffer writable"]
+                        );
+                        gst::FlowError::Error
+                    })?;
+                    let dest = dest_map
                         .as_mut_slice_of::<f32>()
                         .map_err(|_| gst::FlowError::NotNegotiated)?;
-                    assert!(
-                        dest.len()
-                            == audio_frame.no_samples() as usize
-                                * audio_frame.no_channels() as usize
-                    );
+                    if dest.len() != out_samples * info.channels() as usize {
+                        gst::element_error!(
+                            element,
+                            gst::StreamError::Format,
+                            ["Audio frame doesn't match the negotiated format"]
+                        );
+                        return Err(gst::FlowError::NotNegotiated);
+                    }
 
-                    for (channel, samples) in src
-                        .chunks_exact(audio_frame.channel_stride_or_data_size_in_bytes() as usize)
-                        .enumerate()
-                    {
-                        let samples = samples
-                            .as_slice_of::<f32>()
-                            .map_err(|_| gst::FlowError::NotNegotiated)?;
+                    let gain = 10.0f32.powf(-(self.0.settings.reference_level as f32) / 20.0);
+                    let (out_channel_step, out_sample_step) =
+                        if info.layout() == gst_audio::AudioLayout::NonInterleaved {
+                            (out_samples, 1)
+                        } else {
+                            (1, info.channels() as usize)
+                        };
 
-                        for (i, sample) in samples[..audio_frame.no_samples() as usize]
-                            .iter()
-                            .enumerate()
-                        {
-                            dest[i * (audio_frame.no_channels() as usize) + channel] = *sample;
+                    // When downmixing, all channels are first collected in planar form
+                    let mut planar = Vec::new();
+                    let (dest, channel_step, sample_step) = if self.downmixes(audio_frame) {
+                        planar.resize(out_samples * audio_frame.no_channels() as usize, 0.0);
+                        (&mut planar[..], out_samples, 1)
+                    } else {
+                        (dest, out_channel_step, out_sample_step)
+                    };
+
+                    let order = if self.downmixes(audio_frame) {
+                        None
+                    } else {
+                        canonical_channel_order(&self.audio_channel_positions(element, audio_frame))
+                    };
+
+                    let channels = src
+                        .chunks_exact(cmp::max(channel_stride, 1))
+                        .take(audio_frame.no_channels() as usize)
+                        .map(|samples| {
+                            samples
+                                .as_slice_of::<f32>()
+                                .map(|samples| &samples[..no_samples])
+                                .map_err(|_| gst::FlowError::NotNegotiated)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    // Interleaved stereo is the most common case and has its own SIMD path
+                    if let ([left, right], None, 2, true) = (
+                        &channels[..],
+                        &order,
+                        sample_step,
+                        out_samples == no_samples,
+                    ) {
+                        interleave_stereo(dest, left, right, gain);
+                    } else {
+                        for (channel, samples) in channels.into_iter().enumerate() {
+                            let channel = order
+                                .as_ref()
+                                .map(|order| order[channel])
+                                .unwrap_or(channel);
+
+                            if out_samples != no_samples {
+                                // Linear interpolation is good enough for stretching by a few
+                                // samples
+                                let step =
+                                    (no_samples - 1) as f64 / cmp::max(out_samples - 1, 1) as f64;
+                                for i in 0..out_samples {
+                                    let pos = i as f64 * step;
+                                    let idx = cmp::min(pos as usize, no_samples - 1);
+                                    let frac = (pos - idx as f64) as f32;
+                                    let next = samples[cmp::min(idx + 1, no_samples - 1)];
+                                    let sample = samples[idx] + (next - samples[idx]) * frac;
+                                    dest[channel * channel_step + i * sample_step] = sample * gain;
+                                }
+                            } else if sample_step == 1 {
+                                // Planar output is a plain copy per channel, keep that in a form
+                                // the compiler can vectorize as it matters on low-end ARM CPUs
+                                let dest = &mut dest[channel * channel_step..][..no_samples];
+                                #[allow(clippy::float_cmp)]
+                                if gain == 1.0 {
+                                    dest.copy_from_slice(samples);
+                                } else {
+                                    for (dest, sample) in dest.iter_mut().zip(samples) {
+                                        *dest = *sample * gain;
+                                    }
+                                }
+                            } else {
+                                for (i, sample) in samples.iter().enumerate() {
+                                    dest[channel * channel_step + i * sample_step] = *sample * gain;
+                                }
+                            }
+                        }
+                    }
+
+                    if self.downmixes(audio_frame) {
+                        let matrix =
+                            downmix_matrix(&self.audio_channel_positions(element, audio_frame));
+                        let dest = dest_map
+                            .as_mut_slice_of::<f32>()
+                            .map_err(|_| gst::FlowError::NotNegotiated)?;
+                        let mut left = vec![0.0; out_samples];
+                        let mut right = vec![0.0; out_samples];
+                        downmix_stereo(&mut left, &mut right, &planar, out_samples, &matrix);
+                        if out_sample_step == 1 {
+                            dest[..out_samples].copy_from_slice(&left);
+                            dest[out_channel_step..][..out_samples].copy_from_slice(&right);
+                        } else {
+                            interleave_stereo(dest, &left, &right, 1.0);
                         }
                     }
                 }
@@ -1616,3 +3267,926 @@ impl Receiver {
         }
     }
 }
+
+// Exact framerate of the sender as a fraction, e.g. 30000/1001, and 0/1 if it's not set
+fn frame_rate(video_frame: &VideoFrame) -> gst::Fraction {
+    match video_frame.frame_rate() {
+        (fps_n, fps_d) if fps_n > 0 && fps_d > 0 => gst::Fraction::new(fps_n, fps_d),
+        _ => gst::Fraction::new(0, 1),
+    }
+}
+
+// Index of every channel in GStreamer's canonical channel order, or None if the channels are
+// already in that order or unpositioned
+fn canonical_channel_order(positions: &[gst_audio::AudioChannelPosition]) -> Option<Vec<usize>> {
+    let mut sorted = positions.to_vec();
+    if gst_audio::AudioChannelPosition::positions_to_valid_order(&mut sorted).is_err()
+        || sorted == positions
+    {
+        return None;
+    }
+
+    positions
+        .iter()
+        .map(|position| sorted.iter().position(|sorted| sorted == position))
+        .collect()
+}
+
+// Gains of every channel for the left and right output channel, with the usual -3dB for center
+// and surround channels and without LFE, normalized so that the output can't clip
+fn downmix_matrix(positions: &[gst_audio::AudioChannelPosition]) -> Vec<[f32; 2]> {
+    use gst_audio::AudioChannelPosition::*;
+
+    const CENTER: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+    let mut matrix = positions
+        .iter()
+        .enumerate()
+        .map(|(channel, position)| match position {
+            FrontLeft | FrontLeftOfCenter | WideLeft | TopFrontLeft => [1.0, 0.0],
+            FrontRight | FrontRightOfCenter | WideRight | TopFrontRight => [0.0, 1.0],
+            FrontCenter | Mono | TopFrontCenter | TopCenter => [CENTER, CENTER],
+            RearLeft | SideLeft | SurroundLeft | TopRearLeft | TopSideLeft => [CENTER, 0.0],
+            RearRight | SideRight | SurroundRight | TopRearRight | TopSideRight => [0.0, CENTER],
+            RearCenter | TopRearCenter => [0.5, 0.5],
+            Lfe1 | Lfe2 => [0.0, 0.0],
+            // Unpositioned channels alternate between left and right
+            _ if channel % 2 == 0 => [1.0, 0.0],
+            _ => [0.0, 1.0],
+        })
+        .collect::<Vec<_>>();
+
+    for output in 0..2 {
+        let sum = matrix.iter().map(|gains| gains[output]).sum::<f32>();
+        if sum > 1.0 {
+            for gains in &mut matrix {
+                gains[output] /= sum;
+            }
+        }
+    }
+
+    matrix
+}
+
+// Some senders describe the channel layout of their audio in the frame metadata, e.g.
+// `<ndi_audio_layout channel_mask="0x3f"/>`
+fn channel_mask_from_metadata(metadata: &str) -> Option<u64> {
+    let start = metadata
+        .find("channel_mask=\"")
+        .or_else(|| metadata.find("channel-mask=\""))?
+        + "channel_mask=\"".len();
+    let value = &metadata[start..];
+    let value = &value[..value.find('"')?];
+
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+// Ad-insertion markers as SCTE-35 XML (SCTE 35 section 13 / SCTE 224), e.g.
+// `<SpliceInfoSection><SpliceInsert spliceEventId="1" outOfNetworkIndicator="true">...`
+fn splice_from_metadata(
+    metadata: &str,
+    timecode: gst::ClockTime,
+    running_time: Option<gst::ClockTime>,
+) -> Option<gst::Structure> {
+    let command = if metadata.contains("SpliceInsert") {
+        "splice-insert"
+    } else if metadata.contains("TimeSignal") {
+        "time-signal"
+    } else if metadata.contains("SpliceInfoSection")
+        || metadata.contains("scte35")
+        || metadata.contains("SCTE35")
+    {
+        "trigger"
+    } else {
+        return None;
+    };
+
+    let flag = |name| {
+        metadata_attribute(metadata, name)
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false)
+    };
+    // 90kHz MPEG-TS clock ticks
+    let ticks = |name| {
+        metadata_attribute(metadata, name)
+            .and_then(|value| value.parse::<u64>().ok())
+            .and_then(|ticks| ticks.mul_div_floor(100_000, 9))
+            .map(gst::ClockTime::from_nseconds)
+    };
+
+    let mut s = gst::Structure::builder("ndi-splice")
+        .field("command", command)
+        .field("out-of-network", flag("outOfNetworkIndicator"))
+        .field("cancel", flag("spliceEventCancelIndicator"))
+        .field("auto-return", flag("autoReturn"))
+        .field("timecode", timecode)
+        .field("metadata", metadata)
+        .build();
+    if let Some(event_id) =
+        metadata_attribute(metadata, "spliceEventId").and_then(parse_metadata_number)
+    {
+        s.set("event-id", event_id);
+    }
+    if let Some(duration) = ticks("duration") {
+        s.set("duration", duration);
+    }
+    if let Some(pts_time) = ticks("ptsTime") {
+        s.set("pts-time", pts_time);
+    }
+    if let Some(running_time) = running_time {
+        s.set("running-time", running_time);
+    }
+
+    Some(s)
+}
+
+// Value of an XML attribute anywhere in the metadata, e.g. `afd="10"`
+fn metadata_attribute<'a>(metadata: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = metadata;
+    loop {
+        let pos = rest.find(name)?;
+        let preceded_by_space = rest[..pos]
+            .chars()
+            .last()
+            .map(char::is_whitespace)
+            .unwrap_or(false);
+        let value = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+
+        if !preceded_by_space {
+            continue;
+        }
+        let value = match value.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let quote = match value.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => continue,
+        };
+        let value = &value[1..];
+        return Some(&value[..value.find(quote)?]);
+    }
+}
+
+fn parse_metadata_number(value: &str) -> Option<u32> {
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+// Converters bridging SDI to NDI carry the AFD code and bar data of the ancillary data as
+// attributes in the frame metadata, e.g.
+// `<ndi_afd afd="10" spec="smpte" bar_top="60" bar_bottom="1020"/>`
+#[cfg(feature = "afd-bar-meta")]
+fn add_afd_bar_meta(buffer: &mut gst::BufferRef, metadata: &str) {
+    let field = metadata_attribute(metadata, "field")
+        .and_then(parse_metadata_number)
+        .map(|field| field as u8)
+        .unwrap_or(0);
+
+    if let Some(afd) = metadata_attribute(metadata, "afd").and_then(parse_metadata_number) {
+        let afd = match afd & 0xf {
+            2 => Some(gst_video::VideoAFDValue::_169TopAligned),
+            3 => Some(gst_video::VideoAFDValue::_149TopAligned),
+            4 => Some(gst_video::VideoAFDValue::GreaterThan169),
+            8 => Some(gst_video::VideoAFDValue::_43Full169Full),
+            9 => Some(gst_video::VideoAFDValue::_43Full43Pillar),
+            10 => Some(gst_video::VideoAFDValue::_169Letter169Full),
+            11 => Some(gst_video::VideoAFDValue::_149Letter149Pillar),
+            13 => Some(gst_video::VideoAFDValue::_43Full149Center),
+            14 => Some(gst_video::VideoAFDValue::_169Letter149Center),
+            15 => Some(gst_video::VideoAFDValue::_169Letter43Center),
+            _ => None,
+        };
+        let spec = match metadata_attribute(metadata, "spec").map(str::to_ascii_lowercase) {
+            Some(ref spec) if spec.starts_with("dvb") => gst_video::VideoAFDSpec::DvbEtsi,
+            Some(ref spec) if spec.starts_with("atsc") => gst_video::VideoAFDSpec::AtscA53,
+            _ => gst_video::VideoAFDSpec::SmpteSt20161,
+        };
+
+        if let Some(afd) = afd {
+            gst_video::VideoAFDMeta::add(buffer, field, spec, afd);
+        }
+    }
+
+    let letterbox = metadata_attribute(metadata, "bar_top")
+        .and_then(parse_metadata_number)
+        .zip(metadata_attribute(metadata, "bar_bottom").and_then(parse_metadata_number));
+    let pillarbox = metadata_attribute(metadata, "bar_left")
+        .and_then(parse_metadata_number)
+        .zip(metadata_attribute(metadata, "bar_right").and_then(parse_metadata_number));
+    if let Some((top, bottom)) = letterbox {
+        gst_video::VideoBarMeta::add(buffer, field, true, top, bottom);
+    } else if let Some((left, right)) = pillarbox {
+        gst_video::VideoBarMeta::add(buffer, field, false, left, right);
+    }
+}
+
+// Converts 16 bit semi-planar 4:2:2 (P216, or PA16 with an additional alpha plane) to
+// AYUV64. Every chroma sample is used for both pixels it covers, so no precision is lost.
+fn convert_p216(
+    dest: &mut [u8],
+    info: &gst_video::VideoInfo,
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    alpha: bool,
+) {
+    let dest_offset = info.offset()[0];
+    let dest_stride = info.stride()[0] as usize;
+    let chroma_offset = height * src_stride;
+
+    let src_line = |offset: usize, y: usize| {
+        let start = cmp::min(offset + y * src_stride, src.len());
+        &src[start..cmp::min(start + src_stride, src.len())]
+    };
+
+    for y in 0..height {
+        let start = cmp::min(dest_offset + y * dest_stride, dest.len());
+        let end = cmp::min(start + width * 8, dest.len());
+        pack_ayuv64_line(
+            &mut dest[start..end],
+            src_line(0, y),
+            src_line(chroma_offset, y),
+            if alpha {
+                Some(src_line(2 * chroma_offset, y))
+            } else {
+                None
+            },
+        );
+    }
+}
+
+// Packs a line of 16 bit little endian luma, interleaved U and V and optionally alpha samples
+// into native endian AYUV64 pixels, fully opaque without alpha. This uses SSE2 on x86-64 and
+// NEON on aarch64 for all but the last few pixels.
+fn pack_ayuv64_line(dest: &mut [u8], luma: &[u8], chroma: &[u8], alpha: Option<&[u8]>) {
+    let pixels = cmp::min(
+        cmp::min(dest.len() / 8, luma.len() / 2),
+        cmp::min(
+            chroma.len() / 4 * 2,
+            alpha.map_or(usize::MAX, |alpha| alpha.len() / 2),
+        ),
+    );
+    #[allow(unused_mut)]
+    let mut done = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::*;
+
+        while done + 4 <= pixels {
+            let y = _mm_loadl_epi64(luma.as_ptr().add(2 * done) as *const __m128i);
+            let uv = _mm_loadl_epi64(chroma.as_ptr().add(2 * done) as *const __m128i);
+            let a = match alpha {
+                Some(alpha) => _mm_loadl_epi64(alpha.as_ptr().add(2 * done) as *const __m128i),
+                None => _mm_set1_epi16(-1),
+            };
+            // A0 Y0 A1 Y1 A2 Y2 A3 Y3 and U0 V0 U0 V0 U1 V1 U1 V1
+            let ay = _mm_unpacklo_epi16(a, y);
+            let uv = _mm_unpacklo_epi32(uv, uv);
+            let dest = dest.as_mut_ptr().add(8 * done) as *mut __m128i;
+            _mm_storeu_si128(dest, _mm_unpacklo_epi32(ay, uv));
+            _mm_storeu_si128(dest.add(1), _mm_unpackhi_epi32(ay, uv));
+            done += 4;
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+    unsafe {
+        use std::arch::aarch64::*;
+
+        while done + 8 <= pixels {
+            let y = vreinterpretq_u16_u8(vld1q_u8(luma.as_ptr().add(2 * done)));
+            let uv = vreinterpretq_u16_u8(vld1q_u8(chroma.as_ptr().add(2 * done)));
+            let a = match alpha {
+                Some(alpha) => vreinterpretq_u16_u8(vld1q_u8(alpha.as_ptr().add(2 * done))),
+                None => vdupq_n_u16(u16::MAX),
+            };
+            // U0 U0 U1 U1 U2 U2 U3 U3 and the same for V
+            let u = vuzp1q_u16(uv, uv);
+            let v = vuzp2q_u16(uv, uv);
+            let (u, v) = (vzip1q_u16(u, u), vzip1q_u16(v, v));
+            // A0 U0 A1 U0 ... and Y0 V0 Y1 V0 ..., zipped again into A Y U V per pixel
+            let (au_low, au_high) = (vzip1q_u16(a, u), vzip2q_u16(a, u));
+            let (yv_low, yv_high) = (vzip1q_u16(y, v), vzip2q_u16(y, v));
+            // Stored as bytes as the destination is not necessarily aligned for u16
+            vst1q_u8_x4(
+                dest.as_mut_ptr().add(8 * done),
+                uint8x16x4_t(
+                    vreinterpretq_u8_u16(vzip1q_u16(au_low, yv_low)),
+                    vreinterpretq_u8_u16(vzip2q_u16(au_low, yv_low)),
+                    vreinterpretq_u8_u16(vzip1q_u16(au_high, yv_high)),
+                    vreinterpretq_u8_u16(vzip2q_u16(au_high, yv_high)),
+                ),
+            );
+            done += 8;
+        }
+    }
+
+    let sample = |data: &[u8], i: usize| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]);
+    for (i, dest) in dest[8 * done..8 * pixels]
+        .chunks_exact_mut(8)
+        .enumerate()
+        .map(|(i, dest)| (done + i, dest))
+    {
+        let a = alpha.map_or(u16::MAX, |alpha| sample(alpha, i));
+        dest[0..2].copy_from_slice(&a.to_ne_bytes());
+        dest[2..4].copy_from_slice(&sample(luma, i).to_ne_bytes());
+        dest[4..6].copy_from_slice(&sample(chroma, i / 2 * 2).to_ne_bytes());
+        dest[6..8].copy_from_slice(&sample(chroma, i / 2 * 2 + 1).to_ne_bytes());
+    }
+}
+
+// Interleaves two channels into stereo frames while applying the gain. This uses SSE2 on
+// x86-64 and NEON on aarch64, which makes a noticeable difference for the audio thread on a
+// Raspberry Pi; the remaining samples, and all samples on other architectures, take the plain
+// loop.
+fn interleave_stereo(dest: &mut [f32], left: &[f32], right: &[f32], gain: f32) {
+    let samples = cmp::min(dest.len() / 2, cmp::min(left.len(), right.len()));
+    #[allow(unused_mut)]
+    let mut done = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::*;
+
+        let gain = _mm_set1_ps(gain);
+        while done + 4 <= samples {
+            let left = _mm_mul_ps(_mm_loadu_ps(left.as_ptr().add(done)), gain);
+            let right = _mm_mul_ps(_mm_loadu_ps(right.as_ptr().add(done)), gain);
+            let dest = dest.as_mut_ptr().add(2 * done);
+            _mm_storeu_ps(dest, _mm_unpacklo_ps(left, right));
+            _mm_storeu_ps(dest.add(4), _mm_unpackhi_ps(left, right));
+            done += 4;
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        use std::arch::aarch64::*;
+
+        let gain = vdupq_n_f32(gain);
+        while done + 4 <= samples {
+            let left = vmulq_f32(vld1q_f32(left.as_ptr().add(done)), gain);
+            let right = vmulq_f32(vld1q_f32(right.as_ptr().add(done)), gain);
+            vst2q_f32(dest.as_mut_ptr().add(2 * done), float32x4x2_t(left, right));
+            done += 4;
+        }
+    }
+
+    for ((dest, left), right) in dest[2 * done..2 * samples]
+        .chunks_exact_mut(2)
+        .zip(&left[done..samples])
+        .zip(&right[done..samples])
+    {
+        dest[0] = *left * gain;
+        dest[1] = *right * gain;
+    }
+}
+
+// Mixes planar channels down to separate left and right channels. Going through the matrix
+// per channel instead of per sample keeps the inner loop in a form the compiler vectorizes.
+fn downmix_stereo(
+    left: &mut [f32],
+    right: &mut [f32],
+    planar: &[f32],
+    samples: usize,
+    matrix: &[[f32; 2]],
+) {
+    for (channel, [left_gain, right_gain]) in planar.chunks_exact(cmp::max(samples, 1)).zip(matrix)
+    {
+        for ((left, right), sample) in left.iter_mut().zip(right.iter_mut()).zip(channel) {
+            *left += sample * left_gain;
+            *right += sample * right_gain;
+        }
+    }
+}
+
+// Planes smaller than this are copied by a single thread as the other threads wouldn't pay off
+const MIN_THREADED_COPY_SIZE: usize = 1024 * 1024;
+
+// Lines of a plane to be copied by a worker of the copy pool. The pointers stay valid until
+// the worker reported back as CopyPool::copy_plane() waits for that before returning.
+struct CopyJob {
+    dest: *mut u8,
+    dest_len: usize,
+    dest_stride: usize,
+    src: *const u8,
+    src_len: usize,
+    src_stride: usize,
+    line_bytes: usize,
+}
+
+unsafe impl Send for CopyJob {}
+
+// Worker threads that are started once per receiver and each copy a range of lines of large
+// planes, while the calling thread copies the first range itself. Public for the benchmarks
+// only.
+pub struct CopyPool(Mutex<CopyPoolInner>);
+
+struct CopyPoolInner {
+    workers: Vec<(mpsc::Sender<CopyJob>, thread::JoinHandle<()>)>,
+    done: mpsc::Receiver<()>,
+}
+
+impl CopyPool {
+    pub fn new(n_threads: usize) -> Self {
+        let (done_sender, done) = mpsc::channel();
+
+        let workers = (1..n_threads)
+            .filter_map(|i| {
+                let (sender, jobs) = mpsc::channel::<CopyJob>();
+                let done_sender = done_sender.clone();
+                let thread = thread::Builder::new()
+                    .name(format!("ndi-copy-{}", i))
+                    .spawn(move || {
+                        for job in jobs {
+                            unsafe {
+                                copy_plane(
+                                    std::slice::from_raw_parts_mut(job.dest, job.dest_len),
+                                    job.dest_stride,
+                                    std::slice::from_raw_parts(job.src, job.src_len),
+                                    job.src_stride,
+                                    job.line_bytes,
+                                );
+                            }
+                            if done_sender.send(()).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .ok()?;
+
+                Some((sender, thread))
+            })
+            .collect();
+
+        CopyPool(Mutex::new(CopyPoolInner { workers, done }))
+    }
+
+    // Same as copy_plane() but large planes are split into ranges of lines that are copied in
+    // parallel by the workers and the calling thread
+    pub fn copy_plane(
+        &self,
+        dest: &mut [u8],
+        dest_stride: usize,
+        src: &[u8],
+        src_stride: usize,
+        line_bytes: usize,
+    ) {
+        let inner = self.0.lock().unwrap();
+        if inner.workers.is_empty()
+            || dest.len() < MIN_THREADED_COPY_SIZE
+            || dest_stride == 0
+            || src_stride == 0
+        {
+            copy_plane(dest, dest_stride, src, src_stride, line_bytes);
+            return;
+        }
+
+        let lines = dest.len().div_ceil(dest_stride);
+        let lines_per_thread = lines.div_ceil(inner.workers.len() + 1);
+        let mut ranges = dest
+            .chunks_mut(lines_per_thread * dest_stride)
+            .zip(src.chunks(lines_per_thread * src_stride));
+
+        let first = ranges.next();
+        let mut pending = 0;
+        for ((dest, src), (sender, _)) in ranges.zip(&inner.workers) {
+            let job = CopyJob {
+                dest: dest.as_mut_ptr(),
+                dest_len: dest.len(),
+                dest_stride,
+                src: src.as_ptr(),
+                src_len: src.len(),
+                src_stride,
+                line_bytes,
+            };
+
+            match sender.send(job) {
+                Ok(_) => pending += 1,
+                // The worker is gone, copy its range here instead
+                Err(_) => copy_plane(dest, dest_stride, src, src_stride, line_bytes),
+            }
+        }
+
+        if let Some((dest, src)) = first {
+            copy_plane(dest, dest_stride, src, src_stride, line_bytes);
+        }
+
+        // The ranges of the plane are borrowed by the workers until they're done
+        for _ in 0..pending {
+            if inner.done.recv().is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for CopyPool {
+    fn drop(&mut self) {
+        let inner = self.0.get_mut().unwrap_or_else(|err| err.into_inner());
+        for (sender, thread) in inner.workers.drain(..) {
+            drop(sender);
+            let _ = thread.join();
+        }
+    }
+}
+
+// Copies a plane line by line, or with a single copy if the strides are the same which
+// is considerably faster on low-end ARM CPUs. Public for the benchmarks only.
+pub fn copy_plane(
+    dest: &mut [u8],
+    dest_stride: usize,
+    src: &[u8],
+    src_stride: usize,
+    line_bytes: usize,
+) {
+    if dest_stride == src_stride {
+        let len = cmp::min(dest.len(), src.len());
+        dest[..len].copy_from_slice(&src[..len]);
+        return;
+    }
+
+    if dest_stride == 0 || src_stride == 0 {
+        return;
+    }
+
+    // Frames with a short stride or truncated data only get the part that is actually there
+    for (dest, src) in dest.chunks_mut(dest_stride).zip(src.chunks(src_stride)) {
+        let len = cmp::min(line_bytes, cmp::min(dest.len(), src.len()));
+        dest[..len].copy_from_slice(&src[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        gst::init().unwrap();
+    }
+
+    fn video_buffer(pts: gst::ClockTime, flags: gst::BufferFlags) -> gst::Buffer {
+        let mut buffer = gst::Buffer::new();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(pts);
+            buffer.set_flags(flags);
+        }
+        buffer
+    }
+
+    #[test]
+    fn retimer() {
+        init();
+
+        let fps = gst::Fraction::new(30, 1);
+        let mut retimer = Retimer::default();
+        let pts = |buffers: &[gst::Buffer]| {
+            buffers
+                .iter()
+                .map(|buffer| buffer.pts().unwrap().nseconds())
+                .collect::<Vec<_>>()
+        };
+
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(gst::ClockTime::ZERO, gst::BufferFlags::DISCONT),
+        );
+        assert_eq!(pts(&buffers), vec![0]);
+        assert_eq!(
+            buffers[0].duration(),
+            Some(gst::ClockTime::from_nseconds(33_333_333))
+        );
+        assert!(buffers[0].flags().contains(gst::BufferFlags::DISCONT));
+
+        // Slightly late frames are moved to their slot, a second frame for a slot is dropped
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(gst::ClockTime::from_mseconds(35), gst::BufferFlags::empty()),
+        );
+        assert_eq!(pts(&buffers), vec![33_333_333]);
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(gst::ClockTime::from_mseconds(40), gst::BufferFlags::empty()),
+        );
+        assert!(buffers.is_empty());
+
+        // Missing slots are filled with the previous frame
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(
+                gst::ClockTime::from_mseconds(100),
+                gst::BufferFlags::empty(),
+            ),
+        );
+        assert_eq!(pts(&buffers), vec![66_666_666, 100_000_000]);
+
+        // but not if the sender stalled for more than a second
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(
+                gst::ClockTime::from_mseconds(2100),
+                gst::BufferFlags::empty(),
+            ),
+        );
+        assert_eq!(pts(&buffers), vec![2_100_000_000]);
+
+        // A discontinuity starts a new grid
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(
+                gst::ClockTime::from_mseconds(5010),
+                gst::BufferFlags::DISCONT,
+            ),
+        );
+        assert_eq!(pts(&buffers), vec![5_010_000_000]);
+        let buffers = retimer.retime(
+            fps,
+            video_buffer(
+                gst::ClockTime::from_mseconds(5045),
+                gst::BufferFlags::empty(),
+            ),
+        );
+        assert_eq!(pts(&buffers), vec![5_043_333_333]);
+    }
+
+    #[test]
+    fn audio_chunker() {
+        init();
+
+        let info = gst_audio::AudioInfo::builder(gst_audio::AUDIO_FORMAT_F32, 48_000, 2)
+            .build()
+            .unwrap();
+        let mut chunker = AudioChunker::default();
+
+        // Without video the audio is passed through
+        let audio_buffer = |first_sample: usize| {
+            let samples = (first_sample..first_sample + 1024)
+                .flat_map(|i| [i as f32, -(i as f32)])
+                .collect::<Vec<_>>();
+            let mut buffer = gst::Buffer::from_mut_slice(samples.as_byte_slice().to_vec());
+            buffer.get_mut().unwrap().set_pts(
+                gst::ClockTime::SECOND
+                    .mul_div_floor(first_sample as u64, 48_000)
+                    .unwrap(),
+            );
+            buffer
+        };
+        assert_eq!(chunker.push(audio_buffer(0), &info).len(), 1);
+
+        // With 25fps video the audio is output in chunks of 1920 samples starting at the frame
+        // boundaries
+        let mut video = video_buffer(gst::ClockTime::ZERO, gst::BufferFlags::empty());
+        video
+            .get_mut()
+            .unwrap()
+            .set_duration(gst::ClockTime::from_mseconds(40));
+        chunker.video_frame(&video);
+
+        let buffers = (0..4)
+            .flat_map(|i| chunker.push(audio_buffer(i * 1024), &info))
+            .collect::<Vec<_>>();
+        assert_eq!(buffers.len(), 2);
+        for (i, buffer) in buffers.iter().enumerate() {
+            assert_eq!(
+                buffer.pts(),
+                Some(gst::ClockTime::from_mseconds(40 * i as u64))
+            );
+            assert_eq!(buffer.duration(), Some(gst::ClockTime::from_mseconds(40)));
+            assert_eq!(buffer.flags().contains(gst::BufferFlags::DISCONT), i == 0);
+
+            let map = buffer.map_readable().unwrap();
+            let samples = map.as_slice_of::<f32>().unwrap();
+            assert_eq!(samples.len(), 1920 * 2);
+            for (j, frame) in samples.chunks_exact(2).enumerate() {
+                let sample = (i * 1920 + j) as f32;
+                assert_eq!(frame, [sample, -sample]);
+            }
+        }
+    }
+
+    #[test]
+    fn channel_order() {
+        use gst_audio::AudioChannelPosition::{FrontCenter, FrontLeft, FrontRight, Lfe1};
+
+        assert_eq!(canonical_channel_order(&[FrontLeft, FrontRight]), None);
+        assert_eq!(
+            canonical_channel_order(&[FrontLeft, FrontRight, Lfe1, FrontCenter]),
+            Some(vec![0, 1, 3, 2])
+        );
+        assert_eq!(
+            canonical_channel_order(&[FrontRight, FrontLeft]),
+            Some(vec![1, 0])
+        );
+    }
+
+    #[test]
+    fn downmix() {
+        use gst_audio::AudioChannelPosition::*;
+
+        assert_eq!(
+            downmix_matrix(&[FrontLeft, FrontRight]),
+            vec![[1.0, 0.0], [0.0, 1.0]]
+        );
+        assert_eq!(
+            downmix_matrix(&[Mono]),
+            vec![[
+                std::f32::consts::FRAC_1_SQRT_2,
+                std::f32::consts::FRAC_1_SQRT_2
+            ]]
+        );
+        assert_eq!(
+            downmix_matrix(&[None, None, None]),
+            vec![[0.5, 0.0], [0.0, 1.0], [0.5, 0.0]]
+        );
+
+        // 5.1 is normalized so that the output can't clip, LFE is dropped
+        let matrix = downmix_matrix(&[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            Lfe1,
+            RearLeft,
+            RearRight,
+        ]);
+        let sum = 1.0 + 2.0 * std::f32::consts::FRAC_1_SQRT_2;
+        let expected = [
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [
+                std::f32::consts::FRAC_1_SQRT_2,
+                std::f32::consts::FRAC_1_SQRT_2,
+            ],
+            [0.0, 0.0],
+            [std::f32::consts::FRAC_1_SQRT_2, 0.0],
+            [0.0, std::f32::consts::FRAC_1_SQRT_2],
+        ];
+        for (gains, expected) in matrix.iter().zip(expected.iter()) {
+            for output in 0..2 {
+                assert!((gains[output] - expected[output] / sum).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn channel_mask() {
+        assert_eq!(
+            channel_mask_from_metadata("<ndi_audio_layout channel_mask=\"0x3f\"/>"),
+            Some(0x3f)
+        );
+        assert_eq!(
+            channel_mask_from_metadata("<ndi_audio_layout channel-mask=\"0X3F\"/>"),
+            Some(0x3f)
+        );
+        assert_eq!(
+            channel_mask_from_metadata("<ndi_audio_layout channel_mask=\"63\"/>"),
+            Some(63)
+        );
+        assert_eq!(
+            channel_mask_from_metadata("<ndi_audio_layout channel_mask=\"0xzz\"/>"),
+            None
+        );
+        assert_eq!(
+            channel_mask_from_metadata("<ndi_audio_layout channel_mask=\"0x3f/>"),
+            None
+        );
+        assert_eq!(channel_mask_from_metadata("<ndi_audio_layout/>"), None);
+    }
+
+    #[test]
+    fn p216_conversion() {
+        init();
+
+        // Wide enough for the SIMD paths and their remainders
+        let (width, height) = (38, 2);
+        for alpha in [false, true] {
+            // 16 bit luma, interleaved chroma and for PA16 alpha, with padding after each line.
+            // The samples use the lowest and highest bits to catch any loss of precision.
+            let src_stride = width * 2 + 4;
+            let planes = if alpha { 3 } else { 2 };
+            let sample =
+                |plane: usize, x: usize, y: usize| (plane << 14 | y << 8 | x << 1 | 1) as u16;
+            let mut src = vec![0u8; src_stride * height * planes];
+            for y in 0..height {
+                for x in 0..width {
+                    let luma = sample(0, x, y);
+                    let chroma = sample(1 + x % 2, x / 2, y);
+                    src[y * src_stride + x * 2..][..2].copy_from_slice(&luma.to_le_bytes());
+                    src[(height + y) * src_stride + x * 2..][..2]
+                        .copy_from_slice(&chroma.to_le_bytes());
+                    if alpha {
+                        let alpha = sample(3, x, y);
+                        src[(2 * height + y) * src_stride + x * 2..][..2]
+                            .copy_from_slice(&alpha.to_le_bytes());
+                    }
+                }
+            }
+
+            let info = gst_video::VideoInfo::builder(
+                gst_video::VideoFormat::Ayuv64,
+                width as u32,
+                height as u32,
+            )
+            .build()
+            .unwrap();
+            let mut dest = vec![0u8; info.size()];
+            convert_p216(&mut dest, &info, &src, src_stride, width, height, alpha);
+
+            for y in 0..height {
+                for x in 0..width {
+                    let offset = y * info.stride()[0] as usize + x * 8;
+                    let pixel = dest[offset..][..8]
+                        .chunks_exact(2)
+                        .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+                        .collect::<Vec<_>>();
+                    let expected = [
+                        if alpha { sample(3, x, y) } else { u16::MAX },
+                        sample(0, x, y),
+                        sample(1, x / 2, y),
+                        sample(2, x / 2, y),
+                    ];
+                    assert_eq!(pixel, expected, "alpha {} pixel {}x{}", alpha, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stereo_downmixing() {
+        let planar = [1.0, 2.0, 3.0, 10.0, 20.0, 30.0];
+        let (mut left, mut right) = (vec![0.0; 3], vec![0.0; 3]);
+        downmix_stereo(&mut left, &mut right, &planar, 3, &[[1.0, 0.0], [0.5, 1.0]]);
+        assert_eq!(left, [6.0, 12.0, 18.0]);
+        assert_eq!(right, [10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn stereo_interleaving() {
+        let left = (0..37).map(|i| i as f32).collect::<Vec<_>>();
+        let right = (0..37).map(|i| -(i as f32)).collect::<Vec<_>>();
+        let mut dest = vec![0.0f32; 74];
+        interleave_stereo(&mut dest, &left, &right, 0.5);
+        for (i, frame) in dest.chunks_exact(2).enumerate() {
+            assert_eq!(frame, [i as f32 * 0.5, -(i as f32) * 0.5]);
+        }
+    }
+
+    #[test]
+    fn plane_copy() {
+        let src = (0..24).collect::<Vec<u8>>();
+
+        // Same stride is copied as a whole
+        let mut dest = vec![0u8; 24];
+        copy_plane(&mut dest, 6, &src, 6, 4);
+        assert_eq!(dest, src);
+
+        // Otherwise line by line, leaving the padding alone
+        let mut dest = vec![0xffu8; 32];
+        copy_plane(&mut dest, 8, &src, 6, 4);
+        for (line, dest) in dest.chunks(8).enumerate() {
+            assert_eq!(dest[..4], src[line * 6..][..4]);
+            assert_eq!(dest[4..], [0xff; 4]);
+        }
+
+        // Truncated source data only fills the lines that are there
+        let mut dest = vec![0xffu8; 32];
+        copy_plane(&mut dest, 8, &src[..10], 6, 4);
+        assert_eq!(dest[..4], src[..4]);
+        assert_eq!(dest[8..12], src[6..10]);
+        assert_eq!(dest[16..], [0xff; 16]);
+
+        // Large planes copied by several threads give the same result
+        let (width, height) = (1920 * 2, 1080);
+        let src = (0..(width + 64) * height)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut single = vec![0u8; (width + 128) * height];
+        let mut threaded = vec![0u8; (width + 128) * height];
+        copy_plane(&mut single, width + 128, &src, width + 64, width);
+        let pool = CopyPool::new(4);
+        pool.copy_plane(&mut threaded, width + 128, &src, width + 64, width);
+        assert!(single == threaded);
+
+        // And the pool can be reused for the next frame
+        let mut threaded = vec![0u8; (width + 128) * height];
+        pool.copy_plane(&mut threaded, width + 128, &src, width + 64, width);
+        assert!(single == threaded);
+    }
+}
@@ -0,0 +1,350 @@
+// Drives ndisrc with the in-process fake NDI library and checks how the timing problems it can
+// simulate via GST_NDI_FAKE_OPTIONS show up in the timestamps, flags and caps of the output.
+#![cfg(feature = "test-backend")]
+
+use gst::prelude::*;
+
+use once_cell::sync::Lazy;
+
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const SOURCE_NAME: &str = "FAKE (Test Source)";
+const FRAME_DURATION: u64 = 33_333_333;
+
+// The fake reads its options from the environment when a receiver is created, so tests that
+// set different options must not run at the same time
+fn init() -> MutexGuard<'static, ()> {
+    static INIT: Once = Once::new();
+    static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstndi::plugin_register_static().expect("Failed to register ndi plugin");
+        gstndi::ndi::load().expect("Failed to load fake NDI library");
+    });
+
+    LOCK.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[derive(Default)]
+struct Collected {
+    caps: Vec<gst::Caps>,
+    buffers: Vec<gst::Buffer>,
+}
+
+// Collects all caps and buffers flowing into the sink pad of the named element
+fn collect(pipeline: &gst::Pipeline, name: &str) -> Arc<Mutex<Collected>> {
+    let collected = Arc::new(Mutex::new(Collected::default()));
+
+    let pad = pipeline.by_name(name).unwrap().static_pad("sink").unwrap();
+    let collected_clone = collected.clone();
+    pad.add_probe(
+        gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+        move |_, info| {
+            let mut collected = collected_clone.lock().unwrap();
+            match info.data {
+                Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                    collected.buffers.push(buffer.clone())
+                }
+                Some(gst::PadProbeData::Event(ref event)) => {
+                    if let gst::EventView::Caps(caps) = event.view() {
+                        collected.caps.push(caps.caps().to_owned());
+                    }
+                }
+                _ => (),
+            }
+            gst::PadProbeReturn::Ok
+        },
+    );
+
+    collected
+}
+
+struct FakeSource {
+    pipeline: gst::Pipeline,
+    video: Arc<Mutex<Collected>>,
+}
+
+impl FakeSource {
+    fn new(options: &str, properties: &str) -> Self {
+        std::env::set_var("GST_NDI_FAKE_OPTIONS", options);
+
+        let pipeline = gst::parse_launch(&format!(
+            "ndisrc name=src {} ! ndisrcdemux name=demux \
+             demux.video ! fakesink name=video-sink sync=false async=false \
+             demux.audio ! fakesink sync=false async=false",
+            properties
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("ndi-name", SOURCE_NAME);
+
+        let video = collect(&pipeline, "video-sink");
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        FakeSource { pipeline, video }
+    }
+
+    // Runs until the given number of video buffers arrived and returns what was collected
+    fn run(&self, buffers: usize) -> MutexGuard<'_, Collected> {
+        let bus = self.pipeline.bus().unwrap();
+        let start = Instant::now();
+        loop {
+            if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+                if let gst::MessageView::Error(err) = msg.view() {
+                    panic!("Error: {} ({:?})", err.error(), err.debug());
+                }
+            }
+
+            if self.video.lock().unwrap().buffers.len() >= buffers {
+                break;
+            }
+
+            assert!(start.elapsed() < TIMEOUT, "Timed out waiting for data");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        self.pipeline.set_state(gst::State::Null).unwrap();
+        self.video.lock().unwrap()
+    }
+}
+
+impl Drop for FakeSource {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+fn timestamps(collected: &Collected) -> Vec<u64> {
+    collected
+        .buffers
+        .iter()
+        .map(|buffer| buffer.pts().expect("Buffer without timestamp").nseconds())
+        .collect()
+}
+
+fn distances(pts: &[u64]) -> Vec<u64> {
+    pts.windows(2)
+        .map(|window| {
+            assert!(
+                window[1] > window[0],
+                "Timestamps not increasing: {:?}",
+                window
+            );
+            window[1] - window[0]
+        })
+        .collect()
+}
+
+// Indices of the buffers, apart from the first one, that are flagged as discontinuous
+fn disconts(collected: &Collected) -> Vec<usize> {
+    collected
+        .buffers
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, buffer)| buffer.flags().contains(gst::BufferFlags::DISCONT))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn size(caps: &gst::Caps) -> (u32, u32) {
+    let info = gst_video::VideoInfo::from_caps(caps).unwrap();
+    (info.width(), info.height())
+}
+
+#[test]
+fn clean_stream() {
+    let _lock = init();
+
+    let source = FakeSource::new("", "");
+    let video = source.run(60);
+
+    assert_eq!(video.caps.len(), 1);
+    let info = gst_video::VideoInfo::from_caps(&video.caps[0]).unwrap();
+    assert_eq!(info.format(), gst_video::VideoFormat::Uyvy);
+    assert_eq!((info.width(), info.height()), (320, 240));
+    assert_eq!(info.fps(), gst::Fraction::new(30, 1));
+
+    assert!(video.buffers[0].flags().contains(gst::BufferFlags::DISCONT));
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    let average = (pts.last().unwrap() - pts[0]) / (pts.len() as u64 - 1);
+    assert!(
+        (31_000_000..=36_000_000).contains(&average),
+        "Average frame distance {}ns",
+        average
+    );
+}
+
+#[test]
+fn jitter() {
+    let _lock = init();
+
+    // Up to 20ms of random delay per frame, which the skew estimation has to smooth out once
+    // its window is filled after 2s
+    let source = FakeSource::new("jitter=20", "");
+    let video = source.run(150);
+
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    let distances = distances(&pts[90..]);
+    let max_deviation = distances
+        .iter()
+        .map(|distance| (*distance as i64 - FRAME_DURATION as i64).unsigned_abs())
+        .max()
+        .unwrap();
+    assert!(
+        max_deviation < 5_000_000,
+        "Frame distances deviate by up to {}ns",
+        max_deviation
+    );
+}
+
+#[test]
+fn drift() {
+    let _lock = init();
+
+    // The sender clock runs 5% fast, so frames arrive every ~31.7ms of local time
+    let source = FakeSource::new("drift=50000", "");
+    let video = source.run(150);
+
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    distances(&pts);
+    let average = (pts.last().unwrap() - pts[90]) / (pts.len() as u64 - 91);
+    assert!(
+        (30_000_000..=32_800_000).contains(&average),
+        "Average frame distance {}ns",
+        average
+    );
+}
+
+#[test]
+fn drift_timecode() {
+    let _lock = init();
+
+    // With the sender timecodes the timestamps follow the sender clock exactly
+    let source = FakeSource::new("drift=50000", "timestamp-mode=timecode");
+    let video = source.run(60);
+
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    for distance in distances(&pts) {
+        assert!(
+            (distance as i64 - FRAME_DURATION as i64).abs() <= 1,
+            "Frame distance {}ns",
+            distance
+        );
+    }
+}
+
+#[test]
+fn dropped_frames() {
+    let _lock = init();
+
+    // Every 5th frame is missing, which leaves a gap of two frames but is no discontinuity
+    let source = FakeSource::new("drop=5", "timestamp-mode=timecode");
+    let video = source.run(60);
+
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    let distances = distances(&pts);
+    for distance in &distances {
+        let frames = (*distance + FRAME_DURATION / 2) / FRAME_DURATION;
+        assert!(
+            (1..=2).contains(&frames)
+                && (*distance as i64 - (frames * FRAME_DURATION) as i64).abs() <= 1,
+            "Frame distance {}ns",
+            distance
+        );
+    }
+
+    let gaps = distances
+        .iter()
+        .filter(|distance| **distance > FRAME_DURATION * 3 / 2)
+        .count();
+    assert!(
+        gaps >= distances.len() / 5 - 1,
+        "Only {} gaps in {} frames",
+        gaps,
+        distances.len()
+    );
+}
+
+#[test]
+fn timecode_jump() {
+    let _lock = init();
+
+    // The timecodes jump forward by a second every 15 frames, which resets the skew
+    // estimation and marks the following buffer as discontinuous while the timestamps keep
+    // following the receive time
+    let source = FakeSource::new("timecode-jump=15", "");
+    let video = source.run(60);
+
+    let disconts = disconts(&video);
+    assert!(disconts.len() >= 3, "Discontinuities at {:?}", disconts);
+
+    let pts = timestamps(&video);
+    for distance in distances(&pts) {
+        assert!(
+            distance < 500_000_000,
+            "Timestamps jumped by {}ns",
+            distance
+        );
+    }
+}
+
+#[test]
+fn timecode_jump_timecode() {
+    let _lock = init();
+
+    // With the sender timecodes the jumps show up in the timestamps
+    let source = FakeSource::new("timecode-jump=15", "timestamp-mode=timecode");
+    let video = source.run(60);
+
+    assert_eq!(disconts(&video), Vec::<usize>::new());
+
+    let pts = timestamps(&video);
+    let jumps = distances(&pts)
+        .into_iter()
+        .filter(|distance| (*distance as i64 - 1_033_333_333).abs() <= 1)
+        .count();
+    assert!(jumps >= 3, "Only {} jumps", jumps);
+}
+
+#[test]
+fn resize() {
+    let _lock = init();
+
+    // Switches between full and half size every 10 frames
+    let source = FakeSource::new("resize=10", "");
+    let video = source.run(60);
+
+    assert!(video.caps.len() >= 5, "Only {} caps", video.caps.len());
+    for (i, caps) in video.caps.iter().enumerate() {
+        let expected = if i % 2 == 0 { (320, 240) } else { (160, 120) };
+        assert_eq!(size(caps), expected);
+    }
+
+    // Every buffer fits either the current caps or the ones following them
+    let mut caps = video.caps.iter().map(size);
+    let mut current = caps.next().unwrap();
+    for buffer in &video.buffers {
+        if buffer.size() != (current.0 * current.1 * 2) as usize {
+            current = caps.next().expect("Buffer without matching caps");
+        }
+        assert_eq!(buffer.size(), (current.0 * current.1 * 2) as usize);
+    }
+}
@@ -0,0 +1,289 @@
+// Sends through ndisink and receives the stream again with ndisrc via the local network,
+// checking caps, timestamps and content of what arrives. This needs the NDI library, the
+// tests are skipped if it can't be loaded.
+#![cfg(all(feature = "sink", not(feature = "test-backend")))]
+
+use gst::prelude::*;
+
+use byte_slice_cast::*;
+
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn init() -> bool {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstndi::plugin_register_static().expect("Failed to register ndi plugin");
+    });
+
+    match gstndi::ndi::load() {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Skipping test, NDI library not available: {}", err);
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+struct Collected {
+    caps: Option<gst::Caps>,
+    buffers: Vec<gst::Buffer>,
+}
+
+// Collects the caps and all buffers flowing into the sink pad of the named element
+fn collect(pipeline: &gst::Pipeline, name: &str) -> Arc<Mutex<Collected>> {
+    let collected = Arc::new(Mutex::new(Collected::default()));
+
+    let pad = pipeline.by_name(name).unwrap().static_pad("sink").unwrap();
+    let collected_clone = collected.clone();
+    pad.add_probe(
+        gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+        move |_, info| {
+            let mut collected = collected_clone.lock().unwrap();
+            match info.data {
+                Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                    collected.buffers.push(buffer.clone())
+                }
+                Some(gst::PadProbeData::Event(ref event)) => {
+                    if let gst::EventView::Caps(caps) = event.view() {
+                        collected.caps = Some(caps.caps().to_owned());
+                    }
+                }
+                _ => (),
+            }
+            gst::PadProbeReturn::Ok
+        },
+    );
+
+    collected
+}
+
+// Waits until the sender shows up on the network and returns its full NDI name, which
+// includes the host name
+fn find_sender(ndi_name: &str) -> String {
+    let mut find = gstndi::ndi::FindInstance::builder()
+        .show_local_sources(true)
+        .build()
+        .expect("Failed to create NDI finder");
+
+    let suffix = format!("({})", ndi_name);
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        find.wait_for_sources(500);
+        if let Some(source) = find
+            .get_current_sources()
+            .iter()
+            .find(|source| source.ndi_name().ends_with(&suffix))
+        {
+            return source.ndi_name().to_string();
+        }
+    }
+
+    panic!("Sender {} not found", ndi_name);
+}
+
+fn check_bus(pipeline: &gst::Pipeline) {
+    let bus = pipeline.bus().unwrap();
+    if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+        if let gst::MessageView::Error(err) = msg.view() {
+            panic!(
+                "Error from {:?}: {} ({:?})",
+                err.src().map(|src| src.path_string()),
+                err.error(),
+                err.debug()
+            );
+        }
+    }
+}
+
+struct RoundTrip {
+    sender: gst::Pipeline,
+    receiver: gst::Pipeline,
+    sent_video: Arc<Mutex<Collected>>,
+    video: Arc<Mutex<Collected>>,
+    audio: Arc<Mutex<Collected>>,
+}
+
+impl RoundTrip {
+    fn new(name: &str) -> Self {
+        let ndi_name = format!("gst-plugin-ndi {} {}", name, std::process::id());
+
+        let sender = gst::parse_launch(
+            "videotestsrc is-live=true pattern=solid-color foreground-color=0xff3060c0 ! \
+             video/x-raw,format=UYVY,width=320,height=240,framerate=30/1 ! \
+             identity name=sent-video ! ndisinkcombiner name=combiner ! ndisink name=sink \
+             audiotestsrc is-live=true freq=440 volume=0.5 ! \
+             audio/x-raw,format=F32LE,rate=48000,channels=2 ! combiner.audio",
+        )
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        sender
+            .by_name("sink")
+            .unwrap()
+            .set_property("ndi-name", &ndi_name);
+
+        let sent_video = collect(&sender, "sent-video");
+        sender.set_state(gst::State::Playing).unwrap();
+
+        // With the default skew slaving the audio timestamps follow the network jitter, the
+        // resample method keeps them contiguous
+        let receiver = gst::parse_launch(
+            "ndisrc name=src slave-method=resample ! ndisrcdemux name=demux \
+             demux.video ! fakesink name=video-sink sync=false async=false \
+             demux.audio ! fakesink name=audio-sink sync=false async=false",
+        )
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        receiver
+            .by_name("src")
+            .unwrap()
+            .set_property("ndi-name", find_sender(&ndi_name));
+
+        let video = collect(&receiver, "video-sink");
+        let audio = collect(&receiver, "audio-sink");
+        receiver.set_state(gst::State::Playing).unwrap();
+
+        RoundTrip {
+            sender,
+            receiver,
+            sent_video,
+            video,
+            audio,
+        }
+    }
+
+    // Runs until the given condition on the received video and audio is true
+    fn run_until(&self, cond: impl Fn(&Collected, &Collected) -> bool) {
+        let start = Instant::now();
+        loop {
+            check_bus(&self.sender);
+            check_bus(&self.receiver);
+
+            if cond(&self.video.lock().unwrap(), &self.audio.lock().unwrap()) {
+                break;
+            }
+
+            assert!(start.elapsed() < TIMEOUT, "Timed out waiting for data");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for RoundTrip {
+    fn drop(&mut self) {
+        let _ = self.receiver.set_state(gst::State::Null);
+        let _ = self.sender.set_state(gst::State::Null);
+    }
+}
+
+#[test]
+fn video_roundtrip() {
+    if !init() {
+        return;
+    }
+
+    let roundtrip = RoundTrip::new("video");
+    roundtrip.run_until(|video, _| video.buffers.len() >= 60);
+
+    let video = roundtrip.video.lock().unwrap();
+    let info =
+        gst_video::VideoInfo::from_caps(video.caps.as_ref().expect("No video caps")).unwrap();
+    assert_eq!(info.format(), gst_video::VideoFormat::Uyvy);
+    assert_eq!((info.width(), info.height()), (320, 240));
+    assert_eq!(info.fps(), gst::Fraction::new(30, 1));
+
+    // Timestamps must increase and on average be one frame apart
+    let pts = video
+        .buffers
+        .iter()
+        .map(|buffer| buffer.pts().expect("Video buffer without timestamp"))
+        .collect::<Vec<_>>();
+    for window in pts.windows(2) {
+        assert!(
+            window[1] > window[0],
+            "Timestamps not increasing: {:?}",
+            window
+        );
+    }
+    let average = (*pts.last().unwrap() - pts[0]).nseconds() / (pts.len() as u64 - 1);
+    assert!(
+        (30_000_000..=36_666_666).contains(&average),
+        "Average frame distance {}ns",
+        average
+    );
+
+    // The picture is compressed on the way, so only approximately the same
+    let sent = roundtrip.sent_video.lock().unwrap();
+    let sent = sent.buffers.first().unwrap().map_readable().unwrap();
+    for buffer in &video.buffers[video.buffers.len() - 10..] {
+        let received = buffer.map_readable().unwrap();
+        assert_eq!(received.len(), sent.len());
+        let difference = sent
+            .iter()
+            .zip(received.iter())
+            .map(|(a, b)| (*a as i32 - *b as i32).unsigned_abs() as u64)
+            .sum::<u64>()
+            / sent.len() as u64;
+        assert!(difference <= 4, "Average difference {} too big", difference);
+    }
+}
+
+#[test]
+fn audio_roundtrip() {
+    if !init() {
+        return;
+    }
+
+    let roundtrip = RoundTrip::new("audio");
+    roundtrip.run_until(|_, audio| {
+        audio
+            .buffers
+            .iter()
+            .filter_map(|buffer| buffer.duration())
+            .sum::<gst::ClockTime>()
+            >= gst::ClockTime::from_seconds(2)
+    });
+
+    let audio = roundtrip.audio.lock().unwrap();
+    let info =
+        gst_audio::AudioInfo::from_caps(audio.caps.as_ref().expect("No audio caps")).unwrap();
+    assert_eq!(info.format(), gst_audio::AUDIO_FORMAT_F32);
+    assert_eq!((info.rate(), info.channels()), (48_000, 2));
+
+    // Audio has to be contiguous after the first buffer
+    for window in audio.buffers.windows(2) {
+        let end = window[0].pts().unwrap() + window[0].duration().unwrap();
+        let next = window[1].pts().unwrap();
+        let gap = if next > end { next - end } else { end - next };
+        assert!(
+            gap <= gst::ClockTime::from_mseconds(1),
+            "Gap of {} between buffers",
+            gap
+        );
+    }
+
+    // A sine with amplitude 0.5 has an RMS of 0.5 / sqrt(2), independent of the layout
+    let (sum, count) = audio.buffers[1..]
+        .iter()
+        .fold((0.0, 0), |(sum, count), buffer| {
+            let map = buffer.map_readable().unwrap();
+            let samples = map.as_slice_of::<f32>().unwrap();
+            (
+                sum + samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>(),
+                count + samples.len(),
+            )
+        });
+    let rms = (sum / count as f64).sqrt();
+    assert!(
+        (rms - 0.5 / 2f64.sqrt()).abs() < 0.02,
+        "Unexpected RMS {}",
+        rms
+    );
+}